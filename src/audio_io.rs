@@ -0,0 +1,440 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read};
+use std::path::Path;
+use rand::Rng;
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::{Decoder, DecoderOptions, CODEC_TYPE_NULL, CODEC_TYPE_OPUS};
+use symphonia::core::errors::Error as SymphoniaError;
+use symphonia::core::formats::{FormatOptions, FormatReader};
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+use crate::config::{BitDepth, OutputFormat, SpectrogramConfig};
+use crate::flac::FlacWriter;
+
+/// Decoded audio samples in [-1, 1]
+#[derive(Debug, Clone)]
+pub struct AudioData {
+    /// One sample Vec per channel
+    pub channels: Vec<Vec<f32>>,
+    pub sample_rate: u32,
+}
+
+/// Sample rate, channel count and length of an audio file, read without decoding it
+#[derive(Debug, Clone, Copy)]
+pub struct AudioInfo {
+    pub sample_rate: u32,
+    pub channels: usize,
+    /// Samples per channel, when the container states it
+    pub num_samples: Option<usize>,
+}
+
+/// Audio container/codec of an input file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AudioFormat {
+    Wav,
+    Flac,
+    Mp3,
+    OggVorbis,
+    Opus,
+    Aiff,
+}
+
+impl AudioFormat {
+    /// File extensions recognized as audio
+    pub const EXTENSIONS: [&'static str; 9] = ["wav", "flac", "mp3", "ogg", "oga", "opus", "aif", "aiff", "aifc"];
+
+    /// Detect the format from the file's magic bytes, falling back to its extension
+    pub fn detect(path: &Path) -> Option<Self> {
+        let mut header = [0u8; 36];
+        let read = File::open(path).and_then(|mut f| f.read(&mut header)).unwrap_or(0);
+        Self::from_magic(&header[..read]).or_else(|| Self::from_extension(path))
+    }
+
+    fn from_magic(header: &[u8]) -> Option<Self> {
+        if header.len() >= 12 && &header[0..4] == b"RIFF" && &header[8..12] == b"WAVE" {
+            Some(AudioFormat::Wav)
+        } else if header.starts_with(b"fLaC") {
+            Some(AudioFormat::Flac)
+        } else if header.len() >= 12 && &header[0..4] == b"FORM" && (&header[8..12] == b"AIFF" || &header[8..12] == b"AIFC") {
+            Some(AudioFormat::Aiff)
+        } else if header.starts_with(b"OggS") {
+            // The first Ogg page carries the codec identification header
+            if header.len() >= 36 && &header[28..36] == b"OpusHead" {
+                Some(AudioFormat::Opus)
+            } else {
+                Some(AudioFormat::OggVorbis)
+            }
+        } else if header.starts_with(b"ID3") || (header.len() >= 2 && header[0] == 0xFF && header[1] & 0xE0 == 0xE0) {
+            // ID3 tag or MPEG audio frame sync
+            Some(AudioFormat::Mp3)
+        } else {
+            None
+        }
+    }
+
+    fn from_extension(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "wav" => Some(AudioFormat::Wav),
+            "flac" => Some(AudioFormat::Flac),
+            "mp3" => Some(AudioFormat::Mp3),
+            "ogg" | "oga" => Some(AudioFormat::OggVorbis),
+            "opus" => Some(AudioFormat::Opus),
+            "aif" | "aiff" | "aifc" => Some(AudioFormat::Aiff),
+            _ => None,
+        }
+    }
+}
+
+/// Read an audio file of any supported format (WAV, FLAC, MP3, Ogg Vorbis, AIFF)
+pub fn read_audio(audio_path: &Path) -> Result<AudioData, Box<dyn std::error::Error>> {
+    read_all(AudioReader::open(audio_path)?, audio_path)
+}
+
+// Decode every chunk of `reader` into memory
+fn read_all(mut reader: AudioReader, audio_path: &Path) -> Result<AudioData, Box<dyn std::error::Error>> {
+    let mut channels: Vec<Vec<f32>> = Vec::new();
+    while let Some(chunk) = reader.next_chunk()? {
+        if channels.is_empty() {
+            channels = vec![Vec::new(); chunk.len()];
+        }
+        for (channel, samples) in channels.iter_mut().zip(chunk) {
+            channel.extend(samples);
+        }
+    }
+
+    if channels.is_empty() || channels[0].is_empty() {
+        return Err(format!("No audio decoded from {}", audio_path.display()).into());
+    }
+
+    Ok(AudioData { channels, sample_rate: reader.sample_rate() })
+}
+
+/// Sequential reader decoding an audio file a chunk at a time, so long files can be processed
+/// without holding all samples in memory
+pub struct AudioReader {
+    source: Source,
+    sample_rate: u32,
+    channels: usize,
+}
+
+enum Source {
+    Wav(hound::WavReader<BufReader<File>>),
+    Compressed {
+        format: Box<dyn FormatReader>,
+        decoder: Box<dyn Decoder>,
+        track_id: u32,
+        sample_buffer: Option<SampleBuffer<f32>>,
+    },
+}
+
+// Frames per chunk read from WAV files
+const WAV_CHUNK_FRAMES: usize = 65536;
+
+impl AudioReader {
+    /// Open an audio file of any supported format (WAV, FLAC, MP3, Ogg Vorbis, AIFF)
+    pub fn open(audio_path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        match AudioFormat::detect(audio_path) {
+            Some(AudioFormat::Wav) => Self::open_wav(audio_path),
+            Some(AudioFormat::Opus) => Err(OPUS_UNSUPPORTED.into()),
+            Some(_) => Self::open_compressed(audio_path),
+            None => Err(format!("Unrecognized audio format: {}", audio_path.display()).into()),
+        }
+    }
+
+    fn open_wav(audio_path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let reader = hound::WavReader::open(audio_path)?;
+        let spec = reader.spec();
+
+        println!("Audio format: {:?}, bits_per_sample: {}, sample_rate: {}, channels: {}",
+                 spec.sample_format, spec.bits_per_sample, spec.sample_rate, spec.channels);
+
+        match (spec.sample_format, spec.bits_per_sample) {
+            (hound::SampleFormat::Float, 32) | (hound::SampleFormat::Int, 8 | 16 | 24 | 32) => {}
+            _ => {
+                return Err(format!(
+                    "Unsupported audio format: {:?} with {} bits per sample",
+                    spec.sample_format, spec.bits_per_sample
+                ).into());
+            }
+        }
+
+        Ok(Self { source: Source::Wav(reader), sample_rate: spec.sample_rate, channels: spec.channels as usize })
+    }
+
+    // Decode FLAC, MP3, Ogg Vorbis or AIFF with symphonia
+    fn open_compressed(audio_path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let format = open_compressed(audio_path)?;
+
+        let track = format.tracks()
+            .iter()
+            .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
+            .ok_or("No audio track found")?;
+        if track.codec_params.codec == CODEC_TYPE_OPUS {
+            return Err(OPUS_UNSUPPORTED.into());
+        }
+        let track_id = track.id;
+        let sample_rate = track.codec_params.sample_rate.ok_or("Unknown sample rate")?;
+        let channels = track.codec_params.channels.map_or(0, |c| c.count());
+
+        let codecs = symphonia::default::get_codecs();
+        let decoder = codecs.make(&track.codec_params, &DecoderOptions::default())?;
+
+        println!("Audio codec: {}, sample_rate: {}, channels: {}",
+                 codecs.get_codec(track.codec_params.codec).map_or("unknown", |c| c.short_name),
+                 sample_rate, channels);
+
+        Ok(Self {
+            source: Source::Compressed { format, decoder, track_id, sample_buffer: None },
+            sample_rate,
+            channels,
+        })
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Number of channels in the file
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+
+    /// Next chunk of samples in [-1, 1], one Vec per channel; None at the end of the file
+    pub fn next_chunk(&mut self) -> Result<Option<Vec<Vec<f32>>>, Box<dyn std::error::Error>> {
+        let num_channels = self.channels;
+        match &mut self.source {
+            Source::Wav(reader) => {
+                let spec = reader.spec();
+                let count = WAV_CHUNK_FRAMES * num_channels;
+                let samples: Vec<f32> = match (spec.sample_format, spec.bits_per_sample) {
+                    (hound::SampleFormat::Float, 32) => {
+                        reader.samples::<f32>().take(count).collect::<Result<_, _>>()?
+                    }
+                    (hound::SampleFormat::Int, 8) => {
+                        reader.samples::<i8>().take(count)
+                            .map(|s| s.map(|s| s as f32 / 128.0)) // 2^7
+                            .collect::<Result<_, _>>()?
+                    }
+                    (hound::SampleFormat::Int, 16) => {
+                        reader.samples::<i16>().take(count)
+                            .map(|s| s.map(|s| s as f32 / 32768.0)) // 2^15
+                            .collect::<Result<_, _>>()?
+                    }
+                    (hound::SampleFormat::Int, 24) => {
+                        reader.samples::<i32>().take(count)
+                            .map(|s| s.map(|s| s as f32 / 8388608.0)) // 2^23
+                            .collect::<Result<_, _>>()?
+                    }
+                    _ => {
+                        reader.samples::<i32>().take(count)
+                            .map(|s| s.map(|s| s as f32 / 2147483648.0)) // 2^31
+                            .collect::<Result<_, _>>()?
+                    }
+                };
+                if samples.is_empty() {
+                    return Ok(None);
+                }
+
+                // Deinterleave channels
+                Ok(Some((0..num_channels)
+                    .map(|ch| samples.iter().skip(ch).step_by(num_channels).copied().collect())
+                    .collect()))
+            }
+            Source::Compressed { format, decoder, track_id, sample_buffer } => loop {
+                let packet = match format.next_packet() {
+                    Ok(packet) => packet,
+                    Err(SymphoniaError::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+                    Err(e) => return Err(e.into()),
+                };
+                if packet.track_id() != *track_id {
+                    continue;
+                }
+
+                let decoded = match decoder.decode(&packet) {
+                    Ok(decoded) => decoded,
+                    Err(SymphoniaError::DecodeError(e)) => {
+                        eprintln!("Warning: skipping corrupt audio packet: {}", e);
+                        continue;
+                    }
+                    Err(e) => return Err(e.into()),
+                };
+
+                let spec = *decoded.spec();
+                let decoded_channels = spec.channels.count();
+                if self.channels == 0 {
+                    // The container did not state the channel count
+                    self.channels = decoded_channels;
+                }
+
+                let buffer = match sample_buffer {
+                    Some(buffer) if buffer.capacity() >= decoded.capacity() * decoded_channels => buffer,
+                    _ => sample_buffer.insert(SampleBuffer::new(decoded.capacity() as u64, spec)),
+                };
+
+                // Planar layout: all samples of channel 0, then channel 1, ...
+                buffer.copy_planar_ref(decoded);
+                let samples = buffer.samples();
+                let frames = samples.len() / decoded_channels;
+                return Ok(Some(samples.chunks(frames.max(1)).map(<[f32]>::to_vec).collect()));
+            },
+        }
+    }
+}
+
+/// Read sample rate, channel count and length without decoding the audio
+pub fn probe_audio(audio_path: &Path) -> Result<AudioInfo, Box<dyn std::error::Error>> {
+    match AudioFormat::detect(audio_path) {
+        Some(AudioFormat::Wav) => {
+            let reader = hound::WavReader::open(audio_path)?;
+            let spec = reader.spec();
+            Ok(AudioInfo {
+                sample_rate: spec.sample_rate,
+                channels: spec.channels as usize,
+                num_samples: Some(reader.duration() as usize), // duration is per channel
+            })
+        }
+        Some(AudioFormat::Opus) => Err(OPUS_UNSUPPORTED.into()),
+        Some(_) => {
+            let format = open_compressed(audio_path)?;
+            let track = format.default_track().ok_or("No audio track found")?;
+            let params = &track.codec_params;
+            Ok(AudioInfo {
+                sample_rate: params.sample_rate.ok_or("Unknown sample rate")?,
+                channels: params.channels.map_or(1, |c| c.count()),
+                num_samples: params.n_frames.map(|n| n as usize),
+            })
+        }
+        None => Err(format!("Unrecognized audio format: {}", audio_path.display()).into()),
+    }
+}
+
+const OPUS_UNSUPPORTED: &str = "Opus decoding is not supported (no pure-Rust Opus decoder available) - convert to FLAC or WAV first";
+
+/// Read a WAV file
+pub fn read_wav(audio_path: &Path) -> Result<AudioData, Box<dyn std::error::Error>> {
+    read_all(AudioReader::open_wav(audio_path)?, audio_path)
+}
+
+/// Write per-channel samples in [-1, 1] in the output format, bit depth and dither of `config`
+pub fn write_audio(output_path: &Path, channels: &[Vec<f32>], sample_rate: u32, config: &SpectrogramConfig) -> Result<(), Box<dyn std::error::Error>> {
+    let mut writer = AudioWriter::create(output_path, channels.len(), sample_rate, config)?;
+    writer.write(channels)?;
+    writer.finish()
+}
+
+/// Write per-channel samples in [-1, 1] as a WAV file
+/// Integer depths clip at full scale; `dither` adds TPDF dither before quantizing
+pub fn write_wav(output_path: &Path, channels: &[Vec<f32>], sample_rate: u32, bit_depth: BitDepth, dither: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let mut writer = AudioWriter::wav(output_path, channels.len(), sample_rate, bit_depth, dither)?;
+    writer.write(channels)?;
+    writer.finish()
+}
+
+/// Audio file written a block of per-channel samples at a time, so decoded audio can be saved
+/// as it is produced
+pub struct AudioWriter {
+    sink: Sink,
+    bit_depth: BitDepth,
+    dither: bool,
+}
+
+enum Sink {
+    Wav(hound::WavWriter<BufWriter<File>>),
+    Flac(FlacWriter),
+}
+
+impl AudioWriter {
+    /// Start a file in the output format, bit depth and dither of `config`
+    pub fn create(output_path: &Path, num_channels: usize, sample_rate: u32, config: &SpectrogramConfig) -> Result<Self, Box<dyn std::error::Error>> {
+        let dither = config.dither && config.bit_depth == BitDepth::Int16;
+        println!("Writing {} {}{}", config.output_format, config.bit_depth, if dither { " with TPDF dither" } else { "" });
+
+        match (config.output_format, config.bit_depth) {
+            (OutputFormat::Wav, bit_depth) => Self::wav(output_path, num_channels, sample_rate, bit_depth, dither),
+            (OutputFormat::Flac, BitDepth::Float32) => Err("FLAC output needs an integer bit depth".into()),
+            (OutputFormat::Flac, bit_depth) => Ok(Self {
+                sink: Sink::Flac(FlacWriter::create(output_path, num_channels, sample_rate, bit_depth.bits() as u32)?),
+                bit_depth,
+                dither,
+            }),
+        }
+    }
+
+    fn wav(output_path: &Path, num_channels: usize, sample_rate: u32, bit_depth: BitDepth, dither: bool) -> Result<Self, Box<dyn std::error::Error>> {
+        let spec = hound::WavSpec {
+            channels: num_channels as u16,
+            sample_rate,
+            bits_per_sample: bit_depth.bits(),
+            sample_format: if bit_depth == BitDepth::Float32 { hound::SampleFormat::Float } else { hound::SampleFormat::Int },
+        };
+        Ok(Self { sink: Sink::Wav(hound::WavWriter::create(output_path, spec)?), bit_depth, dither })
+    }
+
+    /// Append samples in [-1, 1], one Vec per channel
+    pub fn write(&mut self, channels: &[Vec<f32>]) -> Result<(), Box<dyn std::error::Error>> {
+        let num_frames = channels.iter().map(|c| c.len()).min().unwrap_or(0);
+        let quantize = |bit_depth: BitDepth| -> Vec<Vec<i32>> {
+            channels.iter().map(|c| quantize(&c[..num_frames], bit_depth.bits() as u32, self.dither)).collect()
+        };
+
+        match &mut self.sink {
+            Sink::Wav(writer) if self.bit_depth == BitDepth::Float32 => {
+                for i in 0..num_frames {
+                    for channel in channels {
+                        writer.write_sample(channel[i])?;
+                    }
+                }
+            }
+            Sink::Wav(writer) => {
+                let quantized = quantize(self.bit_depth);
+                for i in 0..num_frames {
+                    for channel in &quantized {
+                        writer.write_sample(channel[i])?;
+                    }
+                }
+            }
+            Sink::Flac(writer) => writer.write(&quantize(self.bit_depth))?,
+        }
+        Ok(())
+    }
+
+    /// Complete the file's headers
+    pub fn finish(self) -> Result<(), Box<dyn std::error::Error>> {
+        match self.sink {
+            Sink::Wav(writer) => writer.finalize()?,
+            Sink::Flac(writer) => writer.finish()?,
+        }
+        Ok(())
+    }
+}
+
+// Scale samples in [-1, 1] to signed integers of `bits` bits, clipping at full scale
+// TPDF dither: the difference of two uniform values spans +-1 LSB with a triangular distribution
+fn quantize(samples: &[f32], bits: u32, dither: bool) -> Vec<i32> {
+    let full_scale = (1i64 << (bits - 1)) as f32;
+    let (min, max) = (-full_scale, full_scale - 1.0);
+    let mut rng = rand::rng();
+    samples.iter()
+        .map(|&sample| {
+            let noise = if dither { rng.random::<f32>() - rng.random::<f32>() } else { 0.0 };
+            (sample * full_scale + noise).round().clamp(min, max) as i32
+        })
+        .collect()
+}
+
+// Open a compressed file with symphonia's probe
+fn open_compressed(audio_path: &Path) -> Result<Box<dyn FormatReader>, Box<dyn std::error::Error>> {
+    let file = File::open(audio_path)?;
+    let stream = MediaSourceStream::new(Box::new(file), Default::default());
+
+    let mut hint = Hint::new();
+    if let Some(extension) = audio_path.extension().and_then(|e| e.to_str()) {
+        hint.with_extension(extension);
+    }
+
+    let probed = symphonia::default::get_probe()
+        .format(&hint, stream, &FormatOptions::default(), &MetadataOptions::default())?;
+    Ok(probed.format)
+}
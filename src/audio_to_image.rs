@@ -1,241 +1,77 @@
-use hound;
-use image::{ImageBuffer, Rgb};
-use rustfft::{FftPlanner, num_complex::Complex};
+use image::{Rgb, Rgb32FImage};
 use std::path::{Path, PathBuf};
-use crate::config::SpectrogramConfig;
-
-pub fn audio_to_spectrogram(
-    audio_path: &Path,
-    output_path: &Path,
-    config: &SpectrogramConfig,
-) -> Result<PathBuf, Box<dyn std::error::Error>> {
-    // Read WAV file
-    let mut reader = hound::WavReader::open(audio_path)?;
-    let spec = reader.spec();
-
-    println!("Audio format: {:?}, bits_per_sample: {}, sample_rate: {}, channels: {}",
-             spec.sample_format, spec.bits_per_sample, spec.sample_rate, spec.channels);
-
-    let mut samples: Vec<f32> = match (spec.sample_format, spec.bits_per_sample) {
-        (hound::SampleFormat::Float, 32) => {
-            reader.samples::<f32>()
-                .map(|s| s.expect("Failed to read f32 sample"))
-                .collect()
-        }
-        (hound::SampleFormat::Int, 8) => {
-            reader.samples::<i8>()
-                .map(|s| s.expect("Failed to read i8 sample") as f32 / i8::MAX as f32)
-                .collect()
-        }
-        (hound::SampleFormat::Int, 16) => {
-            reader.samples::<i16>()
-                .map(|s| s.expect("Failed to read i16 sample") as f32 / i16::MAX as f32)
-                .collect()
-        }
-        (hound::SampleFormat::Int, 24) => {
-            reader.samples::<i32>()
-                .map(|s| s.expect("Failed to read i32 (24-bit) sample") as f32 / 8388608.0) // 2^23
-                .collect()
-        }
-        (hound::SampleFormat::Int, 32) => {
-            reader.samples::<i32>()
-                .map(|s| s.expect("Failed to read i32 sample") as f32 / i32::MAX as f32)
-                .collect()
-        }
-        _ => {
-            return Err(format!(
-                "Unsupported audio format: {:?} with {} bits per sample",
-                spec.sample_format, spec.bits_per_sample
-            ).into());
-        }
-    };
-
-    // Convert stereo to mono if needed
-    if spec.channels == 2 {
-        println!("Converting stereo to mono by averaging channels");
-        let mono_samples: Vec<f32> = samples
-            .chunks(2)
-            .map(|chunk| (chunk[0] + chunk[1]) / 2.0)
-            .collect();
-        samples = mono_samples;
-    } else if spec.channels > 2 {
-        return Err(format!("Only mono and stereo audio supported, got {} channels", spec.channels).into());
-    }
-
-    // Compute STFT using config values
-    let mut planner = FftPlanner::new();
-    let fft = planner.plan_fft_forward(config.fft_size);
-
-    let num_frames = (samples.len() - config.fft_size) / config.hop_size + 1;
-    let num_bins_linear = config.fft_size / 2 + 1; // Only positive frequencies (no mirror)
-
-    println!("Audio duration: {:.2} seconds", samples.len() as f32 / spec.sample_rate as f32);
-    println!("Will create {} frames (image width = {} pixels)", num_frames, num_frames);
-
-    let mut spectrogram_mag_linear = vec![vec![0.0f32; num_frames]; num_bins_linear];
-    let mut spectrogram_phase_linear = vec![vec![0.0f32; num_frames]; num_bins_linear];
-
-    for frame_idx in 0..num_frames {
-        let start = frame_idx * config.hop_size;
-        let end = start + config.fft_size;
-        
-        if end > samples.len() {
-            break;
-        }
-        
-        // Apply Hann window and prepare FFT input
-        let mut buffer: Vec<Complex<f32>> = samples[start..end]
-            .iter()
-            .enumerate()
-            .map(|(i, &s)| {
-                let window = 0.5 * (1.0 - ((2.0 * std::f32::consts::PI * i as f32) / (config.fft_size as f32 - 1.0)).cos());
-                Complex::new(s * window, 0.0)
-            })
-            .collect();
-        
-        fft.process(&mut buffer);
-
-        // Store magnitude and phase spectrum (only positive frequencies)
-        for (bin, &value) in buffer.iter().take(num_bins_linear).enumerate() {
-            spectrogram_mag_linear[bin][frame_idx] = value.norm();
-            spectrogram_phase_linear[bin][frame_idx] = value.arg(); // Phase angle in radians
-        }
-    }
-
-    // Apply frequency scale transformation if needed
-    let (spectrogram_mag, spectrogram_phase, num_bins) = if config.use_log_scale {
-        // Convert to logarithmic frequency scale
-        let sample_rate = spec.sample_rate as f32;
-        let nyquist = sample_rate / 2.0;
-        let min_freq = config.min_freq;
-        let max_freq = nyquist;
-
-        println!("Encoding log scale: nyquist={}, min_freq={}, max_freq={}", nyquist, min_freq, max_freq);
-        println!("num_bins_linear={}, num_bins_log will be={}", num_bins_linear, num_bins_linear);
-
-        // Use the same number of bins for consistency in image size
-        let num_bins_log = num_bins_linear;
-
-        let mut spectrogram_mag_log = vec![vec![0.0f32; num_frames]; num_bins_log];
-        let mut spectrogram_phase_log = vec![vec![0.0f32; num_frames]; num_bins_log];
-
-        // Create logarithmic frequency mapping
-        for log_bin in 0..num_bins_log {
-            // Calculate the frequency for this logarithmic bin
-            let t = log_bin as f32 / (num_bins_log - 1) as f32;
-            let freq_log = min_freq * (max_freq / min_freq).powf(t);
+use crate::audio_io::{probe_audio, read_audio, AudioData};
+use crate::config::{ChannelMode, ImageFormat, SpectrogramConfig};
+use crate::metadata::{save_image, SpectrogramMetadata};
+use crate::progress::Progress;
+use crate::spectrogram::{analyze_with_progress, Spectrogram};
+use crate::streaming::audio_to_tiles;
+
+/// Widest image written in one piece: longer files are split into tiles of this many frames
+/// even without `config.tile_width`, since many editors refuse wider images
+pub const MAX_IMAGE_WIDTH: usize = 65_535;
+
+/// Render a spectrogram as an image (Hue = phase, Saturation = phase hold, Value = magnitude)
+/// Magnitudes are normalized to the spectrogram's peak; high frequencies are at the top
+/// Channel values are floats in [0, 1] (beyond it for EXR, which keeps every level), quantized only when saved
+pub fn spectrogram_to_image(spectrogram: &Spectrogram) -> Rgb32FImage {
+    spectrogram_to_image_with_reference(spectrogram, spectrogram.max_magnitude())
+}
 
-            // Convert frequency to linear bin (fractional)
-            let bin_linear_float = freq_log / nyquist * (num_bins_linear - 1) as f32;
+/// Render a spectrogram as an image with `reference_magnitude` mapped to 0 dB
+/// Use a shared reference to keep the level relationship between several spectrograms
+pub fn spectrogram_to_image_with_reference(spectrogram: &Spectrogram, reference_magnitude: f32) -> Rgb32FImage {
+    let config = &spectrogram.config;
+    let width = spectrogram.num_frames() as u32;
+    let height = spectrogram.num_bins() as u32;
 
-            // Debug: Show frequency mapping at regular intervals to verify exponential distribution
-            if log_bin % 100 == 0 {
-                println!("Log bin {}: {:.1} Hz (pixel row {} from bottom)", log_bin, freq_log, log_bin);
-            }
+    let mut img = Rgb32FImage::new(width, height);
 
-            // Interpolate magnitude and phase from linear bins
-            for frame_idx in 0..num_frames {
-                let (mag, phase) = interpolate_spectrum(
-                    &spectrogram_mag_linear,
-                    &spectrogram_phase_linear,
-                    bin_linear_float,
-                    frame_idx,
-                );
-                spectrogram_mag_log[log_bin][frame_idx] = mag;
-                spectrogram_phase_log[log_bin][frame_idx] = phase;
-            }
-        }
-
-        // Verify logarithmic spacing by checking octave intervals
-        println!("\nVerifying logarithmic spacing:");
-        println!("Looking for musical notes (A notes: 110, 220, 440, 880, 1760 Hz):");
-        for target_freq in [110.0, 220.0, 440.0, 880.0, 1760.0, 3520.0] {
-            // Find which bin corresponds to this frequency
-            let t = (target_freq / min_freq).ln() / (max_freq / min_freq).ln();
-            let bin = (t * (num_bins_log - 1) as f32) as usize;
-            if bin < num_bins_log {
-                let actual_freq = min_freq * (max_freq / min_freq).powf(bin as f32 / (num_bins_log - 1) as f32);
-                println!("  {} Hz -> bin {} (actual: {:.1} Hz)", target_freq, bin, actual_freq);
-            }
-        }
-
-        (spectrogram_mag_log, spectrogram_phase_log, num_bins_log)
-    } else {
-        // Use linear frequency scale as-is
-        (spectrogram_mag_linear, spectrogram_phase_linear, num_bins_linear)
-    };
-    
-    // Convert to HSV image (Hue = phase, Saturation = 1, Value = magnitude)
-    let width = num_frames as u32;
-    let height = num_bins as u32;
-    
-    println!("Creating spectrogram image: {}x{} (width x height)", width, height);
-    println!("FFT_SIZE: {}, HOP_SIZE: {}, num_bins: {}", config.fft_size, config.hop_size, num_bins);
-    
-    let mut img = ImageBuffer::new(width, height);
-    
-    // Find global max magnitude for normalization
-    let max_val = spectrogram_mag.iter()
-        .flat_map(|row| row.iter())
-        .cloned()
-        .fold(0.0f32, f32::max);
+    let max_val = reference_magnitude;
 
-    println!("Max magnitude: {}", max_val);
+    // Float images keep levels above db_max and below db_min; integer formats would clip them
+    // (and shift the hue)
+    let float_image = config.image_format == ImageFormat::Exr;
+    let (min_value, max_value) = if float_image { (f32::NEG_INFINITY, f32::INFINITY) } else { (0.0, 1.0) };
 
-    for (bin, mag_row) in spectrogram_mag.iter().enumerate() {
+    for (bin, mag_row) in spectrogram.magnitude.iter().enumerate() {
         // Apply frequency-dependent boost to preserve high frequencies
-        // Calculate frequency for this bin
-        let bin_freq = if config.use_log_scale {
-            // For log scale, bin represents a specific frequency
-            let sample_rate = spec.sample_rate as f32;
-            let nyquist = sample_rate / 2.0;
-            let t = bin as f32 / (num_bins - 1) as f32;
-            config.min_freq * (nyquist / config.min_freq).powf(t)
-        } else {
-            // For linear scale
-            let sample_rate = spec.sample_rate as f32;
-            let nyquist = sample_rate / 2.0;
-            (bin as f32 / (num_bins - 1) as f32) * nyquist
-        };
-
-        // Apply high-frequency boost using config values
-        let boost_db = if bin_freq > config.boost_start_freq {
-            config.boost_db_per_octave * (bin_freq / config.boost_start_freq).log2()
-        } else {
-            0.0
-        };
+        let boost_db = spectrogram.boost_db(bin);
 
         for (frame, &magnitude) in mag_row.iter().enumerate() {
-            let phase = spectrogram_phase[bin][frame];
-
             // Convert to dB scale with frequency-dependent boost
             let value = if max_val > 0.0 && magnitude > 0.0 {
                 let db = 20.0 * (magnitude / max_val).log10() + boost_db;
                 let normalized = (db - config.db_min) / (config.db_max - config.db_min);
-                normalized.clamp(0.0, 1.0)
+                normalized.clamp(min_value, max_value)
             } else {
                 0.0
             };
-            
-            let rgb = if config.use_phase_encoding {
-                // Color mode: encode phase in hue
-                // Convert phase from [-π, π] to [0, 360] degrees
-                let hue = ((phase + std::f32::consts::PI) / (2.0 * std::f32::consts::PI) * 360.0) % 360.0;
-
-                // Use saturation to encode "phase hold" for very quiet frequencies
-                // When saturation=0, decoder will continue phase from previous frame
-                let saturation = if value < 0.01 {
-                    0.0  // Very quiet - signal to hold/continue phase
-                } else {
-                    1.0  // Normal - use this frame's phase
-                };
 
-                // Convert HSV to RGB
-                hsv_to_rgb(hue, saturation, value)
-            } else {
-                // Grayscale mode: magnitude only (no phase encoding)
-                let gray = (value * 255.0) as u8;
-                [gray, gray, gray]
+            let rgb = match &spectrogram.phase {
+                Some(phase_rows) if config.use_phase_encoding => {
+                    // Color mode: encode phase in hue
+                    // Convert phase from [-π, π] to [0, 360] degrees
+                    let phase = phase_rows[bin][frame];
+                    let hue = ((phase + std::f32::consts::PI) / (2.0 * std::f32::consts::PI) * 360.0) % 360.0;
+
+                    // Use saturation to encode "phase hold" for very quiet frequencies
+                    // When saturation=0, decoder will continue phase from previous frame
+                    // Float images keep the hue of quiet pixels exactly, so they always store the phase
+                    let saturation = if value < 0.01 && !float_image {
+                        0.0  // Very quiet - signal to hold/continue phase
+                    } else {
+                        1.0  // Normal - use this frame's phase
+                    };
+
+                    // Convert HSV to RGB; levels below db_min are stored as the negated color
+                    let rgb = hsv_to_rgb(hue, saturation, value.abs());
+                    if value < 0.0 { rgb.map(|c| -c) } else { rgb }
+                }
+                _ => {
+                    // Grayscale mode: magnitude only (no phase encoding)
+                    [value, value, value]
+                }
             };
 
             // Flip vertically (high frequencies at top)
@@ -243,71 +79,148 @@ pub fn audio_to_spectrogram(
             img.put_pixel(frame as u32, y, Rgb(rgb));
         }
     }
-    
-    // Save image with sample rate and scale mode in filename
-    // Format: filename_SR{sample_rate}_LOG_PHASE.png or filename_SR{sample_rate}_LIN_MAG.png
-    let sample_rate = spec.sample_rate;
-    let scale_suffix = if config.use_log_scale { "_LOG" } else { "_LIN" };
+
+    img
+}
+
+/// Path of the image holding one channel when channels are saved as separate images
+/// Appends `_CH{n}` (1-based) to the file stem
+pub fn channel_image_path(image_path: &Path, channel: usize) -> PathBuf {
+    let stem = image_path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = image_path.extension().unwrap_or_default().to_string_lossy();
+    image_path.with_file_name(format!("{}_CH{}.{}", stem, channel + 1, extension))
+}
+
+/// Output path with sample rate and scale mode appended to the file stem
+/// Format: filename_SR{sample_rate}_LOG_PHASE.png or filename_SR{sample_rate}_MEL_MAG.png,
+/// with the extension of `config.image_format`
+pub fn encoded_image_path(output_path: &Path, sample_rate: u32, config: &SpectrogramConfig) -> PathBuf {
+    let scale_suffix = format!("_{}", config.frequency_scale.suffix());
     let phase_suffix = if config.use_phase_encoding { "_PHASE" } else { "_MAG" };
-    let output_with_sr = if let Some(stem) = output_path.file_stem() {
+    if let Some(stem) = output_path.file_stem() {
         let parent = output_path.parent().unwrap_or(Path::new(""));
-        parent.join(format!("{}_SR{}{}{}.png", stem.to_string_lossy(), sample_rate, scale_suffix, phase_suffix))
+        parent.join(format!("{}_SR{}{}{}.{}", stem.to_string_lossy(), sample_rate, scale_suffix, phase_suffix,
+                            config.image_format.extension()))
     } else {
         output_path.to_path_buf()
-    };
-
-    img.save(&output_with_sr)?;
-    println!("Saved spectrogram to: {}", output_with_sr.display());
-    Ok(output_with_sr)
+    }
 }
 
-// Interpolate magnitude and phase from linear spectrum at a fractional bin index
-fn interpolate_spectrum(
-    mag: &Vec<Vec<f32>>,
-    phase: &Vec<Vec<f32>>,
-    bin_float: f32,
-    frame: usize,
-) -> (f32, f32) {
-    let bin_floor = bin_float.floor() as usize;
-    let bin_ceil = (bin_float.ceil() as usize).min(mag.len() - 1);
-    let frac = bin_float - bin_floor as f32;
+/// Encode an audio file as one or more spectrogram images, returning the first image written
+/// With `config.tile_width` set, or for files wider than `MAX_IMAGE_WIDTH` frames, the file is
+/// streamed into tiles instead and the tile manifest is returned (see `streaming`)
+/// Fails with `Cancelled` when `progress` is cancelled; nothing is written then
+pub fn audio_to_spectrogram(
+    audio_path: &Path,
+    output_path: &Path,
+    config: &SpectrogramConfig,
+    progress: &Progress,
+) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    if config.tile_width > 0 {
+        return audio_to_tiles(audio_path, output_path, config, progress);
+    }
+    // Check the length in the header first, so files too wide for one image are not read whole
+    let estimated_width = probe_audio(audio_path).ok()
+        .and_then(|info| info.num_samples)
+        .map(|samples| config.num_frames(samples));
+    if let Some(width) = estimated_width.filter(|&width| width > MAX_IMAGE_WIDTH) {
+        return audio_to_widest_tiles(audio_path, output_path, config, width, progress);
+    }
 
-    if bin_floor >= mag.len() {
-        return (0.0, 0.0);
+    progress.status("Reading audio file...");
+    let AudioData { channels, sample_rate } = read_audio(audio_path)?;
+    let width = config.num_frames(channels[0].len());
+    if width > MAX_IMAGE_WIDTH {
+        drop(channels);
+        return audio_to_widest_tiles(audio_path, output_path, config, width, progress);
     }
 
-    if bin_floor == bin_ceil {
-        return (mag[bin_floor][frame], phase[bin_floor][frame]);
+    println!("Audio duration: {:.2} seconds", channels[0].len() as f32 / sample_rate as f32);
+
+    if config.channel_mode == ChannelMode::Mono && channels.len() > 1 {
+        println!("Converting {} channels to mono by averaging", channels.len());
     }
+    let signals = config.channel_mode.split(channels)?;
 
-    // Linear interpolation for magnitude
-    let mag_interp = mag[bin_floor][frame] * (1.0 - frac) + mag[bin_ceil][frame] * frac;
+    let spectrograms = signals.iter()
+        .map(|signal| analyze_with_progress(signal, sample_rate, config, progress))
+        .collect::<Result<Vec<Spectrogram>, _>>()?;
+    progress.status("Saving image...");
 
-    // Phase interpolation (handle wraparound)
-    let phase1 = phase[bin_floor][frame];
-    let phase2 = phase[bin_ceil][frame];
-    let mut phase_diff = phase2 - phase1;
+    // All channels share one reference so their relative levels survive the round trip
+    let reference_magnitude = spectrograms.iter()
+        .map(|s| s.max_magnitude())
+        .fold(0.0f32, f32::max);
 
-    // Wrap phase difference to [-π, π]
-    while phase_diff > std::f32::consts::PI {
-        phase_diff -= 2.0 * std::f32::consts::PI;
-    }
-    while phase_diff < -std::f32::consts::PI {
-        phase_diff += 2.0 * std::f32::consts::PI;
-    }
+    println!("Creating {} spectrogram(s): {}x{} (width x height)",
+             spectrograms.len(), spectrograms[0].num_frames(), spectrograms[0].num_bins());
+    println!("FFT_SIZE: {}, HOP_SIZE: {}, num_bins: {}", config.fft_size, config.hop_size, spectrograms[0].num_bins());
+    println!("Max magnitude: {}", reference_magnitude);
+
+    let mut metadata = SpectrogramMetadata::from_spectrogram(&spectrograms[0]);
+    metadata.peak_magnitude = Some(reference_magnitude);
+    metadata.channels = spectrograms.len();
 
-    let phase_interp = phase1 + phase_diff * frac;
+    // Save image with full metadata embedded, and sample rate and scale mode in the filename
+    // for editors that strip PNG text chunks
+    let output_with_sr = encoded_image_path(output_path, sample_rate, config);
+    let saved = save_spectrograms(&spectrograms, reference_magnitude, &mut metadata, &output_with_sr)?;
+    Ok(saved[0].clone())
+}
 
-    (mag_interp, phase_interp)
+// Split a file `width` frames wide into tiles of MAX_IMAGE_WIDTH frames
+fn audio_to_widest_tiles(
+    audio_path: &Path,
+    output_path: &Path,
+    config: &SpectrogramConfig,
+    width: usize,
+    progress: &Progress,
+) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    println!("Image would be {} pixels wide, splitting it into tiles of {} frames", width, MAX_IMAGE_WIDTH);
+    let config = SpectrogramConfig { tile_width: MAX_IMAGE_WIDTH, ..config.clone() };
+    audio_to_tiles(audio_path, output_path, &config, progress)
 }
 
-// Convert HSV to RGB
-fn hsv_to_rgb(h: f32, s: f32, v: f32) -> [u8; 3] {
+// Save the channels' spectrograms stacked in one image or as `_CH{n}` images, returning the paths written
+pub(crate) fn save_spectrograms(
+    spectrograms: &[Spectrogram],
+    reference_magnitude: f32,
+    metadata: &mut SpectrogramMetadata,
+    path: &Path,
+) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+    if spectrograms.len() == 1 || spectrograms[0].config.stack_channels {
+        // Stack channel bands vertically, first channel at the top
+        let band_height = spectrograms[0].num_bins() as u32;
+        let mut img = Rgb32FImage::new(spectrograms[0].num_frames() as u32, band_height * spectrograms.len() as u32);
+        for (channel, spectrogram) in spectrograms.iter().enumerate() {
+            let band = spectrogram_to_image_with_reference(spectrogram, reference_magnitude);
+            image::imageops::replace(&mut img, &band, 0, (channel as u32 * band_height) as i64);
+        }
+
+        save_image(&img, path, metadata)?;
+        println!("Saved spectrogram to: {}", path.display());
+        Ok(vec![path.to_path_buf()])
+    } else {
+        let mut paths = Vec::with_capacity(spectrograms.len());
+        for (channel, spectrogram) in spectrograms.iter().enumerate() {
+            let img = spectrogram_to_image_with_reference(spectrogram, reference_magnitude);
+            let channel_path = channel_image_path(path, channel);
+            metadata.channel = channel;
+            save_image(&img, &channel_path, metadata)?;
+            println!("Saved channel {} spectrogram to: {}", channel + 1, channel_path.display());
+            paths.push(channel_path);
+        }
+        Ok(paths)
+    }
+}
+
+// Convert HSV to RGB in [0, 1]
+fn hsv_to_rgb(h: f32, s: f32, v: f32) -> [f32; 3] {
     let c = v * s;
     let h_prime = h / 60.0;
     let x = c * (1.0 - ((h_prime % 2.0) - 1.0).abs());
     let m = v - c;
-    
+
     let (r, g, b) = if h_prime < 1.0 {
         (c, x, 0.0)
     } else if h_prime < 2.0 {
@@ -321,10 +234,6 @@ fn hsv_to_rgb(h: f32, s: f32, v: f32) -> [u8; 3] {
     } else {
         (c, 0.0, x)
     };
-    
-    [
-        ((r + m) * 255.0) as u8,
-        ((g + m) * 255.0) as u8,
-        ((b + m) * 255.0) as u8,
-    ]
+
+    [r + m, g + m, b + m]
 }
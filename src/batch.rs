@@ -0,0 +1,157 @@
+//! Converting many files at once
+//!
+//! [`collect_inputs`] expands folders into the files in them that can be converted, and
+//! [`run_batch`] converts them on a pool of worker threads with [`process_file`], reporting
+//! each job's progress and outcome as it goes.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use crate::config::SpectrogramConfig;
+use crate::convert::{process_file, InputKind};
+use crate::metadata::SpectrogramMetadata;
+use crate::progress::{CancelToken, Cancelled, Progress};
+use crate::project::is_project;
+use crate::streaming::{is_tile_manifest, manifest_tiles};
+
+/// How far one file of a batch has got
+#[derive(Debug, Clone, PartialEq)]
+pub enum JobStatus {
+    Queued,
+    /// `fraction` is the completed part of the current step, when known
+    Running { status: String, fraction: Option<f32> },
+    Done { output_path: PathBuf },
+    Failed { message: String },
+    Cancelled,
+}
+
+impl JobStatus {
+    /// Whether the job has ended, one way or another
+    pub fn is_finished(&self) -> bool {
+        matches!(self, JobStatus::Done { .. } | JobStatus::Failed { .. } | JobStatus::Cancelled)
+    }
+}
+
+/// Counts of how the jobs of a batch ended
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BatchSummary {
+    pub succeeded: usize,
+    pub failed: usize,
+    pub cancelled: usize,
+}
+
+impl BatchSummary {
+    pub fn of(statuses: &[JobStatus]) -> Self {
+        let count = |matches: fn(&JobStatus) -> bool| statuses.iter().filter(|status| matches(status)).count();
+        Self {
+            succeeded: count(|status| matches!(status, JobStatus::Done { .. })),
+            failed: count(|status| matches!(status, JobStatus::Failed { .. })),
+            cancelled: count(|status| matches!(status, JobStatus::Cancelled)),
+        }
+    }
+}
+
+impl fmt::Display for BatchSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} succeeded, {} failed", self.succeeded, self.failed)?;
+        if self.cancelled > 0 {
+            write!(f, ", {} cancelled", self.cancelled)?;
+        }
+        Ok(())
+    }
+}
+
+/// Files to convert for `paths`: files and projects as given, and for other folders the audio
+/// files, images, tile manifests and projects directly in them, in name order
+/// Images decoded along with another one (`_CH` channel siblings, later tiles and tiles listed
+/// by a manifest in the folder) are left out, as converting them again would write the same audio.
+pub fn collect_inputs(paths: &[PathBuf]) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+    let mut inputs = Vec::new();
+    for path in paths {
+        if !path.is_dir() || is_project(path) {
+            inputs.push(path.clone());
+            continue;
+        }
+
+        let mut entries: Vec<PathBuf> = fs::read_dir(path)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<_, _>>()?;
+        entries.sort();
+        let in_manifests: HashSet<PathBuf> = entries.iter()
+            .filter(|entry| is_tile_manifest(entry))
+            .filter_map(|manifest| manifest_tiles(manifest).ok())
+            .flatten()
+            .flatten()
+            .collect();
+        inputs.extend(entries.into_iter().filter(|entry| {
+            if entry.is_dir() {
+                return is_project(entry);
+            }
+            match InputKind::from_path(entry) {
+                Ok(InputKind::Image) if !is_tile_manifest(entry) => !in_manifests.contains(entry) && !is_sibling_image(entry),
+                Ok(_) => true,
+                Err(_) => false,
+            }
+        }));
+    }
+    Ok(inputs)
+}
+
+// Whether an image is decoded through another one: a channel after the first or a tile after the first
+fn is_sibling_image(path: &Path) -> bool {
+    matches!(SpectrogramMetadata::read(path), Ok(Some(metadata)) if metadata.channel > 0 || metadata.tile > 0)
+}
+
+/// Convert `inputs` to their default output paths, `workers` at a time (0 = one per CPU core)
+/// The threads of `config.num_threads` are shared out between the workers.
+/// `report(index, status)` is called as each job starts, progresses and ends. Cancelling stops
+/// the running jobs and skips the queued ones. Returns how each job ended.
+pub fn run_batch(
+    inputs: &[PathBuf],
+    config: &SpectrogramConfig,
+    workers: usize,
+    cancel: &CancelToken,
+    report: &(dyn Fn(usize, JobStatus) + Sync),
+) -> Vec<JobStatus> {
+    let threads = match config.num_threads {
+        0 => thread::available_parallelism().map_or(1, |n| n.get()),
+        threads => threads,
+    };
+    let workers = if workers == 0 { threads } else { workers }.clamp(1, inputs.len().max(1));
+    let config = SpectrogramConfig { num_threads: (threads / workers).max(1), ..config.clone() };
+
+    let statuses = Mutex::new(vec![JobStatus::Queued; inputs.len()]);
+    let set_status = |index: usize, status: JobStatus| {
+        statuses.lock().unwrap()[index] = status.clone();
+        report(index, status);
+    };
+    let next = AtomicUsize::new(0);
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some(input) = inputs.get(index) else { break };
+                if cancel.is_cancelled() {
+                    set_status(index, JobStatus::Cancelled);
+                    continue;
+                }
+
+                let progress_report = |status: &str, fraction: Option<f32>| {
+                    set_status(index, JobStatus::Running { status: status.to_string(), fraction });
+                };
+                progress_report("Starting...", None);
+                let status = match process_file(input, None, &config, &Progress::new(&progress_report, cancel.clone())) {
+                    Ok(output_path) => JobStatus::Done { output_path },
+                    Err(e) if e.downcast_ref::<Cancelled>().is_some() => JobStatus::Cancelled,
+                    Err(e) => JobStatus::Failed { message: e.to_string() },
+                };
+                set_status(index, status);
+            });
+        }
+    });
+    statuses.into_inner().unwrap()
+}
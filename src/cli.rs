@@ -0,0 +1,619 @@
+use clap::{Args, Parser, Subcommand};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use spectrogram_converter::batch::{collect_inputs, run_batch, BatchSummary, JobStatus};
+use spectrogram_converter::convert::{process_file, InputKind};
+use spectrogram_converter::preset::{Presets, BUILTIN_PRESETS};
+use spectrogram_converter::project::{Project, PROJECT_EXTENSION};
+use spectrogram_converter::{BitDepth, ChannelMode, FrequencyScale, ImageFormat, LevelMode, OutputFormat, Padding, PeakProtection, PhaseInit, PhaseRetrieval, SpectrogramConfig, WindowFunction};
+use spectrogram_converter::{CancelToken, Progress};
+
+/// Bidirectional audio ↔ spectrogram image converter
+///
+/// Run without a subcommand to open the GUI
+#[derive(Parser)]
+#[command(version, about)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Encode an audio file into a spectrogram image
+    Encode(ConvertArgs),
+    /// Decode a spectrogram image back into audio
+    Decode(ConvertArgs),
+    /// Convert many files and folders at once, each in the direction its type calls for
+    Batch(BatchArgs),
+    /// Create or render a project (analysis, edit layers and export settings in one folder)
+    #[command(subcommand)]
+    Project(ProjectCommand),
+    /// List named presets, or save settings as one
+    #[command(subcommand)]
+    Preset(PresetCommand),
+}
+
+#[derive(Subcommand)]
+pub enum PresetCommand {
+    /// List the built-in and saved presets
+    List,
+    /// Save settings (a config file, a preset or the defaults, with any overrides) as a preset
+    Save(Box<SavePresetArgs>),
+}
+
+#[derive(Subcommand)]
+pub enum ProjectCommand {
+    /// Analyze an audio file (or decode a spectrogram image) into a new project folder
+    New(NewProjectArgs),
+    /// Render a project's edited spectrogram to an image and audio
+    Render(RenderProjectArgs),
+}
+
+#[derive(Args)]
+pub struct ConvertArgs {
+    /// Input file
+    input: PathBuf,
+
+    /// Output file (encoded images get the _SR/_LOG/_PHASE suffix appended to the stem)
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// Configuration file to start from (defaults to spectrogram_config.toml if present)
+    #[arg(short, long)]
+    config: Option<PathBuf>,
+
+    /// Named preset to start from instead (see `preset list`)
+    #[arg(short, long, conflicts_with = "config")]
+    preset: Option<String>,
+
+    #[command(flatten)]
+    overrides: ConfigOverrides,
+}
+
+#[derive(Args)]
+pub struct BatchArgs {
+    /// Files, and folders whose files are all converted (next to the inputs, with default names)
+    #[arg(required = true)]
+    inputs: Vec<PathBuf>,
+
+    /// Files converted at once (0 = one per CPU core); the threads are shared out between them
+    #[arg(short, long, default_value_t = 0)]
+    workers: usize,
+
+    /// Configuration file to start from (defaults to spectrogram_config.toml if present)
+    #[arg(short, long)]
+    config: Option<PathBuf>,
+
+    /// Named preset to start from instead (see `preset list`)
+    #[arg(short, long, conflicts_with = "config")]
+    preset: Option<String>,
+
+    #[command(flatten)]
+    overrides: ConfigOverrides,
+}
+
+#[derive(Args)]
+pub struct SavePresetArgs {
+    /// Preset name (letters, digits, '-' and '_')
+    name: String,
+
+    /// Replace a preset already saved under this name
+    #[arg(long)]
+    force: bool,
+
+    /// Configuration file to start from (defaults to spectrogram_config.toml if present)
+    #[arg(short, long)]
+    config: Option<PathBuf>,
+
+    /// Named preset to start from instead
+    #[arg(short, long, conflicts_with = "config")]
+    preset: Option<String>,
+
+    #[command(flatten)]
+    overrides: ConfigOverrides,
+}
+
+#[derive(Args)]
+pub struct NewProjectArgs {
+    /// Audio file or spectrogram image
+    input: PathBuf,
+
+    /// Project folder (defaults to the input path with .specproj)
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// Image rendering writes (defaults to export.<ext> in the project folder)
+    #[arg(long)]
+    image: Option<PathBuf>,
+
+    /// Audio rendering writes (defaults to export.<ext> in the project folder)
+    #[arg(long)]
+    audio: Option<PathBuf>,
+
+    /// Configuration file to start from (defaults to spectrogram_config.toml if present)
+    #[arg(short, long)]
+    config: Option<PathBuf>,
+
+    /// Named preset to start from instead (see `preset list`)
+    #[arg(short, long, conflicts_with = "config")]
+    preset: Option<String>,
+
+    #[command(flatten)]
+    overrides: ConfigOverrides,
+}
+
+#[derive(Args)]
+pub struct RenderProjectArgs {
+    /// Project folder (or the project.toml in it)
+    project: PathBuf,
+
+    /// Write the image here instead of where the project says
+    #[arg(long, conflicts_with = "no_image")]
+    image: Option<PathBuf>,
+
+    /// Write the audio here instead of where the project says
+    #[arg(long, conflicts_with = "no_audio")]
+    audio: Option<PathBuf>,
+
+    /// Skip the image
+    #[arg(long)]
+    no_image: bool,
+
+    /// Skip the audio
+    #[arg(long)]
+    no_audio: bool,
+
+    /// Configuration file for the decoding settings (defaults to spectrogram_config.toml if present)
+    #[arg(short, long)]
+    config: Option<PathBuf>,
+
+    /// Named preset to start from instead (see `preset list`)
+    #[arg(short, long, conflicts_with = "config")]
+    preset: Option<String>,
+
+    /// Only decoding settings (phase retrieval, level, output format, threads) apply; the
+    /// analysis settings are the project's
+    #[command(flatten)]
+    overrides: ConfigOverrides,
+}
+
+/// Command-line overrides for every SpectrogramConfig field
+#[derive(Args)]
+struct ConfigOverrides {
+    /// FFT window size (power of 2, 256-16384)
+    #[arg(long)]
+    fft_size: Option<usize>,
+
+    /// Hop size between consecutive FFT windows
+    #[arg(long, visible_alias = "hop-size")]
+    hop: Option<usize>,
+
+    /// Padding at the signal ends: reflect, zero, edge or none (legacy framing)
+    #[arg(long)]
+    padding: Option<Padding>,
+
+    /// Window function: hann, hamming, blackman-harris, kaiser, gaussian or rectangular
+    #[arg(long)]
+    window: Option<WindowFunction>,
+
+    /// Kaiser window beta (higher = lower sidelobes)
+    #[arg(long)]
+    kaiser_beta: Option<f32>,
+
+    /// Gaussian window sigma, relative to half the window length
+    #[arg(long)]
+    gaussian_sigma: Option<f32>,
+
+    /// Number of frequency bins / image height (0 = fft_size / 2 + 1)
+    #[arg(long, visible_alias = "height")]
+    num_bins: Option<usize>,
+
+    /// Lowest frequency in the image (Hz)
+    #[arg(long)]
+    min_freq: Option<f32>,
+
+    /// Highest frequency in the image (Hz, 0 = Nyquist)
+    #[arg(long)]
+    max_freq: Option<f32>,
+
+    /// Minimum dB level for visualization
+    #[arg(long, allow_hyphen_values = true)]
+    db_min: Option<f32>,
+
+    /// Maximum dB level for visualization
+    #[arg(long, allow_hyphen_values = true)]
+    db_max: Option<f32>,
+
+    /// High-frequency boost starting frequency (Hz)
+    #[arg(long)]
+    boost_start_freq: Option<f32>,
+
+    /// High-frequency boost amount (dB per octave)
+    #[arg(long, allow_hyphen_values = true)]
+    boost_db_per_octave: Option<f32>,
+
+    /// Encode phase in color (perfect reconstruction)
+    #[arg(long, conflicts_with = "mag")]
+    phase: bool,
+
+    /// Grayscale magnitude only (phase rebuilt on decoding, see --phase-retrieval)
+    #[arg(long)]
+    mag: bool,
+
+    /// Frequency scale: linear, log, mel, bark or erb
+    #[arg(long)]
+    scale: Option<FrequencyScale>,
+
+    /// Logarithmic (musical) frequency scale, same as --scale log
+    #[arg(long, conflicts_with_all = ["linear", "scale"])]
+    log: bool,
+
+    /// Linear (technical) frequency scale, same as --scale linear
+    #[arg(long, conflicts_with = "scale")]
+    linear: bool,
+
+    /// Image format: png (8-bit), png16, tiff16 or exr (32-bit float)
+    #[arg(long)]
+    image_format: Option<ImageFormat>,
+
+    /// Frames per image for long files (0 = one image)
+    #[arg(long)]
+    tile_width: Option<usize>,
+
+    /// Number of Griffin-Lim iterations for magnitude-only reconstruction (per frame for RTISI-LA)
+    #[arg(long, visible_alias = "iterations")]
+    griffin_lim_iterations: Option<usize>,
+
+    /// Phase reconstruction: fast-griffin-lim, griffin-lim, rtisi-la or pghi
+    #[arg(long)]
+    phase_retrieval: Option<PhaseRetrieval>,
+
+    /// Starting phases for (Fast) Griffin-Lim: pghi, random or zero
+    #[arg(long)]
+    phase_init: Option<PhaseInit>,
+
+    /// Fast Griffin-Lim momentum (0-1)
+    #[arg(long, visible_alias = "momentum")]
+    griffin_lim_momentum: Option<f32>,
+
+    /// RTISI-LA lookahead in frames
+    #[arg(long, visible_alias = "lookahead")]
+    rtisi_lookahead: Option<usize>,
+
+    /// Channel handling: mono (downmix), multichannel or mid-side
+    #[arg(long)]
+    channel_mode: Option<ChannelMode>,
+
+    /// Stack channels as bands in one image
+    #[arg(long, conflicts_with = "separate_channels")]
+    stack_channels: bool,
+
+    /// Write one image per channel (_CH1, _CH2, ...)
+    #[arg(long)]
+    separate_channels: bool,
+
+    /// Decoded level: normalize (peak to 0.95) or original (restore encoded level)
+    #[arg(long)]
+    level: Option<LevelMode>,
+
+    /// Peak protection for decoded audio: none, scale or limit
+    #[arg(long)]
+    peak_protection: Option<PeakProtection>,
+
+    /// Ceiling for peak protection (dBTP)
+    #[arg(long, allow_hyphen_values = true)]
+    peak_ceiling_db: Option<f32>,
+
+    /// Decoded audio file format: wav or flac
+    #[arg(long)]
+    format: Option<OutputFormat>,
+
+    /// Decoded audio sample format: 16, 24 or 32f (float, WAV only)
+    #[arg(long)]
+    bit_depth: Option<BitDepth>,
+
+    /// Add TPDF dither when writing 16-bit audio
+    #[arg(long, conflicts_with = "no_dither")]
+    dither: bool,
+
+    /// Plain rounding when writing 16-bit audio
+    #[arg(long)]
+    no_dither: bool,
+
+    /// Worker threads (0 = one per CPU core)
+    #[arg(long, visible_alias = "jobs")]
+    threads: Option<usize>,
+}
+
+impl ConfigOverrides {
+    fn apply(&self, config: &mut SpectrogramConfig) {
+        if let Some(fft_size) = self.fft_size {
+            config.fft_size = fft_size;
+        }
+        if let Some(hop) = self.hop {
+            config.hop_size = hop;
+        }
+        if let Some(padding) = self.padding {
+            config.padding = padding;
+        }
+        if let Some(window) = self.window {
+            config.window = window;
+        }
+        if let Some(beta) = self.kaiser_beta {
+            config.kaiser_beta = beta;
+        }
+        if let Some(sigma) = self.gaussian_sigma {
+            config.gaussian_sigma = sigma;
+        }
+        if let Some(num_bins) = self.num_bins {
+            config.num_bins = num_bins;
+        }
+        if let Some(min_freq) = self.min_freq {
+            config.min_freq = min_freq;
+        }
+        if let Some(max_freq) = self.max_freq {
+            config.max_freq = max_freq;
+        }
+        if let Some(db_min) = self.db_min {
+            config.db_min = db_min;
+        }
+        if let Some(db_max) = self.db_max {
+            config.db_max = db_max;
+        }
+        if let Some(freq) = self.boost_start_freq {
+            config.boost_start_freq = freq;
+        }
+        if let Some(boost) = self.boost_db_per_octave {
+            config.boost_db_per_octave = boost;
+        }
+        if self.phase {
+            config.use_phase_encoding = true;
+        }
+        if self.mag {
+            config.use_phase_encoding = false;
+        }
+        if let Some(scale) = self.scale {
+            config.frequency_scale = scale;
+        }
+        if self.log {
+            config.frequency_scale = FrequencyScale::Log;
+        }
+        if self.linear {
+            config.frequency_scale = FrequencyScale::Linear;
+        }
+        if let Some(format) = self.image_format {
+            config.image_format = format;
+        }
+        if let Some(tile_width) = self.tile_width {
+            config.tile_width = tile_width;
+        }
+        if let Some(iterations) = self.griffin_lim_iterations {
+            config.griffin_lim_iterations = iterations;
+        }
+        if let Some(algorithm) = self.phase_retrieval {
+            config.phase_retrieval = algorithm;
+        }
+        if let Some(init) = self.phase_init {
+            config.phase_init = init;
+        }
+        if let Some(momentum) = self.griffin_lim_momentum {
+            config.griffin_lim_momentum = momentum;
+        }
+        if let Some(lookahead) = self.rtisi_lookahead {
+            config.rtisi_lookahead = lookahead;
+        }
+        if let Some(mode) = self.channel_mode {
+            config.channel_mode = mode;
+        }
+        if self.stack_channels {
+            config.stack_channels = true;
+        }
+        if self.separate_channels {
+            config.stack_channels = false;
+        }
+        if let Some(level) = self.level {
+            config.level_mode = level;
+        }
+        if let Some(protection) = self.peak_protection {
+            config.peak_protection = protection;
+        }
+        if let Some(ceiling) = self.peak_ceiling_db {
+            config.peak_ceiling_db = ceiling;
+        }
+        if let Some(format) = self.format {
+            config.output_format = format;
+        }
+        if let Some(bit_depth) = self.bit_depth {
+            config.bit_depth = bit_depth;
+        }
+        if self.dither {
+            config.dither = true;
+        }
+        if self.no_dither {
+            config.dither = false;
+        }
+        if let Some(threads) = self.threads {
+            config.num_threads = threads;
+        }
+    }
+}
+
+/// Run a headless conversion, returning the process exit code
+pub fn run(command: Command) -> ExitCode {
+    let result = match &command {
+        Command::Encode(args) => convert(args, InputKind::Audio),
+        Command::Decode(args) => convert(args, InputKind::Image),
+        Command::Batch(args) => return batch(args),
+        Command::Preset(PresetCommand::List) => return list_presets(),
+        Command::Preset(PresetCommand::Save(args)) => save_preset(args),
+        Command::Project(ProjectCommand::New(args)) => new_project(args),
+        Command::Project(ProjectCommand::Render(args)) => render_project(args),
+    };
+
+    match result {
+        Ok(output_path) => {
+            println!("✓ Successfully exported to: {}", output_path.display());
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("✗ Error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn convert(args: &ConvertArgs, expected: InputKind) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let kind = InputKind::from_path(&args.input)?;
+    if kind != expected {
+        return Err(match expected {
+            InputKind::Audio => format!("{} is not an audio file", args.input.display()),
+            InputKind::Image => format!("{} is not a spectrogram image", args.input.display()),
+            InputKind::Project => format!("{} is not a project", args.input.display()),
+        }.into());
+    }
+
+    let mut config = load_config(args.config.as_deref(), args.preset.as_deref())?;
+    args.overrides.apply(&mut config);
+    config.validate()?;
+    config.print_info();
+
+    process_file(&args.input, args.output.as_deref(), &config, &Progress::new(&print_status, CancelToken::new()))
+}
+
+// Convert every input, printing each outcome as it comes and a summary at the end
+// Fails if any file did
+fn batch(args: &BatchArgs) -> ExitCode {
+    let prepared = load_config(args.config.as_deref(), args.preset.as_deref()).and_then(|mut config| {
+        args.overrides.apply(&mut config);
+        config.validate()?;
+        Ok((config, collect_inputs(&args.inputs)?))
+    });
+    let (config, inputs) = match prepared {
+        Ok(prepared) => prepared,
+        Err(e) => {
+            eprintln!("✗ Error: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    config.print_info();
+    println!("Converting {} files", inputs.len());
+
+    let report = |index: usize, status: JobStatus| {
+        let input = inputs[index].display();
+        match status {
+            JobStatus::Done { output_path } => println!("✓ [{}/{}] {} → {}", index + 1, inputs.len(), input, output_path.display()),
+            JobStatus::Failed { message } => eprintln!("✗ [{}/{}] {}: {}", index + 1, inputs.len(), input, message),
+            _ => {}
+        }
+    };
+    let statuses = run_batch(&inputs, &config, args.workers, &CancelToken::new(), &report);
+
+    let summary = BatchSummary::of(&statuses);
+    println!("{}", summary);
+    for (input, status) in inputs.iter().zip(&statuses) {
+        if let JobStatus::Failed { message } = status {
+            eprintln!("  ✗ {}: {}", input.display(), message);
+        }
+    }
+    if summary.failed > 0 { ExitCode::FAILURE } else { ExitCode::SUCCESS }
+}
+
+fn list_presets() -> ExitCode {
+    let presets = match Presets::user() {
+        Ok(presets) => presets,
+        Err(e) => {
+            eprintln!("✗ Error: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    println!("Presets (saved in {}):", presets.dir().display());
+    for name in presets.names() {
+        let origin = match (presets.is_saved(&name), BUILTIN_PRESETS.contains(&name.as_str())) {
+            (true, true) => "saved, replaces the built-in one",
+            (true, false) => "saved",
+            _ => "built in",
+        };
+        println!("  {} ({})", name, origin);
+    }
+    ExitCode::SUCCESS
+}
+
+fn save_preset(args: &SavePresetArgs) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let mut config = load_config(args.config.as_deref(), args.preset.as_deref())?;
+    args.overrides.apply(&mut config);
+    config.validate()?;
+
+    let presets = Presets::user()?;
+    if presets.is_saved(&args.name) && !args.force {
+        return Err(format!("A preset named \"{}\" is already saved (use --force to replace it)", args.name).into());
+    }
+    presets.save(&args.name, &config)
+}
+
+fn new_project(args: &NewProjectArgs) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let mut config = load_config(args.config.as_deref(), args.preset.as_deref())?;
+    args.overrides.apply(&mut config);
+    config.validate()?;
+    config.print_info();
+
+    let path = args.output.clone().unwrap_or_else(|| args.input.with_extension(PROJECT_EXTENSION));
+    if path.exists() {
+        return Err(format!("{} already exists", path.display()).into());
+    }
+    let progress = Progress::new(&print_status, CancelToken::new());
+    let mut project = match InputKind::from_path(&args.input)? {
+        InputKind::Audio => Project::from_audio(&args.input, &config, &progress)?,
+        InputKind::Image => Project::from_image(&args.input, &config)?,
+        InputKind::Project => return Err(format!("{} is already a project", args.input.display()).into()),
+    };
+    project.export.image_path = args.image.clone();
+    project.export.audio_path = args.audio.clone();
+    project.save(&path)?;
+    Ok(path)
+}
+
+fn render_project(args: &RenderProjectArgs) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let mut config = load_config(args.config.as_deref(), args.preset.as_deref())?;
+    args.overrides.apply(&mut config);
+    config.validate()?;
+
+    let mut project = Project::load(&args.project)?;
+    project.metadata.config.apply_decoder_settings(&config);
+    project.metadata.config.print_info();
+    if let Some(image) = &args.image {
+        project.export.image = true;
+        project.export.image_path = Some(image.clone());
+    }
+    if let Some(audio) = &args.audio {
+        project.export.audio = true;
+        project.export.audio_path = Some(audio.clone());
+    }
+    project.export.image &= !args.no_image;
+    project.export.audio &= !args.no_audio;
+
+    let mut written = project.render(&args.project, &Progress::new(&print_status, CancelToken::new()))?;
+    Ok(written.pop().expect("rendering writes at least one file"))
+}
+
+// Print each step once; per-frame and per-iteration updates are left to the log lines
+fn print_status(status: &str, fraction: Option<f32>) {
+    if fraction.is_none() {
+        eprintln!("{}", status);
+    }
+}
+
+// Load the given config file, or the default one if it exists, without writing anything
+fn load_config(path: Option<&Path>, preset: Option<&str>) -> Result<SpectrogramConfig, Box<dyn std::error::Error>> {
+    if let Some(name) = preset {
+        return Presets::user()?.load(name);
+    }
+    match path {
+        Some(path) => SpectrogramConfig::load_from(path),
+        None if SpectrogramConfig::default_path().exists() => {
+            SpectrogramConfig::load_from(SpectrogramConfig::default_path())
+        }
+        None => Ok(SpectrogramConfig::default()),
+    }
+}
@@ -1,6 +1,596 @@
 use serde::{Deserialize, Serialize};
+use std::fmt;
 use std::fs;
 use std::path::Path;
+use std::str::FromStr;
+
+/// How audio channels are turned into spectrograms
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChannelMode {
+    /// Average all channels into one mono spectrogram
+    Mono,
+    /// One spectrogram per channel, decoded back to the original channel count
+    Multichannel,
+    /// Stereo only: Mid (L+R)/2 and Side (L-R)/2 spectrograms, decoded back to L/R
+    MidSide,
+}
+
+impl ChannelMode {
+    pub const ALL: [ChannelMode; 3] = [ChannelMode::Mono, ChannelMode::Multichannel, ChannelMode::MidSide];
+
+    /// Turn per-channel samples into the signals that get encoded
+    pub fn split(&self, channels: Vec<Vec<f32>>) -> Result<Vec<Vec<f32>>, String> {
+        match self {
+            ChannelMode::Mono => Ok(vec![downmix(&channels)]),
+            ChannelMode::Multichannel => Ok(channels),
+            ChannelMode::MidSide => {
+                if channels.len() != 2 {
+                    return Err(format!("Mid/Side encoding needs stereo audio, got {} channels", channels.len()));
+                }
+                let mid = channels[0].iter().zip(&channels[1]).map(|(l, r)| (l + r) / 2.0).collect();
+                let side = channels[0].iter().zip(&channels[1]).map(|(l, r)| (l - r) / 2.0).collect();
+                Ok(vec![mid, side])
+            }
+        }
+    }
+
+    /// Inverse of `split`: turn decoded signals back into output channels
+    pub fn join(&self, mut signals: Vec<Vec<f32>>) -> Vec<Vec<f32>> {
+        match self {
+            ChannelMode::MidSide if signals.len() == 2 => {
+                let side = signals.pop().unwrap();
+                let mid = signals.pop().unwrap();
+                let left = mid.iter().zip(&side).map(|(m, s)| m + s).collect();
+                let right = mid.iter().zip(&side).map(|(m, s)| m - s).collect();
+                vec![left, right]
+            }
+            _ => signals,
+        }
+    }
+}
+
+impl fmt::Display for ChannelMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ChannelMode::Mono => "Mono (downmix)",
+            ChannelMode::Multichannel => "Multichannel",
+            ChannelMode::MidSide => "Mid/Side",
+        })
+    }
+}
+
+impl FromStr for ChannelMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().replace('-', "_").as_str() {
+            "mono" => Ok(ChannelMode::Mono),
+            "multichannel" => Ok(ChannelMode::Multichannel),
+            "mid_side" | "ms" => Ok(ChannelMode::MidSide),
+            _ => Err(format!("Unknown channel mode '{}' (expected mono, multichannel or mid-side)", s)),
+        }
+    }
+}
+
+/// Output level of decoded audio
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LevelMode {
+    /// Scale the loudest sample to 0.95 (about -0.45 dBFS)
+    Normalize,
+    /// Restore the level of the encoded audio from the reference magnitude stored in the image
+    Original,
+}
+
+impl LevelMode {
+    pub const ALL: [LevelMode; 2] = [LevelMode::Normalize, LevelMode::Original];
+}
+
+impl fmt::Display for LevelMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            LevelMode::Normalize => "Peak normalize",
+            LevelMode::Original => "Original level",
+        })
+    }
+}
+
+impl FromStr for LevelMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "normalize" => Ok(LevelMode::Normalize),
+            "original" => Ok(LevelMode::Original),
+            _ => Err(format!("Unknown level mode '{}' (expected normalize or original)", s)),
+        }
+    }
+}
+
+/// Protection against decoded audio exceeding the peak ceiling
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PeakProtection {
+    /// Leave levels alone - samples beyond full scale clip when written
+    None,
+    /// Lower the whole file just enough for its true peak to meet the ceiling
+    Scale,
+    /// Lookahead true-peak limiter: only the loud passages are turned down
+    Limit,
+}
+
+impl PeakProtection {
+    pub const ALL: [PeakProtection; 3] = [PeakProtection::None, PeakProtection::Scale, PeakProtection::Limit];
+}
+
+impl fmt::Display for PeakProtection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            PeakProtection::None => "None (clip)",
+            PeakProtection::Scale => "Scale to ceiling",
+            PeakProtection::Limit => "True-peak limiter",
+        })
+    }
+}
+
+impl FromStr for PeakProtection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "none" => Ok(PeakProtection::None),
+            "scale" => Ok(PeakProtection::Scale),
+            "limit" => Ok(PeakProtection::Limit),
+            _ => Err(format!("Unknown peak protection '{}' (expected none, scale or limit)", s)),
+        }
+    }
+}
+
+/// Frequency axis of the spectrogram image
+/// Image rows are evenly spaced on the chosen scale between `min_freq` and `max_freq`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FrequencyScale {
+    /// Evenly spaced Hz (technical analysis)
+    Linear,
+    /// Evenly spaced octaves (music, notes equally spaced)
+    Log,
+    /// Mel scale (HTK formula), common for speech and ML features
+    Mel,
+    /// Bark critical-band scale (Traunmüller)
+    Bark,
+    /// ERB-rate scale (Glasberg & Moore)
+    Erb,
+}
+
+impl FrequencyScale {
+    pub const ALL: [FrequencyScale; 5] = [
+        FrequencyScale::Linear, FrequencyScale::Log, FrequencyScale::Mel, FrequencyScale::Bark, FrequencyScale::Erb,
+    ];
+
+    /// Position of a frequency (Hz) on this scale, increasing with frequency
+    pub fn warp(&self, freq: f32) -> f32 {
+        match self {
+            FrequencyScale::Linear => freq,
+            FrequencyScale::Log => freq.max(1e-3).ln(),
+            FrequencyScale::Mel => 2595.0 * (1.0 + freq / 700.0).log10(),
+            FrequencyScale::Bark => 26.81 * freq / (1960.0 + freq) - 0.53,
+            FrequencyScale::Erb => 21.4 * (1.0 + 0.00437 * freq).log10(),
+        }
+    }
+
+    /// Frequency (Hz) at a position on this scale (inverse of `warp`)
+    pub fn unwarp(&self, value: f32) -> f32 {
+        match self {
+            FrequencyScale::Linear => value,
+            FrequencyScale::Log => value.exp(),
+            FrequencyScale::Mel => 700.0 * (10.0f32.powf(value / 2595.0) - 1.0),
+            FrequencyScale::Bark => 1960.0 * (value + 0.53) / (26.28 - value),
+            FrequencyScale::Erb => (10.0f32.powf(value / 21.4) - 1.0) / 0.00437,
+        }
+    }
+
+    /// Tag in encoded image filenames (`_LOG`, `_LIN`, ...)
+    pub fn suffix(&self) -> &'static str {
+        match self {
+            FrequencyScale::Linear => "LIN",
+            FrequencyScale::Log => "LOG",
+            FrequencyScale::Mel => "MEL",
+            FrequencyScale::Bark => "BARK",
+            FrequencyScale::Erb => "ERB",
+        }
+    }
+}
+
+impl fmt::Display for FrequencyScale {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            FrequencyScale::Linear => "Linear (technical)",
+            FrequencyScale::Log => "Logarithmic (musical)",
+            FrequencyScale::Mel => "Mel",
+            FrequencyScale::Bark => "Bark",
+            FrequencyScale::Erb => "ERB",
+        })
+    }
+}
+
+impl FromStr for FrequencyScale {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "linear" | "lin" => Ok(FrequencyScale::Linear),
+            "log" | "logarithmic" => Ok(FrequencyScale::Log),
+            "mel" => Ok(FrequencyScale::Mel),
+            "bark" => Ok(FrequencyScale::Bark),
+            "erb" => Ok(FrequencyScale::Erb),
+            _ => Err(format!("Unknown frequency scale '{}' (expected linear, log, mel, bark or erb)", s)),
+        }
+    }
+}
+
+/// Window applied to each STFT frame
+/// The synthesis window is derived from it so overlap-add reconstructs the signal at the hop size
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WindowFunction {
+    /// Periodic Hann, a good general-purpose default
+    Hann,
+    /// Periodic Hamming, narrower main lobe with higher far sidelobes
+    Hamming,
+    /// 4-term Blackman-Harris, very low sidelobes (about -92 dB)
+    BlackmanHarris,
+    /// Kaiser, sidelobe level set by `kaiser_beta`
+    Kaiser,
+    /// Gaussian, width set by `gaussian_sigma`
+    Gaussian,
+    /// No tapering (best frequency resolution, strong leakage)
+    Rectangular,
+}
+
+impl WindowFunction {
+    pub const ALL: [WindowFunction; 6] = [
+        WindowFunction::Hann, WindowFunction::Hamming, WindowFunction::BlackmanHarris,
+        WindowFunction::Kaiser, WindowFunction::Gaussian, WindowFunction::Rectangular,
+    ];
+}
+
+impl fmt::Display for WindowFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            WindowFunction::Hann => "Hann",
+            WindowFunction::Hamming => "Hamming",
+            WindowFunction::BlackmanHarris => "Blackman-Harris",
+            WindowFunction::Kaiser => "Kaiser",
+            WindowFunction::Gaussian => "Gaussian",
+            WindowFunction::Rectangular => "Rectangular",
+        })
+    }
+}
+
+impl FromStr for WindowFunction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().replace('-', "_").as_str() {
+            "hann" | "hanning" => Ok(WindowFunction::Hann),
+            "hamming" => Ok(WindowFunction::Hamming),
+            "blackman_harris" | "blackmanharris" => Ok(WindowFunction::BlackmanHarris),
+            "kaiser" => Ok(WindowFunction::Kaiser),
+            "gaussian" | "gauss" => Ok(WindowFunction::Gaussian),
+            "rectangular" | "rect" | "none" => Ok(WindowFunction::Rectangular),
+            _ => Err(format!("Unknown window '{}' (expected hann, hamming, blackman-harris, kaiser, gaussian or rectangular)", s)),
+        }
+    }
+}
+
+/// How the signal is extended beyond its ends so frames can be centered on every sample
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Padding {
+    /// No padding, frames start at the first sample (images from older versions)
+    /// The tail after the last full frame is dropped and the first half-window is attenuated
+    None,
+    /// Silence
+    Zero,
+    /// Mirror the signal around its first and last samples
+    Reflect,
+    /// Repeat the first and last samples
+    Edge,
+}
+
+impl Padding {
+    pub const ALL: [Padding; 4] = [Padding::None, Padding::Zero, Padding::Reflect, Padding::Edge];
+}
+
+impl fmt::Display for Padding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Padding::None => "None (legacy framing)",
+            Padding::Zero => "Zero",
+            Padding::Reflect => "Reflect",
+            Padding::Edge => "Edge",
+        })
+    }
+}
+
+impl FromStr for Padding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "none" => Ok(Padding::None),
+            "zero" | "zeros" | "constant" => Ok(Padding::Zero),
+            "reflect" => Ok(Padding::Reflect),
+            "edge" => Ok(Padding::Edge),
+            _ => Err(format!("Unknown padding '{}' (expected none, zero, reflect or edge)", s)),
+        }
+    }
+}
+
+/// Algorithm that estimates phases for magnitude-only (grayscale) images
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PhaseRetrieval {
+    /// Plain Griffin-Lim: alternate projections, converges slowly
+    GriffinLim,
+    /// Fast Griffin-Lim: Griffin-Lim with momentum (`griffin_lim_momentum`)
+    FastGriffinLim,
+    /// Real-time iterative spectrogram inversion with lookahead: frame by frame,
+    /// suited to streaming (`rtisi_lookahead` frames ahead)
+    RtisiLa,
+    /// Phase Gradient Heuristic Integration: non-iterative, phases integrated from
+    /// the magnitude's gradients
+    Pghi,
+}
+
+impl PhaseRetrieval {
+    pub const ALL: [PhaseRetrieval; 4] = [
+        PhaseRetrieval::GriffinLim, PhaseRetrieval::FastGriffinLim, PhaseRetrieval::RtisiLa, PhaseRetrieval::Pghi,
+    ];
+
+    /// Whether the algorithm runs `griffin_lim_iterations` iterations
+    pub fn is_iterative(&self) -> bool {
+        *self != PhaseRetrieval::Pghi
+    }
+}
+
+impl fmt::Display for PhaseRetrieval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            PhaseRetrieval::GriffinLim => "Griffin-Lim",
+            PhaseRetrieval::FastGriffinLim => "Fast Griffin-Lim",
+            PhaseRetrieval::RtisiLa => "RTISI-LA",
+            PhaseRetrieval::Pghi => "PGHI",
+        })
+    }
+}
+
+impl FromStr for PhaseRetrieval {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().replace('-', "_").as_str() {
+            "griffin_lim" | "gl" => Ok(PhaseRetrieval::GriffinLim),
+            "fast_griffin_lim" | "fgla" | "fgl" => Ok(PhaseRetrieval::FastGriffinLim),
+            "rtisi_la" | "rtisi" => Ok(PhaseRetrieval::RtisiLa),
+            "pghi" => Ok(PhaseRetrieval::Pghi),
+            _ => Err(format!("Unknown phase retrieval '{}' (expected griffin-lim, fast-griffin-lim, rtisi-la or pghi)", s)),
+        }
+    }
+}
+
+/// Starting phases for Griffin-Lim and Fast Griffin-Lim
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PhaseInit {
+    /// All phases zero
+    Zero,
+    /// Uniformly random phases (from a fixed seed, so results are repeatable)
+    Random,
+    /// Phases from Phase Gradient Heuristic Integration (fewer iterations needed)
+    Pghi,
+}
+
+impl PhaseInit {
+    pub const ALL: [PhaseInit; 3] = [PhaseInit::Zero, PhaseInit::Random, PhaseInit::Pghi];
+}
+
+impl fmt::Display for PhaseInit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            PhaseInit::Zero => "Zero",
+            PhaseInit::Random => "Random",
+            PhaseInit::Pghi => "PGHI",
+        })
+    }
+}
+
+impl FromStr for PhaseInit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "zero" => Ok(PhaseInit::Zero),
+            "random" => Ok(PhaseInit::Random),
+            "pghi" => Ok(PhaseInit::Pghi),
+            _ => Err(format!("Unknown phase init '{}' (expected zero, random or pghi)", s)),
+        }
+    }
+}
+
+/// File format and precision of encoded spectrogram images
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImageFormat {
+    /// 8 bits per channel PNG, editable in any image editor
+    Png,
+    /// 16 bits per channel PNG
+    Png16,
+    /// 16 bits per channel TIFF
+    Tiff16,
+    /// 32-bit float OpenEXR (lossless)
+    Exr,
+}
+
+impl ImageFormat {
+    pub const ALL: [ImageFormat; 4] = [ImageFormat::Png, ImageFormat::Png16, ImageFormat::Tiff16, ImageFormat::Exr];
+
+    /// File extension for this format
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ImageFormat::Png | ImageFormat::Png16 => "png",
+            ImageFormat::Tiff16 => "tif",
+            ImageFormat::Exr => "exr",
+        }
+    }
+}
+
+impl fmt::Display for ImageFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ImageFormat::Png => "PNG (8-bit)",
+            ImageFormat::Png16 => "PNG (16-bit)",
+            ImageFormat::Tiff16 => "TIFF (16-bit)",
+            ImageFormat::Exr => "OpenEXR (32-bit float)",
+        })
+    }
+}
+
+impl FromStr for ImageFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "png" | "png8" => Ok(ImageFormat::Png),
+            "png16" => Ok(ImageFormat::Png16),
+            "tiff" | "tiff16" | "tif" => Ok(ImageFormat::Tiff16),
+            "exr" => Ok(ImageFormat::Exr),
+            _ => Err(format!("Unknown image format '{}' (expected png, png16, tiff16 or exr)", s)),
+        }
+    }
+}
+
+/// File format of decoded audio
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputFormat {
+    Wav,
+    /// Lossless compression, integer bit depths only
+    Flac,
+}
+
+impl OutputFormat {
+    pub const ALL: [OutputFormat; 2] = [OutputFormat::Wav, OutputFormat::Flac];
+
+    /// File extension for this format
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Wav => "wav",
+            OutputFormat::Flac => "flac",
+        }
+    }
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            OutputFormat::Wav => "WAV",
+            OutputFormat::Flac => "FLAC",
+        })
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "wav" => Ok(OutputFormat::Wav),
+            "flac" => Ok(OutputFormat::Flac),
+            _ => Err(format!("Unknown output format '{}' (expected wav or flac)", s)),
+        }
+    }
+}
+
+/// Sample format of decoded audio
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BitDepth {
+    Int16,
+    Int24,
+    /// 32-bit float, keeps samples beyond full scale (WAV only)
+    Float32,
+}
+
+impl BitDepth {
+    pub const ALL: [BitDepth; 3] = [BitDepth::Int16, BitDepth::Int24, BitDepth::Float32];
+
+    /// Bits per sample
+    pub fn bits(&self) -> u16 {
+        match self {
+            BitDepth::Int16 => 16,
+            BitDepth::Int24 => 24,
+            BitDepth::Float32 => 32,
+        }
+    }
+}
+
+impl fmt::Display for BitDepth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            BitDepth::Int16 => "16-bit",
+            BitDepth::Int24 => "24-bit",
+            BitDepth::Float32 => "32-bit float",
+        })
+    }
+}
+
+impl FromStr for BitDepth {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "16" | "int16" => Ok(BitDepth::Int16),
+            "24" | "int24" => Ok(BitDepth::Int24),
+            "32f" | "32" | "float" | "float32" => Ok(BitDepth::Float32),
+            _ => Err(format!("Unknown bit depth '{}' (expected 16, 24 or 32f)", s)),
+        }
+    }
+}
+
+/// Average channels into a single mono signal
+pub fn downmix(channels: &[Vec<f32>]) -> Vec<f32> {
+    let len = channels.iter().map(|c| c.len()).min().unwrap_or(0);
+    (0..len)
+        .map(|i| channels.iter().map(|c| c[i]).sum::<f32>() / channels.len() as f32)
+        .collect()
+}
+
+// Tallest image (per channel) num_bins may ask for
+const MAX_NUM_BINS: usize = 16384;
+
+// Longest RTISI-LA lookahead (frames)
+const MAX_RTISI_LOOKAHEAD: usize = 32;
+
+// Setting named by a failed validation rule, so validate_and_fix knows what to reset
+#[derive(Debug, Clone, Copy)]
+enum Setting {
+    FftSize,
+    HopSize,
+    KaiserBeta,
+    GaussianSigma,
+    NumBins,
+    MinFreq,
+    MaxFreq,
+    DbRange,
+    TileWidth,
+    GriffinLimMomentum,
+    RtisiLookahead,
+    PeakCeiling,
+    BitDepth,
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SpectrogramConfig {
@@ -14,10 +604,37 @@ pub struct SpectrogramConfig {
     #[serde(default = "default_hop_size")]
     pub hop_size: usize,
     
-    /// Minimum frequency for logarithmic scale (Hz)
+    /// Window function for analysis (the synthesis window is derived from it)
+    #[serde(default = "default_window")]
+    pub window: WindowFunction,
+    
+    /// Kaiser window shape: 0 = rectangular, higher = lower sidelobes and a wider main lobe
+    #[serde(default = "default_kaiser_beta")]
+    pub kaiser_beta: f32,
+    
+    /// Gaussian window standard deviation, relative to half the window length
+    #[serde(default = "default_gaussian_sigma")]
+    pub gaussian_sigma: f32,
+    
+    /// Padding that centers the first frame on the first sample and lets the last frames
+    /// cover the end, so decoding gives back exactly the input length
+    #[serde(default = "default_padding")]
+    pub padding: Padding,
+    
+    /// Number of frequency bins (image height), 0 = one row per FFT bin (fft_size / 2 + 1)
+    /// Fewer rows make compact images; rows are resampled to and from the FFT bins
+    #[serde(default = "default_num_bins")]
+    pub num_bins: usize,
+    
+    /// Lowest frequency in the image (Hz)
+    /// Must be above 0 for the log scale; 0 with a linear scale keeps every FFT bin
     #[serde(default = "default_min_freq")]
     pub min_freq: f32,
     
+    /// Highest frequency in the image (Hz), 0 = Nyquist
+    #[serde(default = "default_max_freq")]
+    pub max_freq: f32,
+    
     /// Minimum dB level for visualization
     #[serde(default = "default_db_min")]
     pub db_min: f32,
@@ -40,44 +657,161 @@ pub struct SpectrogramConfig {
     #[serde(default = "default_use_phase_encoding")]
     pub use_phase_encoding: bool,
     
-    /// Whether to use logarithmic frequency scale (musical/note-based)
-    /// true = logarithmic scale (better for music, notes equally spaced)
-    /// false = linear scale (better for technical analysis)
-    #[serde(default = "default_use_log_scale")]
-    pub use_log_scale: bool,
+    /// Frequency axis of the image
+    /// "log" = musical (notes equally spaced), "linear" = technical,
+    /// "mel" / "bark" / "erb" = perceptual scales for speech and ML
+    #[serde(default = "default_frequency_scale")]
+    pub frequency_scale: FrequencyScale,
+    
+    /// Image file format and precision
+    /// "png" = 8-bit PNG (easiest to edit), "png16" / "tiff16" = 16 bits per channel,
+    /// "exr" = 32-bit float OpenEXR (lossless magnitude and phase)
+    #[serde(default = "default_image_format")]
+    pub image_format: ImageFormat,
+    
+    /// Frames (pixels) per image for long files, 0 = one image for the whole file
+    /// Tiled files are encoded and decoded a tile at a time, with bounded memory, into
+    /// name_part001.png, name_part002.png, ...
+    #[serde(default = "default_tile_width")]
+    pub tile_width: usize,
     
     /// Number of Griffin-Lim iterations for magnitude-only reconstruction
     /// Only used when use_phase_encoding = false
     /// More iterations = better quality but slower (typical: 10-50)
+    /// For RTISI-LA this is the number of iterations per frame
     #[serde(default = "default_griffin_lim_iterations")]
     pub griffin_lim_iterations: usize,
+    
+    /// Phase reconstruction algorithm for magnitude-only images
+    /// "fast_griffin_lim" (default), "griffin_lim", "rtisi_la" (streaming) or "pghi" (non-iterative)
+    #[serde(default = "default_phase_retrieval")]
+    pub phase_retrieval: PhaseRetrieval,
+    
+    /// Starting phases for Griffin-Lim and Fast Griffin-Lim: "pghi", "random" or "zero"
+    #[serde(default = "default_phase_init")]
+    pub phase_init: PhaseInit,
+    
+    /// Fast Griffin-Lim momentum (0 = plain Griffin-Lim, typical 0.99)
+    #[serde(default = "default_griffin_lim_momentum")]
+    pub griffin_lim_momentum: f32,
+    
+    /// Frames RTISI-LA looks ahead before committing a frame
+    #[serde(default = "default_rtisi_lookahead")]
+    pub rtisi_lookahead: usize,
+    
+    /// How channels are encoded
+    /// "mono" = average to mono, "multichannel" = one spectrogram per channel,
+    /// "mid_side" = Mid and Side spectrograms (stereo only)
+    #[serde(default = "default_channel_mode")]
+    pub channel_mode: ChannelMode,
+    
+    /// Where the spectrograms of multiple channels go
+    /// true = stacked as bands in one image (first channel at the top)
+    /// false = one image per channel (_CH1, _CH2, ...)
+    #[serde(default = "default_stack_channels")]
+    pub stack_channels: bool,
+    
+    /// Level of decoded audio
+    /// "normalize" = loudest sample at 0.95 (every file comes back at full scale)
+    /// "original" = restore the encoded file's absolute level (needs embedded metadata)
+    #[serde(default = "default_level_mode")]
+    pub level_mode: LevelMode,
+    
+    /// What to do when decoded audio would exceed peak_ceiling_db
+    /// "none" = clip, "scale" = turn the whole file down, "limit" = true-peak limiter
+    #[serde(default = "default_peak_protection")]
+    pub peak_protection: PeakProtection,
+    
+    /// Ceiling for peak protection (dBTP, true peak)
+    #[serde(default = "default_peak_ceiling_db")]
+    pub peak_ceiling_db: f32,
+    
+    /// File format of decoded audio: "wav" or "flac"
+    #[serde(default = "default_output_format")]
+    pub output_format: OutputFormat,
+    
+    /// Sample format of decoded audio: "int16", "int24" or "float32" (WAV only)
+    #[serde(default = "default_bit_depth")]
+    pub bit_depth: BitDepth,
+    
+    /// Add TPDF dither when writing 16-bit audio
+    /// Turns quantization distortion of quiet passages into a constant low noise floor
+    #[serde(default = "default_dither")]
+    pub dither: bool,
+    
+    /// Worker threads for the STFT, inverse STFT and phase retrieval (0 = one per CPU core)
+    /// Results are identical for every thread count
+    #[serde(default = "default_num_threads")]
+    pub num_threads: usize,
 }
 
 // Default values - now with higher time resolution
 fn default_fft_size() -> usize { 4096 }
 fn default_hop_size() -> usize { 128 }  // Changed from 512 to 128 for 4x time resolution
+fn default_window() -> WindowFunction { WindowFunction::Hann }
+fn default_kaiser_beta() -> f32 { 8.6 }  // Sidelobes similar to Blackman-Harris
+fn default_gaussian_sigma() -> f32 { 0.4 }
+fn default_padding() -> Padding { Padding::Reflect }
+fn default_num_bins() -> usize { 0 }  // One row per FFT bin
 fn default_min_freq() -> f32 { 20.0 }
+fn default_max_freq() -> f32 { 0.0 }
 fn default_db_min() -> f32 { -80.0 }
 fn default_db_max() -> f32 { 0.0 }
 fn default_boost_start_freq() -> f32 { 1000.0 }
 fn default_boost_db_per_octave() -> f32 { 6.0 }
 fn default_use_phase_encoding() -> bool { true }
-fn default_use_log_scale() -> bool { true }  // Default to log scale for music
+fn default_frequency_scale() -> FrequencyScale { FrequencyScale::Log }  // Default to log scale for music
+fn default_image_format() -> ImageFormat { ImageFormat::Png }
+fn default_tile_width() -> usize { 0 }  // One image
 fn default_griffin_lim_iterations() -> usize { 30 }  // 30 iterations is a good balance
+fn default_phase_retrieval() -> PhaseRetrieval { PhaseRetrieval::FastGriffinLim }
+fn default_phase_init() -> PhaseInit { PhaseInit::Pghi }
+fn default_griffin_lim_momentum() -> f32 { 0.99 }
+fn default_rtisi_lookahead() -> usize { 3 }
+fn default_channel_mode() -> ChannelMode { ChannelMode::Mono }
+fn default_stack_channels() -> bool { true }
+fn default_level_mode() -> LevelMode { LevelMode::Normalize }
+fn default_peak_protection() -> PeakProtection { PeakProtection::None }
+fn default_peak_ceiling_db() -> f32 { -1.0 }
+fn default_output_format() -> OutputFormat { OutputFormat::Wav }
+fn default_bit_depth() -> BitDepth { BitDepth::Int16 }
+fn default_dither() -> bool { false }
+fn default_num_threads() -> usize { 0 }  // One per CPU core
 
 impl Default for SpectrogramConfig {
     fn default() -> Self {
         Self {
             fft_size: default_fft_size(),
             hop_size: default_hop_size(),
+            window: default_window(),
+            kaiser_beta: default_kaiser_beta(),
+            gaussian_sigma: default_gaussian_sigma(),
+            padding: default_padding(),
+            num_bins: default_num_bins(),
             min_freq: default_min_freq(),
+            max_freq: default_max_freq(),
             db_min: default_db_min(),
             db_max: default_db_max(),
             boost_start_freq: default_boost_start_freq(),
             boost_db_per_octave: default_boost_db_per_octave(),
             use_phase_encoding: default_use_phase_encoding(),
-            use_log_scale: default_use_log_scale(),
+            frequency_scale: default_frequency_scale(),
+            image_format: default_image_format(),
+            tile_width: default_tile_width(),
             griffin_lim_iterations: default_griffin_lim_iterations(),
+            phase_retrieval: default_phase_retrieval(),
+            phase_init: default_phase_init(),
+            griffin_lim_momentum: default_griffin_lim_momentum(),
+            rtisi_lookahead: default_rtisi_lookahead(),
+            channel_mode: default_channel_mode(),
+            stack_channels: default_stack_channels(),
+            level_mode: default_level_mode(),
+            peak_protection: default_peak_protection(),
+            peak_ceiling_db: default_peak_ceiling_db(),
+            output_format: default_output_format(),
+            bit_depth: default_bit_depth(),
+            dither: default_dither(),
+            num_threads: default_num_threads(),
         }
     }
 }
@@ -92,7 +826,7 @@ impl SpectrogramConfig {
         if config_path.exists() {
             // Try to load existing config
             let contents = fs::read_to_string(config_path)?;
-            let mut config: Self = toml::from_str(&contents)
+            let mut config = Self::from_toml(&contents)
                 .unwrap_or_else(|e| {
                     eprintln!("Warning: Error parsing config file: {}. Using defaults.", e);
                     Self::default()
@@ -114,6 +848,41 @@ impl SpectrogramConfig {
         }
     }
     
+    /// Load configuration from a specific file without creating or rewriting it
+    pub fn load_from(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let contents = fs::read_to_string(path)?;
+        let config = Self::from_toml(&contents)
+            .map_err(|e| format!("Error parsing config file {}: {}", path.display(), e))?;
+        Ok(config)
+    }
+    
+    /// Parse a configuration from TOML, upgrading settings written by older versions
+    pub fn from_toml(contents: &str) -> Result<Self, toml::de::Error> {
+        let mut table: toml::Table = toml::from_str(contents)?;
+        Self::upgrade_table(&mut table);
+        toml::Value::Table(table).try_into()
+    }
+    
+    /// Convert settings written by older versions in a parsed configuration table
+    pub(crate) fn upgrade_table(table: &mut toml::Table) {
+        // use_log_scale = true/false was replaced by frequency_scale; the old linear scale
+        // always started at 0 Hz
+        if let Some(toml::Value::Boolean(use_log_scale)) = table.remove("use_log_scale") {
+            if !table.contains_key("frequency_scale") {
+                let scale = if use_log_scale { "log" } else { "linear" };
+                table.insert("frequency_scale".to_string(), scale.into());
+                if !use_log_scale {
+                    table.insert("min_freq".to_string(), 0.0.into());
+                }
+            }
+        }
+    }
+    
+    /// Path of the default configuration file in the working directory
+    pub fn default_path() -> &'static Path {
+        Path::new(Self::CONFIG_FILE)
+    }
+    
     /// Save configuration to file
     pub fn save(&self) -> Result<(), Box<dyn std::error::Error>> {
         let toml_string = toml::to_string_pretty(self)?;
@@ -121,45 +890,183 @@ impl SpectrogramConfig {
         Ok(())
     }
     
-    /// Validate and fix any invalid values
-    fn validate_and_fix(&mut self) {
-        // Ensure FFT size is a power of 2 and reasonable
+    /// Check that all values are usable, describing the first invalid one
+    pub fn validate(&self) -> Result<(), String> {
+        self.first_invalid().map_or(Ok(()), |(_, message)| Err(message))
+    }
+    
+    /// First setting that breaks a rule, with a description of the rule
+    fn first_invalid(&self) -> Option<(Setting, String)> {
         if self.fft_size < 256 || self.fft_size > 16384 || !self.fft_size.is_power_of_two() {
-            eprintln!("Warning: Invalid fft_size {}, using default", self.fft_size);
-            self.fft_size = default_fft_size();
+            return Some((Setting::FftSize, format!("fft_size must be a power of 2 between 256 and 16384, got {}", self.fft_size)));
         }
-        
-        // Ensure hop size is reasonable (not larger than FFT size)
         if self.hop_size == 0 || self.hop_size > self.fft_size {
-            eprintln!("Warning: Invalid hop_size {}, using default", self.hop_size);
-            self.hop_size = default_hop_size();
+            return Some((Setting::HopSize, format!("hop_size must be between 1 and fft_size ({}), got {}", self.fft_size, self.hop_size)));
         }
-        
-        // Ensure frequency range is valid
-        if self.min_freq <= 0.0 || self.min_freq >= 20000.0 {
-            eprintln!("Warning: Invalid min_freq {}, using default", self.min_freq);
-            self.min_freq = default_min_freq();
+        if !(0.0..=50.0).contains(&self.kaiser_beta) {
+            return Some((Setting::KaiserBeta, format!("kaiser_beta must be between 0 and 50, got {}", self.kaiser_beta)));
+        }
+        if self.gaussian_sigma <= 0.0 || self.gaussian_sigma > 1.0 {
+            return Some((Setting::GaussianSigma, format!("gaussian_sigma must be above 0 and at most 1, got {}", self.gaussian_sigma)));
+        }
+        if self.num_bins == 1 || self.num_bins > MAX_NUM_BINS {
+            return Some((Setting::NumBins, format!("num_bins must be 0 (FFT resolution) or between 2 and {}, got {}", MAX_NUM_BINS, self.num_bins)));
+        }
+        if !self.min_freq_valid() {
+            return Some((Setting::MinFreq, format!("min_freq must be between 0 and 20000 Hz (above 0 for the log scale), got {}", self.min_freq)));
+        }
+        if self.max_freq != 0.0 && self.max_freq <= self.min_freq {
+            return Some((Setting::MaxFreq, format!("max_freq must be 0 (Nyquist) or above min_freq ({}), got {}", self.min_freq, self.max_freq)));
         }
-        
-        // Ensure dB range is valid
         if self.db_min >= self.db_max {
-            eprintln!("Warning: Invalid dB range, using defaults");
-            self.db_min = default_db_min();
-            self.db_max = default_db_max();
+            return Some((Setting::DbRange, format!("db_min ({}) must be lower than db_max ({})", self.db_min, self.db_max)));
+        }
+        if !self.tile_width_valid() {
+            return Some((Setting::TileWidth, format!("tile_width must be 0 (one image) or at least fft_size / hop_size ({}) frames, got {}",
+                                                     self.frames_per_window(), self.tile_width)));
+        }
+        if !(0.0..=1.0).contains(&self.griffin_lim_momentum) {
+            return Some((Setting::GriffinLimMomentum, format!("griffin_lim_momentum must be between 0 and 1, got {}", self.griffin_lim_momentum)));
+        }
+        if self.rtisi_lookahead > MAX_RTISI_LOOKAHEAD {
+            return Some((Setting::RtisiLookahead, format!("rtisi_lookahead must be at most {} frames, got {}", MAX_RTISI_LOOKAHEAD, self.rtisi_lookahead)));
+        }
+        if self.peak_ceiling_db > 0.0 {
+            return Some((Setting::PeakCeiling, format!("peak_ceiling_db must be at most 0 dBTP, got {}", self.peak_ceiling_db)));
+        }
+        if self.output_format == OutputFormat::Flac && self.bit_depth == BitDepth::Float32 {
+            return Some((Setting::BitDepth, "FLAC output needs an integer bit_depth (int16 or int24)".to_string()));
+        }
+        None
+    }
+    
+    /// Take the reconstruction settings (which belong to the decoder, not the encoder) from `decoder`
+    pub fn apply_decoder_settings(&mut self, decoder: &SpectrogramConfig) {
+        self.griffin_lim_iterations = decoder.griffin_lim_iterations;
+        self.phase_retrieval = decoder.phase_retrieval;
+        self.phase_init = decoder.phase_init;
+        self.griffin_lim_momentum = decoder.griffin_lim_momentum;
+        self.rtisi_lookahead = decoder.rtisi_lookahead;
+        self.level_mode = decoder.level_mode;
+        self.peak_protection = decoder.peak_protection;
+        self.peak_ceiling_db = decoder.peak_ceiling_db;
+        self.output_format = decoder.output_format;
+        self.bit_depth = decoder.bit_depth;
+        self.dither = decoder.dither;
+        self.num_threads = decoder.num_threads;
+    }
+    
+    /// Window function with its shape parameter, if it has one
+    pub fn window_description(&self) -> String {
+        match self.window {
+            WindowFunction::Kaiser => format!("Kaiser (beta {})", self.kaiser_beta),
+            WindowFunction::Gaussian => format!("Gaussian (sigma {})", self.gaussian_sigma),
+            window => window.to_string(),
         }
     }
     
+    /// Phase retrieval algorithm with its settings
+    pub fn phase_retrieval_description(&self) -> String {
+        match self.phase_retrieval {
+            PhaseRetrieval::GriffinLim => format!("Griffin-Lim ({} iterations, {} initial phase)", self.griffin_lim_iterations, self.phase_init),
+            PhaseRetrieval::FastGriffinLim => format!("Fast Griffin-Lim ({} iterations, momentum {}, {} initial phase)",
+                                                      self.griffin_lim_iterations, self.griffin_lim_momentum, self.phase_init),
+            PhaseRetrieval::RtisiLa => format!("RTISI-LA ({} iterations per frame, {} frames lookahead)", self.griffin_lim_iterations, self.rtisi_lookahead),
+            PhaseRetrieval::Pghi => "PGHI (non-iterative)".to_string(),
+        }
+    }
+    
+    /// Number of STFT frames (image width) for a signal of `num_samples` samples
+    pub fn num_frames(&self, num_samples: usize) -> usize {
+        match self.padding {
+            Padding::None => num_samples.saturating_sub(self.fft_size) / self.hop_size + 1,
+            // Frame centers from the first sample to at or past the last one
+            _ => num_samples.div_ceil(self.hop_size) + 1,
+        }
+    }
+    
+    /// Number of frequency bins in the image (image height per channel)
+    pub fn image_height(&self) -> usize {
+        if self.num_bins > 0 { self.num_bins } else { self.fft_size / 2 + 1 }
+    }
+    
+    /// Lowest and highest frequency in the image for audio at `sample_rate`
+    pub fn frequency_range(&self, sample_rate: u32) -> (f32, f32) {
+        let nyquist = sample_rate as f32 / 2.0;
+        let max_freq = if self.max_freq > 0.0 { self.max_freq.min(nyquist) } else { nyquist };
+        (self.min_freq.min(max_freq), max_freq)
+    }
+    
+    /// Number of frames overlapping each sample (fft_size / hop_size, rounded up)
+    pub fn frames_per_window(&self) -> usize {
+        self.fft_size.div_ceil(self.hop_size.max(1))
+    }
+    
+    fn tile_width_valid(&self) -> bool {
+        self.tile_width == 0 || self.tile_width >= self.frames_per_window()
+    }
+    
+    fn min_freq_valid(&self) -> bool {
+        let lowest_allowed = self.frequency_scale != FrequencyScale::Log || self.min_freq > 0.0;
+        self.min_freq >= 0.0 && self.min_freq < 20000.0 && lowest_allowed
+    }
+    
+    /// Validate and fix any invalid values
+    fn validate_and_fix(&mut self) {
+        // Reset each broken setting in turn; every reset value passes its own rule
+        while let Some((setting, message)) = self.first_invalid() {
+            eprintln!("Warning: {}, using the default", message);
+            match setting {
+                Setting::FftSize => self.fft_size = default_fft_size(),
+                Setting::HopSize => self.hop_size = default_hop_size(),
+                Setting::KaiserBeta => self.kaiser_beta = default_kaiser_beta(),
+                Setting::GaussianSigma => self.gaussian_sigma = default_gaussian_sigma(),
+                Setting::NumBins => self.num_bins = default_num_bins(),
+                Setting::MinFreq => self.min_freq = default_min_freq(),
+                Setting::MaxFreq => self.max_freq = default_max_freq(),
+                Setting::DbRange => {
+                    self.db_min = default_db_min();
+                    self.db_max = default_db_max();
+                }
+                Setting::TileWidth => self.tile_width = default_tile_width(),
+                Setting::GriffinLimMomentum => self.griffin_lim_momentum = default_griffin_lim_momentum(),
+                Setting::RtisiLookahead => self.rtisi_lookahead = default_rtisi_lookahead(),
+                Setting::PeakCeiling => self.peak_ceiling_db = default_peak_ceiling_db(),
+                // FLAC has no float samples
+                Setting::BitDepth => self.bit_depth = BitDepth::Int24,
+            }
+        }
+        debug_assert!(self.validate().is_ok());
+    }
+    
     /// Print current configuration
     pub fn print_info(&self) {
         println!("\n=== Spectrogram Configuration ===");
         println!("FFT Size: {} samples", self.fft_size);
         println!("Hop Size: {} samples", self.hop_size);
         println!("Overlap: {:.1}%", (1.0 - self.hop_size as f32 / self.fft_size as f32) * 100.0);
-        println!("Frequency range (log scale): {:.0} Hz - Nyquist", self.min_freq);
+        println!("Window: {}", self.window_description());
+        println!("Padding: {}", self.padding);
+        println!("Image height: {} bins{}", self.image_height(), if self.num_bins == 0 { " (FFT resolution)" } else { "" });
+        println!("Frequency range: {:.0} Hz - {}", self.min_freq,
+                 if self.max_freq > 0.0 { format!("{:.0} Hz", self.max_freq) } else { "Nyquist".to_string() });
         println!("Dynamic range: {} to {} dB", self.db_min, self.db_max);
         println!("HF Boost: {} dB/octave above {} Hz", self.boost_db_per_octave, self.boost_start_freq);
         println!("Phase Encoding: {}", if self.use_phase_encoding { "Enabled (color)" } else { "Disabled (grayscale)" });
-        println!("Frequency Scale: {}", if self.use_log_scale { "Logarithmic (musical)" } else { "Linear (technical)" });
+        if !self.use_phase_encoding {
+            println!("Phase Retrieval: {}", self.phase_retrieval_description());
+        }
+        println!("Frequency Scale: {}", self.frequency_scale);
+        println!("Image Format: {}", self.image_format);
+        if self.tile_width > 0 {
+            println!("Tiles: {} frames per image", self.tile_width);
+        }
+        println!("Output Level: {}, peak protection: {} (ceiling {} dBTP)", self.level_mode, self.peak_protection, self.peak_ceiling_db);
+        println!("Output Format: {} {}{}", self.output_format, self.bit_depth,
+                 if self.dither && self.bit_depth == BitDepth::Int16 { ", TPDF dither" } else { "" });
+        println!("Channels: {}{}", self.channel_mode,
+                 if self.channel_mode == ChannelMode::Mono { "" } else if self.stack_channels { ", stacked in one image" } else { ", one image per channel" });
+        println!("Threads: {}", if self.num_threads == 0 { "one per CPU core".to_string() } else { self.num_threads.to_string() });
         println!("=================================\n");
     }
 }
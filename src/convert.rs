@@ -0,0 +1,147 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use crate::audio_io::{probe_audio, AudioFormat};
+use crate::audio_to_image::{audio_to_spectrogram, channel_image_path, encoded_image_path, MAX_IMAGE_WIDTH};
+use crate::config::{ChannelMode, SpectrogramConfig};
+use crate::image_to_audio::spectrogram_to_audio;
+use crate::progress::Progress;
+use crate::project::{is_project, Project};
+use crate::streaming::{is_tile_manifest, manifest_path};
+
+/// What a file is converted from, decided by its extension or contents
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputKind {
+    /// Audio file, encoded to a spectrogram image
+    Audio,
+    /// Spectrogram image (or tile manifest or folder of tiles), decoded back to audio
+    Image,
+    /// Project folder (or its project.toml), rendered to the image and audio of its export settings
+    Project,
+}
+
+impl InputKind {
+    /// Detect the input kind from a file extension, or from the file's magic bytes when the
+    /// extension is missing or unknown
+    pub fn from_path(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        if is_project(path) {
+            return Ok(InputKind::Project);
+        }
+        // Tiled image sequences are decoded from their manifest or folder
+        if path.is_dir() || is_tile_manifest(path) {
+            return Ok(InputKind::Image);
+        }
+
+        let extension = path.extension()
+            .and_then(|s| s.to_str())
+            .map(|s| s.to_lowercase())
+            .unwrap_or_default();
+
+        match extension.as_str() {
+            "png" | "jpg" | "jpeg" | "tif" | "tiff" | "exr" => Ok(InputKind::Image),
+            ext if AudioFormat::EXTENSIONS.contains(&ext) => Ok(InputKind::Audio),
+            _ if is_image_file(path) => Ok(InputKind::Image),
+            _ if AudioFormat::detect(path).is_some() => Ok(InputKind::Audio),
+            _ => Err("Unsupported file format. Use WAV, FLAC, MP3, OGG or AIFF for audio, or PNG/JPG/TIFF/EXR images (or a .tiles.toml tile manifest, or a project folder).".into()),
+        }
+    }
+}
+
+// Check for a PNG, JPEG, TIFF or OpenEXR signature
+fn is_image_file(path: &Path) -> bool {
+    let mut header = [0u8; 8];
+    let read = File::open(path).and_then(|mut f| f.read(&mut header)).unwrap_or(0);
+    let signatures: [&[u8]; 5] = [b"\x89PNG\r\n\x1a\n", &[0xFF, 0xD8, 0xFF], b"II*\0", b"MM\0*", &[0x76, 0x2f, 0x31, 0x01]];
+    signatures.iter().any(|signature| header[..read].starts_with(signature))
+}
+
+/// Default output path for an input file, plus the estimated image width for audio inputs
+pub fn get_output_info(
+    path: &Path,
+    config: &SpectrogramConfig,
+) -> Result<(PathBuf, Option<usize>), Box<dyn std::error::Error>> {
+    match InputKind::from_path(path)? {
+        InputKind::Audio => {
+            // Read the sample rate and length from the header to estimate the width
+            let info = probe_audio(path)?;
+
+            // Some compressed streams (e.g. MP3 without a Xing header) do not state their length
+            let est_width = info.num_samples
+                .map(|samples| config.num_frames(samples));
+
+            let output_path = encoded_image_path(&path.with_extension("png"), info.sample_rate, config);
+            // Long files are split into tiles listed in a manifest
+            let tile_width = if config.tile_width > 0 { config.tile_width } else { MAX_IMAGE_WIDTH };
+            let tiled = match est_width {
+                Some(width) => width > tile_width,
+                None => config.tile_width > 0,
+            };
+            if tiled {
+                return Ok((manifest_path(&output_path), est_width));
+            }
+            let separate_images = config.channel_mode != ChannelMode::Mono && info.channels > 1 && !config.stack_channels;
+            if separate_images {
+                Ok((channel_image_path(&output_path, 0), est_width))
+            } else {
+                Ok((output_path, est_width))
+            }
+        }
+        InputKind::Image => {
+            Ok((decoded_audio_path(path, config), None))
+        }
+        InputKind::Project => {
+            let (image_path, audio_path) = Project::load(path)?.export_paths(path);
+            let output_path = audio_path.or(image_path).ok_or("The project's export settings render nothing")?;
+            Ok((output_path, None))
+        }
+    }
+}
+
+// Default audio path for an image: the same name with the output format's extension
+// (name.tiles.toml manifests give name.wav)
+fn decoded_audio_path(path: &Path, config: &SpectrogramConfig) -> PathBuf {
+    let path = if is_tile_manifest(path) { path.with_extension("") } else { path.to_path_buf() };
+    path.with_extension(config.output_format.extension())
+}
+
+/// Convert a file in whichever direction its extension calls for
+/// `output_path` defaults to the input path with the other extension; encoded images always get
+/// the `_SR..._LOG/_LIN_PHASE/_MAG` suffix appended. `progress` receives human-readable status
+/// lines with the completed fraction of each step, and can cancel the conversion (the error is
+/// then `Cancelled`). Projects are rendered as their export settings say, with `output_path` as
+/// the audio path. Returns the path actually written (the audio of a project, if rendered).
+pub fn process_file(
+    path: &Path,
+    output_path: Option<&Path>,
+    config: &SpectrogramConfig,
+    progress: &Progress,
+) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    match InputKind::from_path(path)? {
+        InputKind::Audio => {
+            let output_path = output_path.map_or_else(|| path.with_extension("png"), Path::to_path_buf);
+
+            let result = audio_to_spectrogram(path, &output_path, config, progress)?;
+
+            Ok(result)
+        }
+        InputKind::Image => {
+            let output_path = output_path.map_or_else(|| decoded_audio_path(path, config), Path::to_path_buf);
+
+            spectrogram_to_audio(path, &output_path, config, progress)?;
+
+            Ok(output_path)
+        }
+        InputKind::Project => {
+            // Rendered with the project's own settings, apart from the decoding ones
+            let mut project = Project::load(path)?;
+            project.metadata.config.apply_decoder_settings(config);
+            if let Some(output_path) = output_path {
+                project.export.audio = true;
+                project.export.audio_path = Some(output_path.to_path_buf());
+            }
+            let written = project.render(path, progress)?;
+            let (_, audio_path) = project.export_paths(path);
+            Ok(audio_path.unwrap_or_else(|| written[0].clone()))
+        }
+    }
+}
@@ -0,0 +1,466 @@
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+use crate::spectrogram::Spectrogram;
+
+/// Cells an edit covers, in spectrogram coordinates: `[frame, bin]` points where cell (frame, bin)
+/// is centered on integer coordinates and bin 0 is the lowest frequency
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "shape", rename_all = "snake_case")]
+pub enum Region {
+    /// Round brush dabs along a path; `hardness` is the part of the radius at full strength,
+    /// falling off linearly to nothing at `radius` (in cells)
+    Stroke { points: Vec<[f32; 2]>, radius: f32, hardness: f32 },
+    /// Every cell between two corners
+    Rectangle { from: [f32; 2], to: [f32; 2] },
+    /// Every cell inside a closed polygon
+    Lasso { points: Vec<[f32; 2]> },
+}
+
+/// What an edit does to the magnitudes (and phases) in its region
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Operation {
+    /// Move magnitudes toward `level_db` (relative to the reference magnitude) by `strength`
+    /// Cells that mostly hold painted energy get phases continuing at their bin's frequency
+    Paint { level_db: f32, strength: f32 },
+    /// Turn magnitudes down by `strength` (1 = silence)
+    Erase { strength: f32 },
+    /// Scale magnitudes by `db`
+    Gain { db: f32 },
+    /// Average magnitudes over `radius` cells in each direction
+    Blur { radius: usize },
+    /// Copy the cells `offset` ([frames, bins]) away, with their phases when `include_phase`
+    Clone { offset: [i32; 2], include_phase: bool },
+}
+
+/// One spectral edit: an operation on a region of one channel (or of all channels)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Edit {
+    pub operation: Operation,
+    pub region: Region,
+    /// Channel edited, None for all channels
+    #[serde(default)]
+    pub channel: Option<usize>,
+}
+
+/// Weight in [0, 1] of every cell in a region's bounding box, clipped to the spectrogram
+#[derive(Debug, Clone)]
+pub struct Mask {
+    pub bins: Range<usize>,
+    pub frames: Range<usize>,
+    weights: Vec<f32>,
+}
+
+impl Mask {
+    /// Weight of a cell, 0 outside the bounding box
+    pub fn weight(&self, bin: usize, frame: usize) -> f32 {
+        if self.bins.contains(&bin) && self.frames.contains(&frame) {
+            self.weights[(bin - self.bins.start) * self.frames.len() + frame - self.frames.start]
+        } else {
+            0.0
+        }
+    }
+
+    // Cells with a weight above 0
+    fn cells(&self) -> impl Iterator<Item = (usize, usize, f32)> + '_ {
+        let width = self.frames.len();
+        self.weights.iter().enumerate()
+            .filter(|(_, &weight)| weight > 0.0)
+            .map(move |(i, &weight)| (self.bins.start + i / width, self.frames.start + i % width, weight))
+    }
+}
+
+impl Region {
+    /// Rasterize the region for a spectrogram of `num_bins` by `num_frames` cells
+    pub fn mask(&self, num_bins: usize, num_frames: usize) -> Mask {
+        let (min, max) = self.bounds();
+        let frames = cell_range(min[0], max[0], num_frames);
+        let bins = cell_range(min[1], max[1], num_bins);
+
+        let mut weights = Vec::with_capacity(bins.len() * frames.len());
+        for bin in bins.clone() {
+            for frame in frames.clone() {
+                weights.push(self.weight([frame as f32, bin as f32]));
+            }
+        }
+        Mask { bins, frames, weights }
+    }
+
+    /// Frames the region reaches in a spectrogram `num_frames` wide
+    pub fn frames(&self, num_frames: usize) -> Range<usize> {
+        let (min, max) = self.bounds();
+        cell_range(min[0], max[0], num_frames)
+    }
+
+    // Corners of the bounding box, including the brush radius
+    fn bounds(&self) -> ([f32; 2], [f32; 2]) {
+        let (points, margin): (&[[f32; 2]], f32) = match self {
+            Region::Stroke { points, radius, .. } => (points, *radius),
+            Region::Rectangle { from, to } => return ([from[0].min(to[0]), from[1].min(to[1])], [from[0].max(to[0]), from[1].max(to[1])]),
+            Region::Lasso { points } => (points, 0.0),
+        };
+        let mut min = [f32::INFINITY; 2];
+        let mut max = [f32::NEG_INFINITY; 2];
+        for point in points {
+            for axis in 0..2 {
+                min[axis] = min[axis].min(point[axis] - margin);
+                max[axis] = max[axis].max(point[axis] + margin);
+            }
+        }
+        (min, max)
+    }
+
+    fn weight(&self, cell: [f32; 2]) -> f32 {
+        match self {
+            Region::Stroke { points, radius, hardness } => {
+                let distance = match points.as_slice() {
+                    [] => return 0.0,
+                    [point] => distance(cell, *point),
+                    _ => points.windows(2).map(|segment| segment_distance(cell, segment[0], segment[1])).fold(f32::INFINITY, f32::min),
+                };
+                let full = radius * hardness.clamp(0.0, 1.0);
+                if distance <= full {
+                    1.0
+                } else if distance < *radius {
+                    (radius - distance) / (radius - full)
+                } else {
+                    0.0
+                }
+            }
+            // The bounding box is the rectangle
+            Region::Rectangle { .. } => 1.0,
+            Region::Lasso { points } => {
+                // Even-odd rule
+                let mut inside = false;
+                for (i, a) in points.iter().enumerate() {
+                    let b = points[(i + 1) % points.len()];
+                    if (a[1] > cell[1]) != (b[1] > cell[1]) && cell[0] < a[0] + (cell[1] - a[1]) / (b[1] - a[1]) * (b[0] - a[0]) {
+                        inside = !inside;
+                    }
+                }
+                if inside { 1.0 } else { 0.0 }
+            }
+        }
+    }
+}
+
+impl Edit {
+    /// Apply the edit to the spectrograms of all channels; `reference_magnitude` is the magnitude
+    /// 0 dB stands for (the image's peak magnitude)
+    /// Returns the frames changed
+    pub fn apply(&self, spectrograms: &mut [Spectrogram], reference_magnitude: f32) -> Range<usize> {
+        let mut changed = 0..0;
+        for (channel, spectrogram) in spectrograms.iter_mut().enumerate() {
+            if self.channel.is_some_and(|edited| edited != channel) {
+                continue;
+            }
+            let mask = self.region.mask(spectrogram.num_bins(), spectrogram.num_frames());
+            if mask.frames.is_empty() || mask.bins.is_empty() {
+                continue;
+            }
+            self.operation.apply(spectrogram, &mask, reference_magnitude);
+            changed = union(changed, mask.frames.clone());
+        }
+        changed
+    }
+}
+
+/// Named list of edits kept apart from the analyzed spectrogram, so it can be hidden, reordered
+/// or removed without touching the analysis
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Layer {
+    pub name: String,
+    #[serde(default = "default_visible")]
+    pub visible: bool,
+    /// Applied in order
+    #[serde(default)]
+    pub edits: Vec<Edit>,
+}
+
+fn default_visible() -> bool {
+    true
+}
+
+impl Layer {
+    pub fn new(name: &str) -> Self {
+        Self { name: name.to_string(), visible: true, edits: Vec::new() }
+    }
+}
+
+/// One undoable change to a layer stack
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    /// Append an edit to a layer
+    AddEdit { layer: usize, edit: Edit },
+    /// Remove the last edit of a layer (undoes `AddEdit`)
+    RemoveLastEdit { layer: usize },
+    /// Insert a layer at `index`
+    AddLayer { index: usize, layer: Layer },
+    RemoveLayer { index: usize },
+    /// Move a layer from one position to another, shifting the ones between
+    MoveLayer { from: usize, to: usize },
+    SetVisible { layer: usize, visible: bool },
+}
+
+/// Edit layers over an analyzed spectrogram, applied bottom (first) to top, with an undo/redo
+/// history of every change made to them
+#[derive(Debug, Clone, PartialEq)]
+pub struct Layers {
+    layers: Vec<Layer>,
+    // Changes that revert the last changes (undo) or restore the last reverted ones (redo), latest last
+    undo: Vec<Change>,
+    redo: Vec<Change>,
+}
+
+impl Default for Layers {
+    fn default() -> Self {
+        Self::from_layers(vec![Layer::new("Layer 1")])
+    }
+}
+
+impl Layers {
+    /// One empty layer
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Layers loaded from a project, with an empty history
+    pub fn from_layers(layers: Vec<Layer>) -> Self {
+        Self { layers, undo: Vec::new(), redo: Vec::new() }
+    }
+
+    pub fn layers(&self) -> &[Layer] {
+        &self.layers
+    }
+
+    /// Whether any layer holds an edit, shown or not
+    pub fn has_edits(&self) -> bool {
+        self.layers.iter().any(|layer| !layer.edits.is_empty())
+    }
+
+    /// Make a change and record it for undo, dropping what could be redone
+    /// Returns the edits whose effect the change adds, removes or reorders (see `changed_frames`)
+    pub fn change(&mut self, change: Change) -> Result<Vec<Edit>, String> {
+        let (inverse, affected) = self.perform(change)?;
+        self.undo.push(inverse);
+        self.redo.clear();
+        Ok(affected)
+    }
+
+    /// Revert the last change; None when there is nothing to undo
+    pub fn undo(&mut self) -> Option<Vec<Edit>> {
+        let change = self.undo.pop()?;
+        let (inverse, affected) = self.perform(change).expect("recorded changes fit the layers");
+        self.redo.push(inverse);
+        Some(affected)
+    }
+
+    /// Make the last undone change again; None when there is nothing to redo
+    pub fn redo(&mut self) -> Option<Vec<Edit>> {
+        let change = self.redo.pop()?;
+        let (inverse, affected) = self.perform(change).expect("recorded changes fit the layers");
+        self.undo.push(inverse);
+        Some(affected)
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    /// The base spectrograms with the edits of every visible layer applied
+    pub fn render(&self, base: &[Spectrogram], reference_magnitude: f32) -> Vec<Spectrogram> {
+        let mut spectrograms = base.to_vec();
+        for edit in self.layers.iter().filter(|layer| layer.visible).flat_map(|layer| &layer.edits) {
+            edit.apply(&mut spectrograms, reference_magnitude);
+        }
+        spectrograms
+    }
+
+    // Apply a change, returning the change that reverts it and the edits affected
+    fn perform(&mut self, change: Change) -> Result<(Change, Vec<Edit>), String> {
+        let count = self.layers.len();
+        let check = |index: usize, limit: usize| {
+            if index < limit { Ok(()) } else { Err(format!("No layer {} (there are {})", index + 1, count)) }
+        };
+        Ok(match change {
+            Change::AddEdit { layer, edit } => {
+                check(layer, count)?;
+                self.layers[layer].edits.push(edit.clone());
+                (Change::RemoveLastEdit { layer }, vec![edit])
+            }
+            Change::RemoveLastEdit { layer } => {
+                check(layer, count)?;
+                let edit = self.layers[layer].edits.pop().ok_or("The layer has no edits")?;
+                (Change::AddEdit { layer, edit: edit.clone() }, vec![edit])
+            }
+            Change::AddLayer { index, layer } => {
+                check(index, count + 1)?;
+                let affected = layer.edits.clone();
+                self.layers.insert(index, layer);
+                (Change::RemoveLayer { index }, affected)
+            }
+            Change::RemoveLayer { index } => {
+                check(index, count)?;
+                let layer = self.layers.remove(index);
+                let affected = layer.edits.clone();
+                (Change::AddLayer { index, layer }, affected)
+            }
+            Change::MoveLayer { from, to } => {
+                check(from, count)?;
+                check(to, count)?;
+                let layer = self.layers.remove(from);
+                let affected = layer.edits.clone();
+                self.layers.insert(to, layer);
+                (Change::MoveLayer { from: to, to: from }, affected)
+            }
+            Change::SetVisible { layer, visible } => {
+                check(layer, count)?;
+                let was_visible = std::mem::replace(&mut self.layers[layer].visible, visible);
+                (Change::SetVisible { layer, visible: was_visible }, self.layers[layer].edits.clone())
+            }
+        })
+    }
+}
+
+/// Frames whose rendering may differ after a change affecting `edits`
+pub fn changed_frames(edits: &[Edit], num_frames: usize) -> Range<usize> {
+    edits.iter().fold(0..0, |frames, edit| union(frames, edit.region.frames(num_frames)))
+}
+
+impl Operation {
+    fn apply(&self, spectrogram: &mut Spectrogram, mask: &Mask, reference_magnitude: f32) {
+        match *self {
+            Operation::Paint { level_db, strength } => {
+                let target = reference_magnitude * 10.0f32.powf(level_db / 20.0);
+                for (bin, frame, weight) in mask.cells() {
+                    let old = spectrogram.magnitude[bin][frame];
+                    let new = old + (target - old) * weight * strength.clamp(0.0, 1.0);
+                    spectrogram.magnitude[bin][frame] = new;
+                    if new > 2.0 * old && spectrogram.phase.is_some() {
+                        let phase = continuing_phase(spectrogram, bin, frame);
+                        if let Some(phases) = &mut spectrogram.phase {
+                            phases[bin][frame] = phase;
+                        }
+                    }
+                }
+            }
+            Operation::Erase { strength } => {
+                for (bin, frame, weight) in mask.cells() {
+                    spectrogram.magnitude[bin][frame] *= 1.0 - weight * strength.clamp(0.0, 1.0);
+                }
+            }
+            Operation::Gain { db } => {
+                for (bin, frame, weight) in mask.cells() {
+                    spectrogram.magnitude[bin][frame] *= 10.0f32.powf(db * weight / 20.0);
+                }
+            }
+            Operation::Blur { radius } => {
+                let blurred = box_blur(&spectrogram.magnitude, mask, radius);
+                for (bin, frame, weight) in mask.cells() {
+                    let old = spectrogram.magnitude[bin][frame];
+                    let new = blurred[(bin - mask.bins.start) * mask.frames.len() + frame - mask.frames.start];
+                    spectrogram.magnitude[bin][frame] = old + (new - old) * weight;
+                }
+            }
+            Operation::Clone { offset, include_phase } => {
+                // Read every source cell before writing, as source and target may overlap
+                let (num_bins, num_frames) = (spectrogram.num_bins() as i64, spectrogram.num_frames() as i64);
+                let copies: Vec<(usize, usize, f32, f32, Option<f32>)> = mask.cells()
+                    .filter_map(|(bin, frame, weight)| {
+                        let source_frame = frame as i64 + offset[0] as i64;
+                        let source_bin = bin as i64 + offset[1] as i64;
+                        if !(0..num_frames).contains(&source_frame) || !(0..num_bins).contains(&source_bin) {
+                            return None;
+                        }
+                        let (source_bin, source_frame) = (source_bin as usize, source_frame as usize);
+                        let phase = spectrogram.phase.as_ref()
+                            .filter(|_| include_phase && weight >= 0.5)
+                            .map(|phases| phases[source_bin][source_frame]);
+                        Some((bin, frame, weight, spectrogram.magnitude[source_bin][source_frame], phase))
+                    })
+                    .collect();
+                for (bin, frame, weight, magnitude, phase) in copies {
+                    let old = spectrogram.magnitude[bin][frame];
+                    spectrogram.magnitude[bin][frame] = old + (magnitude - old) * weight;
+                    if let (Some(phases), Some(phase)) = (&mut spectrogram.phase, phase) {
+                        phases[bin][frame] = phase;
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Cells whose centers lie within [min, max] on one axis
+fn cell_range(min: f32, max: f32, len: usize) -> Range<usize> {
+    if min.is_nan() || max.is_nan() || min > max {
+        return 0..0;
+    }
+    let end = ((max.floor() + 1.0).max(0.0) as usize).min(len);
+    let start = (min.ceil().max(0.0) as usize).min(end);
+    start..end
+}
+
+// Smallest range covering both (empty ranges cover nothing)
+fn union(a: Range<usize>, b: Range<usize>) -> Range<usize> {
+    if a.is_empty() {
+        b
+    } else if b.is_empty() {
+        a
+    } else {
+        a.start.min(b.start)..a.end.max(b.end)
+    }
+}
+
+fn distance(a: [f32; 2], b: [f32; 2]) -> f32 {
+    (a[0] - b[0]).hypot(a[1] - b[1])
+}
+
+fn segment_distance(point: [f32; 2], a: [f32; 2], b: [f32; 2]) -> f32 {
+    let direction = [b[0] - a[0], b[1] - a[1]];
+    let length_squared = direction[0] * direction[0] + direction[1] * direction[1];
+    if length_squared == 0.0 {
+        return distance(point, a);
+    }
+    let t = (((point[0] - a[0]) * direction[0] + (point[1] - a[1]) * direction[1]) / length_squared).clamp(0.0, 1.0);
+    distance(point, [a[0] + t * direction[0], a[1] + t * direction[1]])
+}
+
+// Phase a steady sinusoid at the bin's frequency has in this frame, so painted tones sound
+// continuous instead of buzzing at the frame rate
+fn continuing_phase(spectrogram: &Spectrogram, bin: usize, frame: usize) -> f32 {
+    let cycles = spectrogram.bin_frequency(bin) as f64 * spectrogram.frame_time(frame as f32) as f64;
+    (std::f64::consts::TAU * cycles.fract() - std::f64::consts::PI) as f32
+}
+
+// Box-blurred magnitudes over the mask's bounding box, rows first then columns
+fn box_blur(magnitude: &[Vec<f32>], mask: &Mask, radius: usize) -> Vec<f32> {
+    let num_frames = magnitude.first().map_or(0, |row| row.len());
+    let bins = mask.bins.start.saturating_sub(radius)..(mask.bins.end + radius).min(magnitude.len());
+    let width = mask.frames.len();
+
+    // Horizontal pass over the rows the vertical pass reads
+    let mut rows = Vec::with_capacity(bins.len() * width);
+    for bin in bins.clone() {
+        let row = &magnitude[bin];
+        for frame in mask.frames.clone() {
+            let window = &row[frame.saturating_sub(radius)..(frame + radius + 1).min(num_frames)];
+            rows.push(window.iter().sum::<f32>() / window.len() as f32);
+        }
+    }
+
+    let mut blurred = Vec::with_capacity(mask.bins.len() * width);
+    for bin in mask.bins.clone() {
+        let first = bin.saturating_sub(radius).max(bins.start) - bins.start;
+        let last = (bin + radius + 1).min(bins.end) - bins.start;
+        for column in 0..width {
+            let sum: f32 = (first..last).map(|row| rows[row * width + column]).sum();
+            blurred.push(sum / (last - first) as f32);
+        }
+    }
+    blurred
+}
@@ -0,0 +1,331 @@
+use eframe::egui;
+use egui::{Color32, PointerButton, Pos2, Rect, Shape, Stroke, Vec2};
+
+use spectrogram_converter::{Change, Edit, Layer, Layers, Operation, Region};
+
+// Colors of strokes and selections drawn over the spectrogram
+const GESTURE_COLOR: Color32 = Color32::from_rgba_premultiplied(80, 160, 255, 160);
+const SELECTION_COLOR: Color32 = Color32::from_rgb(255, 255, 255);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Tool {
+    Brush,
+    Eraser,
+    Clone,
+    Rectangle,
+    Lasso,
+}
+
+impl Tool {
+    const ALL: [Tool; 5] = [Tool::Brush, Tool::Eraser, Tool::Clone, Tool::Rectangle, Tool::Lasso];
+
+    fn label(self) -> &'static str {
+        match self {
+            Tool::Brush => "Brush",
+            Tool::Eraser => "Eraser",
+            Tool::Clone => "Clone",
+            Tool::Rectangle => "Rectangle",
+            Tool::Lasso => "Lasso",
+        }
+    }
+
+    fn is_brush(self) -> bool {
+        matches!(self, Tool::Brush | Tool::Eraser | Tool::Clone)
+    }
+}
+
+/// Painting tools and selections of the viewer's edit mode
+/// Positions come in image pixels and become `[frame, bin]` points of the band they start in
+pub struct SpectralEditor {
+    tool: Tool,
+    radius: f32,
+    hardness: f32,
+    level_db: f32,
+    strength: f32,
+    gain_db: f32,
+    blur_radius: usize,
+    include_phase: bool,
+    all_channels: bool,
+    // Clone source picked with Ctrl+click, and the offset to it fixed by the first stroke after that
+    clone_source: Option<(usize, [f32; 2])>,
+    clone_offset: Option<[i32; 2]>,
+    // Band and points of the stroke, rectangle or lasso being dragged
+    gesture: Option<(usize, Vec<[f32; 2]>)>,
+    // Finished rectangle or lasso selection and its band
+    selection: Option<(usize, Region)>,
+}
+
+impl SpectralEditor {
+    pub fn new() -> Self {
+        Self {
+            tool: Tool::Brush,
+            radius: 4.0,
+            hardness: 0.5,
+            level_db: -20.0,
+            strength: 1.0,
+            gain_db: -12.0,
+            blur_radius: 2,
+            include_phase: true,
+            all_channels: false,
+            clone_source: None,
+            clone_offset: None,
+            gesture: None,
+            selection: None,
+        }
+    }
+
+    /// Tool choice and settings; returns the edit of a selection command that was clicked
+    pub fn toolbar(&mut self, ui: &mut egui::Ui, num_bands: usize, has_phase: bool) -> Option<Edit> {
+        ui.horizontal(|ui| {
+            for tool in Tool::ALL {
+                if ui.selectable_label(self.tool == tool, tool.label()).clicked() {
+                    self.tool = tool;
+                    self.gesture = None;
+                }
+            }
+            ui.separator();
+            if self.tool.is_brush() {
+                ui.label("Radius");
+                ui.add(egui::DragValue::new(&mut self.radius).range(0.5..=200.0).speed(0.2));
+                ui.label("Hardness");
+                ui.add(egui::DragValue::new(&mut self.hardness).range(0.0..=1.0).speed(0.01));
+            }
+            match self.tool {
+                Tool::Brush => {
+                    ui.label("Level");
+                    ui.add(egui::DragValue::new(&mut self.level_db).range(-120.0..=20.0).suffix(" dB"));
+                    ui.label("Strength");
+                    ui.add(egui::DragValue::new(&mut self.strength).range(0.0..=1.0).speed(0.01));
+                }
+                Tool::Eraser => {
+                    ui.label("Strength");
+                    ui.add(egui::DragValue::new(&mut self.strength).range(0.0..=1.0).speed(0.01));
+                }
+                Tool::Clone => {
+                    ui.add_enabled(has_phase, egui::Checkbox::new(&mut self.include_phase, "Copy phase"));
+                    ui.label(if self.clone_source.is_some() { "Ctrl+click picks a new source" } else { "Ctrl+click picks the source" });
+                }
+                Tool::Rectangle | Tool::Lasso => {
+                    ui.label("Drag to select");
+                }
+            }
+            if num_bands > 1 {
+                ui.separator();
+                ui.checkbox(&mut self.all_channels, "All channels");
+            }
+        });
+
+        let mut edit = None;
+        ui.horizontal(|ui| {
+            ui.add_enabled_ui(self.selection.is_some(), |ui| {
+                ui.label("Selection:");
+                ui.add(egui::DragValue::new(&mut self.gain_db).range(-60.0..=24.0).suffix(" dB"));
+                if ui.button("Gain").clicked() {
+                    edit = self.selection_edit(Operation::Gain { db: self.gain_db });
+                }
+                ui.add(egui::DragValue::new(&mut self.blur_radius).range(1..=20).prefix("r "));
+                if ui.button("Blur").clicked() {
+                    edit = self.selection_edit(Operation::Blur { radius: self.blur_radius });
+                }
+                if ui.button("Erase").clicked() {
+                    edit = self.selection_edit(Operation::Erase { strength: 1.0 });
+                }
+                if ui.button("Deselect").clicked() {
+                    self.selection = None;
+                }
+            });
+            ui.label("Right-drag to pan");
+        });
+        edit
+    }
+
+    /// Handle the pointer over the plot; `position` is the pointer in image pixels
+    /// Returns the edit of a finished brush stroke
+    pub fn pointer(&mut self, ui: &egui::Ui, response: &egui::Response, position: Option<Pos2>, band_height: f32, num_bands: usize) -> Option<Edit> {
+        let position = position?;
+        let band = ((position.y / band_height).floor().max(0.0) as usize).min(num_bands - 1);
+        let ctrl = ui.input(|i| i.modifiers.command);
+
+        if self.tool == Tool::Clone && ctrl && (response.clicked() || response.drag_started_by(PointerButton::Primary)) {
+            self.clone_source = Some((band, band_point(position, band, band_height)));
+            self.clone_offset = None;
+            return None;
+        }
+
+        if response.drag_started_by(PointerButton::Primary) || response.clicked() {
+            self.gesture = Some((band, Vec::new()));
+        }
+        let (band, points) = self.gesture.as_mut()?;
+        let point = band_point(position, *band, band_height);
+        if points.last().is_none_or(|last| (last[0] - point[0]).hypot(last[1] - point[1]) >= 0.5) {
+            points.push(point);
+        }
+        if self.tool == Tool::Rectangle {
+            points.truncate(1);
+            points.push(point);
+        }
+
+        if response.dragged_by(PointerButton::Primary) {
+            return None;
+        }
+        let (band, points) = self.gesture.take()?;
+        let channel = if self.all_channels { None } else { Some(band) };
+        match self.tool {
+            Tool::Rectangle => {
+                self.selection = Some((band, Region::Rectangle { from: points[0], to: *points.last().expect("has the start point") }));
+                None
+            }
+            Tool::Lasso => {
+                self.selection = (points.len() >= 3).then_some((band, Region::Lasso { points }));
+                None
+            }
+            tool => {
+                let operation = match tool {
+                    Tool::Brush => Operation::Paint { level_db: self.level_db, strength: self.strength },
+                    Tool::Eraser => Operation::Erase { strength: self.strength },
+                    _ => {
+                        let source = self.clone_source?.1;
+                        let offset = *self.clone_offset.get_or_insert([(source[0] - points[0][0]).round() as i32, (source[1] - points[0][1]).round() as i32]);
+                        Operation::Clone { offset, include_phase: self.include_phase }
+                    }
+                };
+                let region = Region::Stroke { points, radius: self.radius, hardness: self.hardness };
+                Some(Edit { operation, region, channel })
+            }
+        }
+    }
+
+    /// Whether the plot should pan on this drag instead of editing
+    pub fn pans(response: &egui::Response) -> bool {
+        response.dragged_by(PointerButton::Secondary) || response.dragged_by(PointerButton::Middle)
+    }
+
+    /// Draw the gesture in progress, the selection, the clone source and the brush outline
+    pub fn draw(&self, painter: &egui::Painter, to_screen: impl Fn(Pos2) -> Pos2, zoom: Vec2, band_height: f32, hover: Option<Pos2>) {
+        let screen = |band: usize, point: [f32; 2]| to_screen(image_position(point, band, band_height));
+
+        if let Some((band, region)) = &self.selection {
+            draw_region(painter, region, |point| screen(*band, point), Stroke::new(1.0, SELECTION_COLOR));
+        }
+        if let Some((band, points)) = &self.gesture {
+            let stroke = Stroke::new(1.5, GESTURE_COLOR);
+            match self.tool {
+                Tool::Rectangle if points.len() == 2 => {
+                    let region = Region::Rectangle { from: points[0], to: points[1] };
+                    draw_region(painter, &region, |point| screen(*band, point), stroke);
+                }
+                Tool::Lasso => {
+                    painter.add(Shape::line(points.iter().map(|&point| screen(*band, point)).collect(), stroke));
+                }
+                _ => {
+                    let width = 2.0 * self.radius * (zoom.x + zoom.y) / 2.0;
+                    let color = GESTURE_COLOR.gamma_multiply(0.5);
+                    painter.add(Shape::line(points.iter().map(|&point| screen(*band, point)).collect(), Stroke::new(width, color)));
+                }
+            }
+        }
+        if let (Tool::Clone, Some((band, source))) = (self.tool, self.clone_source) {
+            let center = screen(band, source);
+            let stroke = Stroke::new(1.5, SELECTION_COLOR);
+            painter.line_segment([center - Vec2::new(6.0, 0.0), center + Vec2::new(6.0, 0.0)], stroke);
+            painter.line_segment([center - Vec2::new(0.0, 6.0), center + Vec2::new(0.0, 6.0)], stroke);
+        }
+        if let Some(pointer) = hover.filter(|_| self.tool.is_brush()) {
+            // Ellipse, since time and frequency zoom separately
+            let radius = self.radius * zoom;
+            let outline = (0..48)
+                .map(|i| {
+                    let angle = i as f32 / 48.0 * std::f32::consts::TAU;
+                    pointer + Vec2::new(angle.cos() * radius.x, angle.sin() * radius.y)
+                })
+                .collect();
+            painter.add(Shape::closed_line(outline, Stroke::new(1.0, Color32::from_white_alpha(160))));
+        }
+    }
+
+    // Edit applying `operation` to the selection
+    fn selection_edit(&self, operation: Operation) -> Option<Edit> {
+        let (band, region) = self.selection.clone()?;
+        Some(Edit { operation, region, channel: if self.all_channels { None } else { Some(band) } })
+    }
+}
+
+/// What the layer panel asked for
+pub enum LayerAction {
+    Change(Change),
+    Undo,
+    Redo,
+}
+
+/// Undo/redo and the layer list: which layers show, their order, and the layer new edits go to
+pub fn layer_panel(ui: &mut egui::Ui, layers: &Layers, active: &mut usize) -> Option<LayerAction> {
+    let mut action = None;
+    ui.horizontal_wrapped(|ui| {
+        if ui.add_enabled(layers.can_undo(), egui::Button::new("Undo")).on_hover_text("Ctrl+Z").clicked() {
+            action = Some(LayerAction::Undo);
+        }
+        if ui.add_enabled(layers.can_redo(), egui::Button::new("Redo")).on_hover_text("Ctrl+Shift+Z").clicked() {
+            action = Some(LayerAction::Redo);
+        }
+        ui.separator();
+
+        // Top layer first, as in image editors
+        ui.label("Layers:");
+        for (index, layer) in layers.layers().iter().enumerate().rev() {
+            let mut visible = layer.visible;
+            if ui.checkbox(&mut visible, "").on_hover_text("Show this layer").changed() {
+                action = Some(LayerAction::Change(Change::SetVisible { layer: index, visible }));
+            }
+            if ui.selectable_label(*active == index, format!("{} ({})", layer.name, layer.edits.len())).clicked() {
+                *active = index;
+            }
+        }
+        ui.separator();
+
+        let count = layers.layers().len();
+        if ui.add_enabled(*active + 1 < count, egui::Button::new("Up")).on_hover_text("Move the layer up").clicked() {
+            action = Some(LayerAction::Change(Change::MoveLayer { from: *active, to: *active + 1 }));
+            *active += 1;
+        }
+        if ui.add_enabled(*active > 0, egui::Button::new("Down")).on_hover_text("Move the layer down").clicked() {
+            action = Some(LayerAction::Change(Change::MoveLayer { from: *active, to: *active - 1 }));
+            *active -= 1;
+        }
+        if ui.button("New").on_hover_text("Add a layer on top").clicked() {
+            let layer = Layer::new(&format!("Layer {}", count + 1));
+            action = Some(LayerAction::Change(Change::AddLayer { index: count, layer }));
+            *active = count;
+        }
+        if ui.add_enabled(count > 1, egui::Button::new("Delete")).on_hover_text("Delete the layer and its edits").clicked() {
+            action = Some(LayerAction::Change(Change::RemoveLayer { index: *active }));
+        }
+    });
+    action
+}
+
+fn draw_region(painter: &egui::Painter, region: &Region, screen: impl Fn([f32; 2]) -> Pos2, stroke: Stroke) {
+    match region {
+        Region::Rectangle { from, to } => {
+            // Outline the cells, not their centers
+            let rect = Rect::from_two_pos(screen(*from), screen(*to));
+            let half_cell = (screen([1.0, 0.0]).x - screen([0.0, 0.0]).x).abs() / 2.0;
+            let half_row = (screen([0.0, 1.0]).y - screen([0.0, 0.0]).y).abs() / 2.0;
+            painter.rect_stroke(rect.expand2(Vec2::new(half_cell, half_row)), 0.0, stroke);
+        }
+        Region::Lasso { points } => {
+            painter.add(Shape::closed_line(points.iter().map(|&point| screen(point)).collect(), stroke));
+        }
+        Region::Stroke { points, .. } => {
+            painter.add(Shape::line(points.iter().map(|&point| screen(point)).collect(), stroke));
+        }
+    }
+}
+
+// `[frame, bin]` point of an image position, relative to a band (pixel x holds frame x, centered at x + 0.5)
+fn band_point(position: Pos2, band: usize, band_height: f32) -> [f32; 2] {
+    [position.x - 0.5, band_height - 0.5 - (position.y - band as f32 * band_height)]
+}
+
+fn image_position(point: [f32; 2], band: usize, band_height: f32) -> Pos2 {
+    Pos2::new(point[0] + 0.5, band as f32 * band_height + band_height - 0.5 - point[1])
+}
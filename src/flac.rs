@@ -0,0 +1,402 @@
+//! Minimal FLAC encoder: fixed-block frames with the best of the fixed linear predictors
+//! (orders 0-4) per channel and Rice-coded residuals
+
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+// Samples per frame (per channel)
+const BLOCK_SIZE: usize = 4096;
+
+// Highest residual partition order tried (2^6 = 64 partitions per subframe)
+const MAX_PARTITION_ORDER: u32 = 6;
+
+// Rice parameter escape code for the 5-bit parameter coding method
+const RICE2_ESCAPE: u32 = 31;
+
+/// Write integer samples (one Vec per channel) as a FLAC file
+/// `bits_per_sample` is 16 or 24; samples must fit in that many bits
+pub fn write_flac(output_path: &Path, channels: &[Vec<i32>], sample_rate: u32, bits_per_sample: u32) -> Result<(), Box<dyn std::error::Error>> {
+    let mut writer = FlacWriter::create(output_path, channels.len(), sample_rate, bits_per_sample)?;
+    writer.write(channels)?;
+    writer.finish()
+}
+
+/// FLAC file written a block of integer samples (one Vec per channel) at a time
+/// The sample count and MD5 signature in STREAMINFO are filled in by `finish`
+pub struct FlacWriter {
+    writer: BufWriter<File>,
+    num_channels: usize,
+    sample_rate: u32,
+    bits_per_sample: u32,
+    // Samples not yet making up a whole frame
+    pending: Vec<Vec<i32>>,
+    frame_index: u64,
+    num_frames: usize,
+    md5: md5::Context,
+}
+
+impl FlacWriter {
+    /// `bits_per_sample` is 16 or 24; samples must fit in that many bits
+    pub fn create(output_path: &Path, num_channels: usize, sample_rate: u32, bits_per_sample: u32) -> Result<Self, Box<dyn std::error::Error>> {
+        if num_channels == 0 || num_channels > 8 {
+            return Err(format!("FLAC supports 1 to 8 channels, got {}", num_channels).into());
+        }
+        if !(4..=24).contains(&bits_per_sample) {
+            return Err(format!("Unsupported FLAC bit depth {}", bits_per_sample).into());
+        }
+
+        let mut writer = BufWriter::new(File::create(output_path)?);
+        writer.write_all(b"fLaC")?;
+        writer.write_all(&stream_info(num_channels, 0, sample_rate, bits_per_sample, [0; 16]))?;
+        Ok(Self {
+            writer,
+            num_channels,
+            sample_rate,
+            bits_per_sample,
+            pending: vec![Vec::with_capacity(BLOCK_SIZE); num_channels],
+            frame_index: 0,
+            num_frames: 0,
+            md5: md5::Context::new(),
+        })
+    }
+
+    /// Append samples, writing every frame that fills up
+    pub fn write(&mut self, channels: &[Vec<i32>]) -> Result<(), Box<dyn std::error::Error>> {
+        let num_frames = channels.iter().map(|c| c.len()).min().unwrap_or(0);
+        self.consume_md5(channels, num_frames);
+        self.num_frames += num_frames;
+
+        let mut start = 0;
+        while start < num_frames {
+            let take = (BLOCK_SIZE - self.pending[0].len()).min(num_frames - start);
+            for (pending, channel) in self.pending.iter_mut().zip(channels) {
+                pending.extend_from_slice(&channel[start..start + take]);
+            }
+            start += take;
+            if self.pending[0].len() == BLOCK_SIZE {
+                self.write_frame()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the last, partial frame and complete STREAMINFO
+    pub fn finish(mut self) -> Result<(), Box<dyn std::error::Error>> {
+        if !self.pending[0].is_empty() {
+            self.write_frame()?;
+        }
+        let md5 = self.md5.compute().0;
+        self.writer.seek(SeekFrom::Start(4))?;
+        self.writer.write_all(&stream_info(self.num_channels, self.num_frames, self.sample_rate, self.bits_per_sample, md5))?;
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    fn write_frame(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let blocks: Vec<&[i32]> = self.pending.iter().map(Vec::as_slice).collect();
+        self.writer.write_all(&encode_frame(&blocks, self.frame_index, self.bits_per_sample))?;
+        self.frame_index += 1;
+        for pending in &mut self.pending {
+            pending.clear();
+        }
+        Ok(())
+    }
+
+    // MD5 of the samples as STREAMINFO defines it: interleaved, little-endian, in whole bytes
+    fn consume_md5(&mut self, channels: &[Vec<i32>], num_frames: usize) {
+        let bytes_per_sample = self.bits_per_sample.div_ceil(8) as usize;
+        let mut bytes = Vec::with_capacity(num_frames * channels.len() * bytes_per_sample);
+        for i in 0..num_frames {
+            for channel in channels {
+                bytes.extend_from_slice(&channel[i].to_le_bytes()[..bytes_per_sample]);
+            }
+        }
+        self.md5.consume(&bytes);
+    }
+}
+
+// STREAMINFO metadata block (marked as the last metadata block)
+fn stream_info(num_channels: usize, num_frames: usize, sample_rate: u32, bits_per_sample: u32, md5: [u8; 16]) -> Vec<u8> {
+    let mut bits = BitWriter::default();
+    bits.write(BLOCK_SIZE as u64, 16); // min block size
+    bits.write(BLOCK_SIZE as u64, 16); // max block size
+    bits.write(0, 24); // min frame size (unknown)
+    bits.write(0, 24); // max frame size (unknown)
+    bits.write(sample_rate as u64, 20);
+    bits.write(num_channels as u64 - 1, 3);
+    bits.write(bits_per_sample as u64 - 1, 5);
+    bits.write(num_frames as u64, 36);
+    for byte in md5 {
+        bits.write(byte as u64, 8); // MD5 signature
+    }
+    let body = bits.into_bytes();
+
+    let mut block = vec![0x80]; // last-metadata-block flag, type 0 (STREAMINFO)
+    block.extend_from_slice(&(body.len() as u32).to_be_bytes()[1..]);
+    block.extend_from_slice(&body);
+    block
+}
+
+// One frame holding a block of every channel, coded independently
+fn encode_frame(blocks: &[&[i32]], frame_index: u64, bits_per_sample: u32) -> Vec<u8> {
+    let block_size = blocks[0].len();
+
+    let mut bits = BitWriter::default();
+    bits.write(0b11111111111110, 14); // sync code
+    bits.write(0, 1); // reserved
+    bits.write(0, 1); // fixed block size
+    bits.write(0b0111, 4); // block size stored as 16 bits after the header
+    bits.write(0b0000, 4); // sample rate from STREAMINFO
+    bits.write(blocks.len() as u64 - 1, 4); // independent channels
+    bits.write(sample_size_code(bits_per_sample), 3);
+    bits.write(0, 1); // reserved
+    for byte in utf8_number(frame_index) {
+        bits.write(byte as u64, 8);
+    }
+    bits.write(block_size as u64 - 1, 16);
+    let crc = crc8(bits.bytes());
+    bits.write(crc as u64, 8);
+
+    for block in blocks {
+        encode_subframe(&mut bits, block, bits_per_sample);
+    }
+
+    bits.align();
+    let crc = crc16(bits.bytes());
+    bits.write(crc as u64, 16);
+    bits.into_bytes()
+}
+
+// Smallest of the CONSTANT, FIXED (orders 0-4) and VERBATIM encodings
+fn encode_subframe(bits: &mut BitWriter, samples: &[i32], bits_per_sample: u32) {
+    if samples.iter().all(|&s| s == samples[0]) {
+        bits.write(0, 1);
+        bits.write(0b000000, 6); // CONSTANT
+        bits.write(0, 1);
+        bits.write_signed(samples[0] as i64, bits_per_sample);
+        return;
+    }
+
+    let verbatim_bits = samples.len() as u64 * bits_per_sample as u64;
+    let best = (0..=4usize)
+        .filter(|&order| order < samples.len())
+        .map(|order| {
+            let residual = fixed_residual(samples, order);
+            let (partition_order, params, cost) = best_partitioning(&residual, samples.len(), order);
+            (order, residual, partition_order, params, cost.saturating_add(order as u64 * bits_per_sample as u64))
+        })
+        .min_by_key(|candidate| candidate.4);
+
+    match best {
+        Some((order, residual, partition_order, params, cost)) if cost < verbatim_bits => {
+            bits.write(0, 1);
+            bits.write(0b001000 | order as u64, 6); // FIXED
+            bits.write(0, 1);
+            for &warm_up in &samples[..order] {
+                bits.write_signed(warm_up as i64, bits_per_sample);
+            }
+            write_residual(bits, &residual, samples.len(), order, partition_order, &params);
+        }
+        _ => {
+            bits.write(0, 1);
+            bits.write(0b000001, 6); // VERBATIM
+            bits.write(0, 1);
+            for &sample in samples {
+                bits.write_signed(sample as i64, bits_per_sample);
+            }
+        }
+    }
+}
+
+// Prediction error of the fixed polynomial predictor of the given order
+fn fixed_residual(samples: &[i32], order: usize) -> Vec<i64> {
+    (order..samples.len())
+        .map(|i| {
+            let s = |k: usize| samples[i - k] as i64;
+            let prediction = match order {
+                0 => 0,
+                1 => s(1),
+                2 => 2 * s(1) - s(2),
+                3 => 3 * s(1) - 3 * s(2) + s(3),
+                _ => 4 * s(1) - 6 * s(2) + 4 * s(3) - s(4),
+            };
+            samples[i] as i64 - prediction
+        })
+        .collect()
+}
+
+// Partition order and per-partition Rice parameters with the fewest bits
+fn best_partitioning(residual: &[i64], block_size: usize, order: usize) -> (u32, Vec<u32>, u64) {
+    let mut best: Option<(u32, Vec<u32>, u64)> = None;
+    for partition_order in 0..=MAX_PARTITION_ORDER {
+        let partitions = 1usize << partition_order;
+        // Partitions must be equal-sized and hold at least the warm-up samples
+        if !block_size.is_multiple_of(partitions) || block_size / partitions <= order {
+            break;
+        }
+
+        let mut params = Vec::with_capacity(partitions);
+        let mut cost = 6; // coding method and partition order
+        for partition in partition_ranges(block_size, order, partition_order) {
+            let (param, bits) = best_rice_parameter(&residual[partition]);
+            params.push(param);
+            cost += 5 + bits;
+        }
+
+        if best.as_ref().is_none_or(|b| cost < b.2) {
+            best = Some((partition_order, params, cost));
+        }
+    }
+    best.unwrap_or((0, vec![RICE2_ESCAPE], u64::MAX))
+}
+
+// Residual index range of each partition (the first is shortened by the predictor order)
+fn partition_ranges(block_size: usize, order: usize, partition_order: u32) -> Vec<std::ops::Range<usize>> {
+    let partition_len = block_size >> partition_order;
+    (0..1usize << partition_order)
+        .map(|p| {
+            let start = if p == 0 { 0 } else { p * partition_len - order };
+            start..(p + 1) * partition_len - order
+        })
+        .collect()
+}
+
+// Rice parameter with the fewest bits for these values, and that bit count
+fn best_rice_parameter(values: &[i64]) -> (u32, u64) {
+    let folded: Vec<u64> = values.iter().map(|&v| zigzag(v)).collect();
+    let mean = folded.iter().sum::<u64>() / folded.len().max(1) as u64;
+    let estimate = 64 - mean.leading_zeros();
+
+    (estimate.saturating_sub(1)..=(estimate + 1).min(RICE2_ESCAPE - 1))
+        .map(|param| {
+            let bits = folded.iter().map(|&u| (u >> param) + 1 + param as u64).sum::<u64>();
+            (param, bits)
+        })
+        .min_by_key(|&(_, bits)| bits)
+        .unwrap()
+}
+
+fn write_residual(bits: &mut BitWriter, residual: &[i64], block_size: usize, order: usize, partition_order: u32, params: &[u32]) {
+    bits.write(0b01, 2); // Rice coding with 5-bit parameters
+    bits.write(partition_order as u64, 4);
+    for (range, &param) in partition_ranges(block_size, order, partition_order).into_iter().zip(params) {
+        bits.write(param as u64, 5);
+        for &value in &residual[range] {
+            let folded = zigzag(value);
+            bits.write_unary(folded >> param);
+            bits.write(folded & ((1u64 << param) - 1), param);
+        }
+    }
+}
+
+// Map signed to unsigned: 0, -1, 1, -2, 2... -> 0, 1, 2, 3, 4...
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+fn sample_size_code(bits_per_sample: u32) -> u64 {
+    match bits_per_sample {
+        8 => 0b001,
+        12 => 0b010,
+        16 => 0b100,
+        20 => 0b101,
+        24 => 0b110,
+        _ => 0b000, // from STREAMINFO
+    }
+}
+
+// Frame number in FLAC's extended UTF-8 coding
+fn utf8_number(value: u64) -> Vec<u8> {
+    if value < 0x80 {
+        return vec![value as u8];
+    }
+    let mut continuation = Vec::new();
+    let mut rest = value;
+    // Each continuation byte carries 6 bits; the leading byte shrinks as more follow
+    while rest >= (0x40 >> continuation.len()) {
+        continuation.push(0x80 | (rest & 0x3F) as u8);
+        rest >>= 6;
+    }
+    let count = continuation.len();
+    let leading = (0xFF00u16 >> (count + 1)) as u8 | rest as u8;
+    std::iter::once(leading).chain(continuation.into_iter().rev()).collect()
+}
+
+fn crc8(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |mut crc, &byte| {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 { (crc << 1) ^ 0x07 } else { crc << 1 };
+        }
+        crc
+    })
+}
+
+fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0u16, |mut crc, &byte| {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x8005 } else { crc << 1 };
+        }
+        crc
+    })
+}
+
+// MSB-first bit writer, shifting whole values into a 64-bit accumulator
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    accumulator: u64,
+    count: u32,
+}
+
+impl BitWriter {
+    // Longest value one write takes, leaving room for the up to 7 bits still pending
+    const MAX_BITS: u32 = 56;
+
+    fn write(&mut self, value: u64, bits: u32) {
+        debug_assert!(bits <= Self::MAX_BITS);
+        if bits == 0 {
+            return;
+        }
+        self.accumulator = (self.accumulator << bits) | (value & ((1u64 << bits) - 1));
+        self.count += bits;
+        while self.count >= 8 {
+            self.count -= 8;
+            self.bytes.push((self.accumulator >> self.count) as u8);
+        }
+        self.accumulator &= (1u64 << self.count) - 1;
+    }
+
+    fn write_signed(&mut self, value: i64, bits: u32) {
+        self.write(value as u64, bits);
+    }
+
+    // `value` zeros followed by a one
+    fn write_unary(&mut self, value: u64) {
+        let mut zeros = value;
+        while zeros >= Self::MAX_BITS as u64 {
+            self.write(0, Self::MAX_BITS);
+            zeros -= Self::MAX_BITS as u64;
+        }
+        self.write(1, zeros as u32 + 1);
+    }
+
+    // Pad with zeros to a byte boundary
+    fn align(&mut self) {
+        if self.count > 0 {
+            self.write(0, 8 - self.count);
+        }
+    }
+
+    // Complete bytes written so far
+    fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    fn into_bytes(mut self) -> Vec<u8> {
+        self.align();
+        self.bytes
+    }
+}
@@ -1,343 +1,248 @@
-use image;
-use hound;
-use rustfft::{FftPlanner, num_complex::Complex};
-use std::path::Path;
-use crate::config::SpectrogramConfig;
-
-/// Griffin-Lim algorithm for phase reconstruction from magnitude spectrogram
-/// Iteratively estimates phases that produce a consistent signal
-fn griffin_lim(
-    magnitude_spectrogram: &[Vec<f32>],
-    fft_size: usize,
-    hop_size: usize,
-    num_iterations: usize,
-) -> Vec<Vec<f32>> {
-    let num_bins = magnitude_spectrogram.len();
-    let num_frames = magnitude_spectrogram[0].len();
-    
-    println!("Running Griffin-Lim algorithm with {} iterations...", num_iterations);
-    
-    // Initialize with zero phases
-    let mut phase_spectrogram: Vec<Vec<f32>> = vec![vec![0.0f32; num_frames]; num_bins];
-    
-    let mut planner = FftPlanner::new();
-    let fft_forward = planner.plan_fft_forward(fft_size);
-    let fft_inverse = planner.plan_fft_inverse(fft_size);
-    
-    // Iteratively refine phases
-    for iteration in 0..num_iterations {
-        // Step 1: Reconstruct time-domain signal with current phases
-        let output_len = (num_frames - 1) * hop_size + fft_size;
-        let mut time_signal = vec![0.0f32; output_len];
-        let mut window_sum = vec![0.0f32; output_len];
-        
-        for frame_idx in 0..num_frames {
-            let mut spectrum = vec![Complex::new(0.0, 0.0); fft_size];
-            
-            // Build complex spectrum from magnitude and phase
-            for bin in 0..num_bins.min(fft_size / 2 + 1) {
-                let mag = magnitude_spectrogram[bin][frame_idx];
-                let phase = phase_spectrogram[bin][frame_idx];
-                spectrum[bin] = Complex::new(mag * phase.cos(), mag * phase.sin());
-            }
-            
-            // Mirror for negative frequencies
-            for bin in 1..num_bins.min(fft_size / 2) {
-                spectrum[fft_size - bin] = spectrum[bin].conj();
-            }
-            
-            fft_inverse.process(&mut spectrum);
-            
-            // Overlap-add with Hann window
-            let start = frame_idx * hop_size;
-            for (i, &value) in spectrum.iter().take(fft_size).enumerate() {
-                if start + i < output_len {
-                    let window = 0.5 * (1.0 - ((2.0 * std::f32::consts::PI * i as f32) / (fft_size as f32 - 1.0)).cos());
-                    time_signal[start + i] += value.re * window / fft_size as f32;
-                    window_sum[start + i] += window;
-                }
-            }
-        }
-        
-        // Normalize by window sum
-        for i in 0..output_len {
-            if window_sum[i] > 1e-8 {
-                time_signal[i] /= window_sum[i];
-            }
-        }
-        
-        // Step 2: Re-analyze time signal to get improved phases
-        for frame_idx in 0..num_frames {
-            let start = frame_idx * hop_size;
-            let end = (start + fft_size).min(output_len);
-            
-            if end - start < fft_size {
-                break;
-            }
-            
-            // Apply Hann window and FFT
-            let mut buffer: Vec<Complex<f32>> = time_signal[start..end]
-                .iter()
-                .enumerate()
-                .map(|(i, &s)| {
-                    let window = 0.5 * (1.0 - ((2.0 * std::f32::consts::PI * i as f32) / (fft_size as f32 - 1.0)).cos());
-                    Complex::new(s * window, 0.0)
-                })
-                .collect();
-            
-            fft_forward.process(&mut buffer);
-            
-            // Update phases while keeping original magnitudes
-            for bin in 0..num_bins.min(fft_size / 2 + 1) {
-                phase_spectrogram[bin][frame_idx] = buffer[bin].arg();
-            }
-        }
-        
-        if iteration % 10 == 0 || iteration == num_iterations - 1 {
-            println!("  Iteration {}/{} complete", iteration + 1, num_iterations);
-        }
-    }
-    
-    println!("Griffin-Lim complete!");
-    phase_spectrogram
-}
+use image::Rgb32FImage;
+use std::path::{Path, PathBuf};
+use crate::audio_io::{write_audio, AudioData};
+use crate::config::{Padding, SpectrogramConfig};
+use crate::level::apply_output_level;
+use crate::metadata::SpectrogramMetadata;
+use crate::progress::{Cancelled, Progress};
+use crate::spectrogram::{synthesize_with_progress, Spectrogram};
+use crate::streaming::{is_tile_manifest, open_manifest, sibling_tiles, tiles_to_file, tiles_to_signals, Tile};
 
+/// Decode a spectrogram image back into magnitudes (and phases when encoded in hue)
+/// `metadata` must describe how the image was made; magnitudes are scaled back by its peak
+/// Channel values are floats in [0, 1] (`DynamicImage::to_rgb32f` keeps 16-bit and float precision)
+/// Fails when the image is too low for a spectrum, or its FFT size is shorter than the hop size
+pub fn image_to_spectrogram(img: &Rgb32FImage, metadata: &SpectrogramMetadata) -> Result<Spectrogram, Box<dyn std::error::Error>> {
+    let (width, height) = img.dimensions();
 
-pub fn spectrogram_to_audio(
-    image_path: &Path,
-    output_path: &Path,
-    config: &SpectrogramConfig,
-) -> Result<(), Box<dyn std::error::Error>> {
-    // Extract sample rate, scale mode, and phase encoding from filename
-    let (sample_rate, use_log_scale, use_phase_encoding) = if let Some(stem) = image_path.file_stem() {
-        let stem_str = stem.to_string_lossy();
-        println!("Filename stem: {}", stem_str);
-        
-        let sample_rate = if let Some(sr_pos) = stem_str.rfind("_SR") {
-            let after_sr = &stem_str[sr_pos + 3..];
-            let sr_str: String = after_sr.chars().take_while(|c| c.is_numeric()).collect();
-            let parsed_sr = sr_str.parse::<u32>().unwrap_or(44100);
-            println!("Extracted sample rate from filename: {}", parsed_sr);
-            parsed_sr
-        } else {
-            println!("No _SR found in filename, using default 44100");
-            44100
-        };
+    let num_frames = width as usize;
+    let num_bins_image = height as usize;
+    if num_bins_image < 2 {
+        return Err(format!("image is {} pixels high; a spectrum needs at least 2 rows", num_bins_image).into());
+    }
 
-        let use_log_scale = stem_str.contains("_LOG");
-        println!("use_log_scale: {}", use_log_scale);
-        
-        let use_phase_encoding = if stem_str.contains("_PHASE") {
-            println!("Phase encoding: ENABLED");
-            true
-        } else if stem_str.contains("_MAG") {
-            println!("Phase encoding: DISABLED");
-            false
+    // Images at FFT resolution from legacy encoders only carry the FFT size in their height;
+    // otherwise the FFT size comes from the metadata and rows are resampled to it
+    let mut config = metadata.config.clone();
+    if config.image_height() != num_bins_image {
+        if config.num_bins == 0 {
+            config.fft_size = (num_bins_image - 1) * 2;
         } else {
-            println!("Phase encoding: ENABLED (legacy)");
-            true
-        };
+            // Resized in an editor
+            config.num_bins = num_bins_image;
+        }
+    }
+    if config.hop_size == 0 || config.hop_size > config.fft_size {
+        return Err(format!("hop size {} does not fit the {}-sample FFT of a {}-row image", config.hop_size, config.fft_size, num_bins_image).into());
+    }
 
-        (sample_rate, use_log_scale, use_phase_encoding)
+    let num_samples = if metadata.num_samples > 0 {
+        metadata.num_samples
     } else {
-        (44100, false, true)
+        // Everything the frames cover, without padding
+        match config.padding {
+            Padding::None => (num_frames.max(1) - 1) * config.hop_size + config.fft_size,
+            _ => (num_frames.max(1) - 1) * config.hop_size,
+        }
     };
-    
-    let img = image::open(image_path)?.to_rgb8();
-    let (width, height) = img.dimensions();
-
-    let num_frames = width as usize;
-    let num_bins_image = height as usize;
 
-    let fft_size = (num_bins_image - 1) * 2;
-    let num_bins_linear = fft_size / 2 + 1;
+    let mut spectrogram = Spectrogram {
+        magnitude: vec![vec![0.0f32; num_frames]; num_bins_image],
+        phase: None,
+        sample_rate: metadata.sample_rate,
+        num_samples,
+        config,
+    };
+    let mut phase_image = vec![vec![0.0f32; num_frames]; num_bins_image];
+    let config = &spectrogram.config;
 
-    println!("Image size: {}x{}", width, height);
-    println!("FFT size: {}, HOP_SIZE: {}", fft_size, config.hop_size);
+    let reference_magnitude = metadata.peak_magnitude.unwrap_or(1.0);
 
-    let mut spectrogram_mag_image = vec![vec![0.0f32; num_frames]; num_bins_image];
-    let mut spectrogram_phase_image = vec![vec![0.0f32; num_frames]; num_bins_image];
-    
     // Decode magnitude and phase
-    for frame in 0..num_frames {
-        for bin in 0..num_bins_image {
+    for (bin, phase_row) in phase_image.iter_mut().enumerate() {
+        let boost_db = spectrogram.boost_db(bin);
+
+        for frame in 0..num_frames {
             let y = height - 1 - bin as u32;
             let pixel = img.get_pixel(frame as u32, y);
-            let (h, s, v) = rgb_to_hsv(pixel[0], pixel[1], pixel[2]);
-
-            // Calculate frequency for this bin
-            let bin_freq = if use_log_scale {
-                let nyquist = sample_rate as f32 / 2.0;
-                let t = bin as f32 / (num_bins_image - 1) as f32;
-                config.min_freq * (nyquist / config.min_freq).powf(t)
+            // Float images store levels below db_min as negated colors
+            let (h, s, v) = if pixel.0.iter().all(|&c| c <= 0.0) {
+                let (h, s, v) = rgb_to_hsv(-pixel[0], -pixel[1], -pixel[2]);
+                (h, s, -v)
             } else {
-                let nyquist = sample_rate as f32 / 2.0;
-                (bin as f32 / (num_bins_image - 1) as f32) * nyquist
+                rgb_to_hsv(pixel[0], pixel[1], pixel[2])
             };
 
-            let phase = if use_phase_encoding {
+            if config.use_phase_encoding {
                 let decoded_phase = (h / 360.0) * 2.0 * std::f32::consts::PI - std::f32::consts::PI;
-                if s < 0.1 && frame > 0 {
-                    spectrogram_phase_image[bin][frame - 1]
+                phase_row[frame] = if s < 0.1 && frame > 0 {
+                    phase_row[frame - 1]
                 } else {
                     decoded_phase
-                }
-            } else {
-                // For magnitude-only: use instantaneous frequency
-                if frame == 0 {
-                    // Start with zero phase
-                    0.0
-                } else {
-                    // Phase advance based on frequency
-                    let prev_phase = spectrogram_phase_image[bin][frame - 1];
-                    let phase_advance = 2.0 * std::f32::consts::PI * bin_freq * (config.hop_size as f32 / sample_rate as f32);
-                    
-                    // Wrap to [-π, π]
-                    let mut new_phase = prev_phase + phase_advance;
-                    while new_phase > std::f32::consts::PI {
-                        new_phase -= 2.0 * std::f32::consts::PI;
-                    }
-                    while new_phase < -std::f32::consts::PI {
-                        new_phase += 2.0 * std::f32::consts::PI;
-                    }
-                    new_phase
-                }
-            };
-
-            let boost_db = if bin_freq > config.boost_start_freq {
-                config.boost_db_per_octave * (bin_freq / config.boost_start_freq).log2()
-            } else {
-                0.0
-            };
+                };
+            }
 
             let db = v * (config.db_max - config.db_min) + config.db_min;
             let db_without_boost = db - boost_db;
-            let magnitude = 10.0f32.powf(db_without_boost / 20.0);
+            let magnitude = 10.0f32.powf(db_without_boost / 20.0) * reference_magnitude;
 
-            spectrogram_mag_image[bin][frame] = magnitude.max(0.0);
-            spectrogram_phase_image[bin][frame] = phase;
+            spectrogram.magnitude[bin][frame] = magnitude.max(0.0);
         }
     }
 
-    // Apply inverse frequency scale transformation
-    let (spectrogram_mag, mut spectrogram_phase) = if use_log_scale {
-        let nyquist = sample_rate as f32 / 2.0;
-        let min_freq = config.min_freq;
-        let max_freq = nyquist;
+    if spectrogram.config.use_phase_encoding {
+        spectrogram.phase = Some(phase_image);
+    }
 
-        let mut spectrogram_mag_linear = vec![vec![0.0f32; num_frames]; num_bins_linear];
-        let mut spectrogram_phase_linear = vec![vec![0.0f32; num_frames]; num_bins_linear];
+    Ok(spectrogram)
+}
 
-        for linear_bin in 0..num_bins_linear {
-            let freq_linear = (linear_bin as f32 / (num_bins_linear - 1) as f32) * nyquist;
-            let log_bin_float = if freq_linear <= min_freq {
-                0.0
-            } else {
-                let t = (freq_linear / min_freq).ln() / (max_freq / min_freq).ln();
-                t * (num_bins_image - 1) as f32
-            };
+/// Decode a spectrogram image (and its sibling channel images, if any) into a WAV or FLAC file
+/// `image_path` may also be a tile manifest or a folder holding one; any tile of a tiled
+/// sequence decodes the whole sequence too
+/// Fails with `Cancelled` when `progress` is cancelled; nothing is written then
+pub fn spectrogram_to_audio(
+    image_path: &Path,
+    output_path: &Path,
+    config: &SpectrogramConfig,
+    progress: &Progress,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let Source { metadata, tiles } = open_spectrogram(image_path, config, progress)?;
 
-            for frame_idx in 0..num_frames {
-                let (mag, phase) = interpolate_spectrum(
-                    &spectrogram_mag_image,
-                    &spectrogram_phase_image,
-                    log_bin_float,
-                    frame_idx,
-                );
-                spectrogram_mag_linear[linear_bin][frame_idx] = mag;
-                spectrogram_phase_linear[linear_bin][frame_idx] = phase;
-            }
+    // Tiled sequences are written as they are decoded, so long files never sit in memory whole
+    let num_channels = if let Some(tiles) = tiles {
+        tiles_to_file(&tiles, &metadata, output_path, config, progress)?;
+        metadata.channels
+    } else {
+        let AudioData { channels, sample_rate } = decode_image(image_path, &metadata, progress)?;
+        progress.status("Writing audio file...");
+        write_audio(output_path, &channels, sample_rate, config)?;
+        channels.len()
+    };
+
+    println!("Saved {}-channel audio to: {}", num_channels, output_path.display());
+    Ok(())
+}
+
+/// Decode a spectrogram image into the samples `spectrogram_to_audio` would write, for playback
+/// or further processing
+pub fn decode_spectrogram(
+    image_path: &Path,
+    config: &SpectrogramConfig,
+    progress: &Progress,
+) -> Result<AudioData, Box<dyn std::error::Error>> {
+    let Source { metadata, tiles } = open_spectrogram(image_path, config, progress)?;
+    match tiles {
+        Some(tiles) => {
+            let signals = tiles_to_signals(&tiles, &metadata, progress)?;
+            Ok(join_channels(signals, &metadata))
         }
+        None => decode_image(image_path, &metadata, progress),
+    }
+}
+
+// Spectrogram to decode: its metadata, and every tile when it is tiled
+struct Source {
+    metadata: SpectrogramMetadata,
+    tiles: Option<Vec<Tile>>,
+}
 
-        (spectrogram_mag_linear, spectrogram_phase_linear)
+// Open an image, tile manifest or folder of tiles for decoding
+fn open_spectrogram(image_path: &Path, config: &SpectrogramConfig, progress: &Progress) -> Result<Source, Box<dyn std::error::Error>> {
+    progress.status("Reading image file...");
+    // A manifest knows the audio's layout even when the images lost their metadata
+    let (listed_tiles, metadata) = if image_path.is_dir() || is_tile_manifest(image_path) {
+        let (tiles, metadata) = open_manifest(image_path, |first_image| SpectrogramMetadata::load(first_image, config))?;
+        (Some(tiles), metadata)
     } else {
-        (spectrogram_mag_image, spectrogram_phase_image)
+        (None, SpectrogramMetadata::load(image_path, config)?)
     };
-    
-    // Apply Griffin-Lim algorithm if phase encoding was disabled
-    if !use_phase_encoding {
-        println!("Phase encoding disabled - using Griffin-Lim for phase reconstruction");
-        spectrogram_phase = griffin_lim(&spectrogram_mag, fft_size, config.hop_size, config.griffin_lim_iterations);
-    } else {
+
+    if metadata.config.use_phase_encoding {
         println!("Phase encoding enabled - using decoded phases");
+    } else {
+        println!("Phase encoding disabled - using {} for phase reconstruction", metadata.config.phase_retrieval_description());
     }
-    
-    // Inverse STFT
-    let mut planner = FftPlanner::new();
-    let ifft = planner.plan_fft_inverse(fft_size);
-    
-    let output_len = (num_frames - 1) * config.hop_size + fft_size;
-    let mut output = vec![0.0f32; output_len];
-    let mut window_sum = vec![0.0f32; output_len];
-    
-    for frame_idx in 0..num_frames {
-        let mut spectrum = vec![Complex::new(0.0, 0.0); fft_size];
 
-        let num_bins_to_use = spectrogram_mag.len().min(fft_size / 2 + 1);
-        for bin in 0..num_bins_to_use {
-            let magnitude = spectrogram_mag[bin][frame_idx];
-            let phase = spectrogram_phase[bin][frame_idx];
-            spectrum[bin] = Complex::new(magnitude * phase.cos(), magnitude * phase.sin());
-        }
-
-        for bin in 1..num_bins_to_use.min(fft_size / 2) {
-            spectrum[fft_size - bin] = spectrum[bin].conj();
-        }
-        
-        ifft.process(&mut spectrum);
-        
-        let start = frame_idx * config.hop_size;
-        for (i, &value) in spectrum.iter().take(fft_size).enumerate() {
-            if start + i < output_len {
-                let window = 0.5 * (1.0 - ((2.0 * std::f32::consts::PI * i as f32) / (fft_size as f32 - 1.0)).cos());
-                output[start + i] += value.re * window / fft_size as f32;
-                window_sum[start + i] += window;
-            }
-        }
-    }
-    
-    // Normalize by window sum
-    for i in 0..output_len {
-        if window_sum[i] > 1e-8 {
-            output[i] /= window_sum[i];
-        }
+    let tiles = listed_tiles.or_else(|| (metadata.tiles > 1).then(|| sibling_tiles(image_path, &metadata)));
+    if let Some(tiles) = &tiles {
+        println!("Reading {} tiles", tiles.len());
     }
-    
-    // Normalize output
-    let max_sample = output.iter().map(|&x| x.abs()).fold(0.0f32, f32::max);
-    if max_sample > 1e-8 {
-        for sample in output.iter_mut() {
-            *sample = (*sample / max_sample) * 0.95;
-        }
+    Ok(Source { metadata, tiles })
+}
+
+// Decode a single (untiled) image and its sibling channel images
+fn decode_image(image_path: &Path, metadata: &SpectrogramMetadata, progress: &Progress) -> Result<AudioData, Box<dyn std::error::Error>> {
+    let spectrograms: Vec<Spectrogram> = read_bands(image_path, metadata)?
+        .iter()
+        .map(|band| image_to_spectrogram(band, metadata))
+        .collect::<Result<_, _>>()?;
+    println!("FFT size: {}, HOP_SIZE: {}", spectrograms[0].config.fft_size, spectrograms[0].config.hop_size);
+    Ok(spectrograms_to_audio(&spectrograms, metadata, progress)?)
+}
+
+/// Resynthesize the channel spectrograms of an image (e.g. after editing them) into the samples
+/// `decode_spectrogram` would return, with the channel mode and level of `metadata`
+pub fn spectrograms_to_audio(
+    spectrograms: &[Spectrogram],
+    metadata: &SpectrogramMetadata,
+    progress: &Progress,
+) -> Result<AudioData, Cancelled> {
+    progress.status("Reconstructing audio...");
+    let mut signals = Vec::with_capacity(spectrograms.len());
+    for spectrogram in spectrograms {
+        signals.push(synthesize_with_progress(spectrogram, progress)?);
     }
-    
-    let spec = hound::WavSpec {
-        channels: 1,
-        sample_rate: sample_rate,
-        bits_per_sample: 16,
-        sample_format: hound::SampleFormat::Int,
-    };
-    
-    let mut writer = hound::WavWriter::create(output_path, spec)?;
-    for &sample in output.iter() {
-        let sample_i16 = (sample * i16::MAX as f32) as i16;
-        writer.write_sample(sample_i16)?;
+    Ok(join_channels(signals, metadata))
+}
+
+// Join decoded channel signals into output channels at the output level
+fn join_channels(signals: Vec<Vec<f32>>, metadata: &SpectrogramMetadata) -> AudioData {
+    let mut channels = metadata.config.channel_mode.join(signals);
+    apply_output_level(&mut channels, metadata.sample_rate, &metadata.config, metadata.peak_magnitude.is_some());
+    AudioData { channels, sample_rate: metadata.sample_rate }
+}
+
+// Read one image per encoded channel: the sibling `_CH{n}` images, or the bands of a stacked image
+pub(crate) fn read_bands(image_path: &Path, metadata: &SpectrogramMetadata) -> Result<Vec<Rgb32FImage>, Box<dyn std::error::Error>> {
+    if metadata.channels > 1 && !metadata.config.stack_channels {
+        (0..metadata.channels)
+            .map(|channel| {
+                let path = sibling_channel_path(image_path, metadata.channel, channel);
+                println!("Reading channel {} from: {}", channel + 1, path.display());
+                open_image(&path)
+                    .map_err(|e| format!("Could not read channel image {}: {}", path.display(), e).into())
+            })
+            .collect()
+    } else {
+        let img = open_image(image_path)?;
+        println!("Image size: {}x{}", img.width(), img.height());
+        let band_height = img.height() / metadata.channels as u32;
+        Ok((0..metadata.channels as u32)
+            .map(|channel| image::imageops::crop_imm(&img, 0, channel * band_height, img.width(), band_height).to_image())
+            .collect())
     }
-    writer.finalize()?;
-    
-    println!("Saved audio to: {}", output_path.display());
-    Ok(())
 }
 
-fn rgb_to_hsv(r: u8, g: u8, b: u8) -> (f32, f32, f32) {
-    let r = r as f32 / 255.0;
-    let g = g as f32 / 255.0;
-    let b = b as f32 / 255.0;
-    
+// Read an image at full precision, recognizing the format by its contents
+pub(crate) fn open_image(path: &Path) -> Result<Rgb32FImage, Box<dyn std::error::Error>> {
+    Ok(image::ImageReader::open(path)?.with_guessed_format()?.decode()?.to_rgb32f())
+}
+
+// Path of another channel's image, given the path of the image holding `channel`
+pub(crate) fn sibling_channel_path(image_path: &Path, channel: usize, other: usize) -> PathBuf {
+    let stem = image_path.file_stem().unwrap_or_default().to_string_lossy();
+    let suffix = format!("_CH{}", channel + 1);
+    let base = stem.strip_suffix(suffix.as_str()).unwrap_or(&stem);
+    let extension = image_path.extension().unwrap_or_default().to_string_lossy();
+    image_path.with_file_name(format!("{}_CH{}.{}", base, other + 1, extension))
+}
+
+fn rgb_to_hsv(r: f32, g: f32, b: f32) -> (f32, f32, f32) {
     let max = r.max(g).max(b);
     let min = r.min(g).min(b);
     let delta = max - min;
-    
+
     let h = if delta == 0.0 {
         0.0
     } else if max == r {
@@ -347,46 +252,10 @@ fn rgb_to_hsv(r: u8, g: u8, b: u8) -> (f32, f32, f32) {
     } else {
         60.0 * (((r - g) / delta) + 4.0)
     };
-    
+
     let h = if h < 0.0 { h + 360.0 } else { h };
     let s = if max == 0.0 { 0.0 } else { delta / max };
     let v = max;
-    
-    (h, s, v)
-}
-
-fn interpolate_spectrum(
-    mag: &Vec<Vec<f32>>,
-    phase: &Vec<Vec<f32>>,
-    bin_float: f32,
-    frame: usize,
-) -> (f32, f32) {
-    let bin_floor = bin_float.floor() as usize;
-    let bin_ceil = (bin_float.ceil() as usize).min(mag.len() - 1);
-    let frac = bin_float - bin_floor as f32;
-
-    if bin_floor >= mag.len() {
-        return (0.0, 0.0);
-    }
-
-    if bin_floor == bin_ceil {
-        return (mag[bin_floor][frame], phase[bin_floor][frame]);
-    }
-
-    let mag_interp = mag[bin_floor][frame] * (1.0 - frac) + mag[bin_ceil][frame] * frac;
 
-    let phase1 = phase[bin_floor][frame];
-    let phase2 = phase[bin_ceil][frame];
-    let mut phase_diff = phase2 - phase1;
-
-    while phase_diff > std::f32::consts::PI {
-        phase_diff -= 2.0 * std::f32::consts::PI;
-    }
-    while phase_diff < -std::f32::consts::PI {
-        phase_diff += 2.0 * std::f32::consts::PI;
-    }
-
-    let phase_interp = phase1 + phase_diff * frac;
-
-    (mag_interp, phase_interp)
+    (h, s, v)
 }
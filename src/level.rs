@@ -0,0 +1,391 @@
+use std::collections::VecDeque;
+use crate::config::{LevelMode, PeakProtection, SpectrogramConfig};
+
+// Peak that normalization scales the output to
+const NORMALIZE_PEAK: f32 = 0.95;
+
+// Oversampling factor and half-length (taps per side) of the true-peak interpolator
+const OVERSAMPLING: usize = 4;
+const INTERPOLATION_TAPS: usize = 8;
+
+// Limiter lookahead and release times (seconds)
+const LIMITER_LOOKAHEAD: f32 = 0.0015;
+const LIMITER_RELEASE: f32 = 0.05;
+
+/// Bring decoded channels to their output level
+/// `has_reference` tells whether the samples are at the encoded file's absolute level;
+/// without it "original" level falls back to normalization
+pub fn apply_output_level(channels: &mut [Vec<f32>], sample_rate: u32, config: &SpectrogramConfig, has_reference: bool) {
+    let mut meter = PeakMeter::new(channels.len(), config);
+    meter.push(channels);
+    let mut level = OutputLevel::new(channels.len(), sample_rate, config, has_reference, Some(meter.finish()));
+
+    let input: Vec<Vec<f32>> = channels.iter_mut().map(std::mem::take).collect();
+    let mut output = level.process(input);
+    for (output, rest) in output.iter_mut().zip(level.finish()) {
+        output.extend(rest);
+    }
+    for (channel, output) in channels.iter_mut().zip(output) {
+        *channel = output;
+    }
+}
+
+/// Peaks of a whole decoded signal, which the output level may depend on
+#[derive(Debug, Clone, Copy)]
+pub struct Peaks {
+    /// Largest absolute sample value across all channels
+    pub sample: f32,
+    /// Largest true peak (0 unless the peak protection scales to it)
+    pub true_peak: f32,
+}
+
+/// Peaks of decoded audio arriving a block of per-channel samples at a time
+pub struct PeakMeter {
+    sample: f32,
+    true_peak: f32,
+    // Only measured for peak protection that scales to it
+    envelopes: Option<Vec<Envelope>>,
+}
+
+impl PeakMeter {
+    pub fn new(num_channels: usize, config: &SpectrogramConfig) -> Self {
+        let measure_true_peak = config.peak_protection == PeakProtection::Scale;
+        Self {
+            sample: 0.0,
+            true_peak: 0.0,
+            envelopes: measure_true_peak.then(|| (0..num_channels).map(|_| Envelope::new()).collect()),
+        }
+    }
+
+    pub fn push(&mut self, channels: &[Vec<f32>]) {
+        self.sample = self.sample.max(sample_peak(channels));
+        if let Some(envelopes) = &mut self.envelopes {
+            for (envelope, channel) in envelopes.iter_mut().zip(channels) {
+                self.true_peak = envelope.push(channel).into_iter().fold(self.true_peak, f32::max);
+            }
+        }
+    }
+
+    pub fn finish(mut self) -> Peaks {
+        for envelope in self.envelopes.iter_mut().flatten() {
+            self.true_peak = envelope.finish().into_iter().fold(self.true_peak, f32::max);
+        }
+        Peaks { sample: self.sample, true_peak: self.true_peak }
+    }
+}
+
+/// Output level applied to decoded audio a block of per-channel samples at a time, so it can be
+/// written as it is decoded
+/// Normalizing, and scaling down to the peak ceiling, depend on the peaks of the whole signal:
+/// when `needs_peaks` says so, measure them with a [`PeakMeter`] before passing the blocks through.
+/// The limiter holds back its lookahead, which `finish` returns.
+pub struct OutputLevel {
+    gain: f32,
+    limiter: Option<Limiter>,
+}
+
+impl OutputLevel {
+    /// Whether the level depends on the peaks of the whole signal
+    pub fn needs_peaks(config: &SpectrogramConfig, has_reference: bool) -> bool {
+        let restores_level = config.level_mode == LevelMode::Original && has_reference;
+        !restores_level || config.peak_protection == PeakProtection::Scale
+    }
+
+    /// `peaks` are those of the whole signal, needed when `needs_peaks` is true
+    pub fn new(num_channels: usize, sample_rate: u32, config: &SpectrogramConfig, has_reference: bool, peaks: Option<Peaks>) -> Self {
+        let mut gain = 1.0;
+        let measured = peaks.is_some();
+        let peaks = peaks.unwrap_or(Peaks { sample: 0.0, true_peak: 0.0 });
+        match config.level_mode {
+            LevelMode::Original if has_reference => {
+                if measured {
+                    println!("Restoring original level (peak {:.2} dBFS)", to_db(peaks.sample));
+                } else {
+                    println!("Restoring original level");
+                }
+            }
+            level_mode => {
+                if level_mode == LevelMode::Original {
+                    println!("Image has no reference magnitude - normalizing instead of restoring original level");
+                }
+                // One gain for all channels keeps their balance
+                if peaks.sample > 1e-8 {
+                    gain = NORMALIZE_PEAK / peaks.sample;
+                }
+            }
+        }
+
+        let ceiling = from_db(config.peak_ceiling_db);
+        let mut limiter = None;
+        match config.peak_protection {
+            PeakProtection::None => {}
+            PeakProtection::Scale => {
+                let peak = peaks.true_peak * gain;
+                if peak > ceiling {
+                    println!("True peak {:.2} dBTP above ceiling, lowering level by {:.2} dB",
+                             to_db(peak), to_db(peak) - config.peak_ceiling_db);
+                    gain *= ceiling / peak;
+                }
+            }
+            PeakProtection::Limit => limiter = Some(Limiter::new(num_channels, sample_rate, ceiling)),
+        }
+        Self { gain, limiter }
+    }
+
+    /// Bring the next block to the output level
+    /// With the limiter, the samples returned lag those given by its lookahead
+    pub fn process(&mut self, mut channels: Vec<Vec<f32>>) -> Vec<Vec<f32>> {
+        if self.gain != 1.0 {
+            scale(&mut channels, self.gain);
+        }
+        match &mut self.limiter {
+            Some(limiter) => limiter.push(&channels),
+            None => channels,
+        }
+    }
+
+    /// Samples still held back by the limiter
+    pub fn finish(&mut self) -> Vec<Vec<f32>> {
+        match &mut self.limiter {
+            Some(limiter) => {
+                let rest = limiter.finish();
+                let reduction = limiter.reduction_db();
+                if reduction > 0.0 {
+                    println!("True-peak limiter: up to {:.2} dB gain reduction", reduction);
+                }
+                rest
+            }
+            None => Vec::new(),
+        }
+    }
+}
+
+/// Largest absolute sample value across all channels
+pub fn sample_peak(channels: &[Vec<f32>]) -> f32 {
+    channels.iter()
+        .flat_map(|channel| channel.iter())
+        .map(|&x| x.abs())
+        .fold(0.0f32, f32::max)
+}
+
+/// Largest absolute value across all channels including inter-sample peaks (4x oversampled)
+pub fn true_peak(channels: &[Vec<f32>]) -> f32 {
+    channels.iter()
+        .flat_map(|channel| true_peak_envelope(channel))
+        .fold(0.0f32, f32::max)
+}
+
+/// Lookahead limiter keeping the true peak of all channels (linked) at or below `ceiling`
+/// Returns the largest gain reduction applied in dB
+pub fn limit_true_peak(channels: &mut [Vec<f32>], sample_rate: u32, ceiling: f32) -> f32 {
+    let mut limiter = Limiter::new(channels.len(), sample_rate, ceiling);
+    let mut output = limiter.push(channels);
+    for (output, rest) in output.iter_mut().zip(limiter.finish()) {
+        output.extend(rest);
+    }
+    for (channel, output) in channels.iter_mut().zip(output) {
+        *channel = output;
+    }
+    limiter.reduction_db()
+}
+
+/// Lookahead true-peak limiter (linked across channels) run a block at a time
+/// Its output lags its input by the lookahead and the interpolator's taps; `finish` flushes it.
+pub struct Limiter {
+    ceiling: f32,
+    lookahead: usize,
+    release: f32,
+    envelopes: Vec<Envelope>,
+    // Gain each sample needs on its own, from the next sample whose gain is computed on
+    required: VecDeque<f32>,
+    // The last lookahead + 1 held gains, their sum, and how many gains were computed
+    held: VecDeque<f32>,
+    sum: f32,
+    position: usize,
+    // Gain after release smoothing, and the lowest it went
+    current: f32,
+    lowest: f32,
+    // Samples waiting for their gain, per channel
+    pending: Vec<VecDeque<f32>>,
+}
+
+impl Limiter {
+    pub fn new(num_channels: usize, sample_rate: u32, ceiling: f32) -> Self {
+        Self {
+            ceiling,
+            lookahead: ((LIMITER_LOOKAHEAD * sample_rate as f32) as usize).max(1),
+            release: 1.0 - (-1.0 / (LIMITER_RELEASE * sample_rate as f32)).exp(),
+            envelopes: (0..num_channels).map(|_| Envelope::new()).collect(),
+            required: VecDeque::new(),
+            held: VecDeque::new(),
+            sum: 0.0,
+            position: 0,
+            current: 1.0,
+            lowest: 1.0,
+            pending: vec![VecDeque::new(); num_channels],
+        }
+    }
+
+    /// Limit the next block (one Vec per channel), returning the samples whose gain is known
+    pub fn push(&mut self, channels: &[Vec<f32>]) -> Vec<Vec<f32>> {
+        let len = channels.iter().map(|c| c.len()).min().unwrap_or(0);
+        for (pending, channel) in self.pending.iter_mut().zip(channels) {
+            pending.extend(&channel[..len]);
+        }
+        let peaks: Vec<Vec<f32>> = self.envelopes.iter_mut().zip(channels).map(|(envelope, channel)| envelope.push(&channel[..len])).collect();
+        self.require(&peaks);
+        self.apply(false)
+    }
+
+    /// The samples still held back
+    pub fn finish(&mut self) -> Vec<Vec<f32>> {
+        let peaks: Vec<Vec<f32>> = self.envelopes.iter_mut().map(Envelope::finish).collect();
+        self.require(&peaks);
+        self.apply(true)
+    }
+
+    /// Largest gain reduction applied so far, in dB
+    pub fn reduction_db(&self) -> f32 {
+        -to_db(self.lowest)
+    }
+
+    // Queue the gain each sample needs on its own, linked across channels
+    fn require(&mut self, peaks: &[Vec<f32>]) {
+        let len = peaks.iter().map(Vec::len).min().unwrap_or(0);
+        for i in 0..len {
+            let mut gain = 1.0f32;
+            for channel in peaks {
+                if channel[i] > self.ceiling {
+                    gain = gain.min(self.ceiling / channel[i]);
+                }
+            }
+            self.required.push_back(gain);
+        }
+    }
+
+    // Apply the gain to every sample whose lookahead is known (all of them at the end)
+    fn apply(&mut self, end: bool) -> Vec<Vec<f32>> {
+        let lookahead = self.lookahead;
+        let mut output: Vec<Vec<f32>> = vec![Vec::new(); self.pending.len()];
+        while self.required.len() > lookahead || (end && !self.required.is_empty()) {
+            // Hold the minimum over the lookahead window so the gain is down before each peak,
+            // then average over the same window to ramp into it smoothly
+            let held = self.required.iter().take(lookahead + 1).cloned().fold(1.0f32, f32::min);
+            self.required.pop_front();
+            self.held.push_back(held);
+            self.sum += held;
+            if self.position > lookahead {
+                self.sum -= self.held.pop_front().unwrap_or(0.0);
+            }
+            // Samples before the start count as the first held gain (the front of the window until
+            // it fills), so files that start loud are turned down from their first sample
+            let missing = lookahead.saturating_sub(self.position) as f32 * self.held[0];
+            let gain = (self.sum + missing) / (lookahead + 1) as f32;
+            self.position += 1;
+
+            // Exponential release back towards unity
+            self.current = gain.min(self.current + (1.0 - self.current) * self.release);
+            self.lowest = self.lowest.min(self.current);
+            for (output, pending) in output.iter_mut().zip(&mut self.pending) {
+                output.push(pending.pop_front().unwrap_or(0.0) * self.current);
+            }
+        }
+        output
+    }
+}
+
+// Per-sample true peak: max of |x[i]| and the interpolated values between x[i] and x[i + 1]
+fn true_peak_envelope(samples: &[f32]) -> Vec<f32> {
+    let mut envelope = Envelope::new();
+    let mut peaks = envelope.push(samples);
+    peaks.extend(envelope.finish());
+    peaks
+}
+
+// True-peak envelope of a signal arriving a block at a time; each position is evaluated once the
+// samples its interpolation reaches have arrived
+struct Envelope {
+    // Hann-windowed sinc coefficients for each fractional position
+    kernels: Vec<Vec<f32>>,
+    // Samples from index `start` of the signal on: the history the next positions reach back to,
+    // and the positions not evaluated yet from index `next` on
+    samples: Vec<f32>,
+    start: usize,
+    next: usize,
+}
+
+impl Envelope {
+    fn new() -> Self {
+        let taps = INTERPOLATION_TAPS as isize;
+        let kernels = (1..OVERSAMPLING)
+            .map(|phase| {
+                let frac = phase as f32 / OVERSAMPLING as f32;
+                (-taps + 1..=taps)
+                    .map(|k| {
+                        let x = k as f32 - frac;
+                        let sinc = if x.abs() < 1e-6 { 1.0 } else { (std::f32::consts::PI * x).sin() / (std::f32::consts::PI * x) };
+                        let window = 0.5 * (1.0 + (std::f32::consts::PI * x / taps as f32).cos());
+                        sinc * window
+                    })
+                    .collect()
+            })
+            .collect();
+        Self { kernels, samples: Vec::new(), start: 0, next: 0 }
+    }
+
+    // Envelope of the positions the new samples complete
+    fn push(&mut self, samples: &[f32]) -> Vec<f32> {
+        self.samples.extend_from_slice(samples);
+        let available = self.start + self.samples.len();
+        let peaks = self.evaluate(available.saturating_sub(INTERPOLATION_TAPS));
+
+        // Keep the history the next position reaches back to
+        let keep_from = self.next.saturating_sub(INTERPOLATION_TAPS - 1).max(self.start);
+        self.samples.drain(..keep_from - self.start);
+        self.start = keep_from;
+        peaks
+    }
+
+    // Envelope of the last positions, with silence after the end
+    fn finish(&mut self) -> Vec<f32> {
+        self.evaluate(self.start + self.samples.len())
+    }
+
+    // Evaluate positions up to `end`, treating samples outside the signal as silence
+    fn evaluate(&mut self, end: usize) -> Vec<f32> {
+        let taps = INTERPOLATION_TAPS as isize;
+        let len = (self.start + self.samples.len()) as isize;
+        let positions = self.next..end.max(self.next);
+        self.next = positions.end;
+        positions
+            .map(|i| {
+                let mut peak = self.samples[i - self.start].abs();
+                for kernel in &self.kernels {
+                    let mut value = 0.0f32;
+                    for (k, &coeff) in (-taps + 1..=taps).zip(kernel) {
+                        let j = i as isize + k;
+                        if j >= 0 && j < len {
+                            value += self.samples[j as usize - self.start] * coeff;
+                        }
+                    }
+                    peak = peak.max(value.abs());
+                }
+                peak
+            })
+            .collect()
+    }
+}
+
+fn scale(channels: &mut [Vec<f32>], gain: f32) {
+    for sample in channels.iter_mut().flat_map(|channel| channel.iter_mut()) {
+        *sample *= gain;
+    }
+}
+
+fn to_db(gain: f32) -> f32 {
+    20.0 * gain.max(1e-10).log10()
+}
+
+fn from_db(db: f32) -> f32 {
+    10.0f32.powf(db / 20.0)
+}
@@ -0,0 +1,41 @@
+//! Bidirectional audio ↔ spectrogram image conversion
+//!
+//! The in-memory API works on sample buffers and [`Spectrogram`] values:
+//! [`analyze`] turns mono samples into a spectrogram, [`synthesize`] turns it back into samples,
+//! and [`spectrogram_to_image`] / [`image_to_spectrogram`] convert to and from the image encoding.
+//! [`Edit`]s change the magnitudes and phases of a spectrogram directly, grouped in [`Layers`] with
+//! undo/redo, and [`spectrograms_to_audio`] resynthesizes the edited channels. A [`Project`] saves
+//! the analysis, its layers and export settings in a folder that can be rendered again later.
+//! [`audio_to_spectrogram`] and [`spectrogram_to_audio`] wrap these for audio files (WAV, FLAC, MP3, Ogg Vorbis, AIFF) and PNG/TIFF/OpenEXR images, writing WAV or FLAC.
+//! [`batch`] converts many files and folders at once on a pool of worker threads.
+//! [`preset`] keeps named settings, built in or saved in the user's configuration folder.
+
+pub mod audio_io;
+pub mod audio_to_image;
+pub mod batch;
+pub mod config;
+pub mod convert;
+pub mod edit;
+pub mod flac;
+pub mod image_to_audio;
+pub mod level;
+pub mod metadata;
+pub mod phase_retrieval;
+pub mod preset;
+pub mod progress;
+pub mod project;
+pub mod spectrogram;
+pub mod stft;
+pub mod streaming;
+
+pub use audio_io::{read_audio, read_wav, write_audio, write_wav, AudioData, AudioFormat};
+pub use audio_to_image::{audio_to_spectrogram, encoded_image_path, spectrogram_to_image};
+pub use config::{BitDepth, ChannelMode, FrequencyScale, ImageFormat, LevelMode, OutputFormat, Padding, PeakProtection, PhaseInit, PhaseRetrieval, SpectrogramConfig, WindowFunction};
+pub use edit::{Change, Edit, Layer, Layers, Operation, Region};
+pub use image_to_audio::{decode_spectrogram, image_to_spectrogram, spectrogram_to_audio, spectrograms_to_audio};
+pub use metadata::SpectrogramMetadata;
+pub use progress::{CancelToken, Cancelled, Progress};
+pub use project::Project;
+pub use spectrogram::{analyze, synthesize, Spectrogram};
+pub use stft::Window;
+pub use streaming::TileManifest;
@@ -1,38 +1,101 @@
 use eframe::egui;
 use rfd::FileDialog;
-use std::path::{Path, PathBuf};
+use clap::Parser;
+use std::path::PathBuf;
+use std::process::ExitCode;
 use std::sync::{Arc, Mutex};
 use std::thread;
-use hound;
 
-mod audio_to_image;
-mod image_to_audio;
-mod config;
+use spectrogram_converter::convert::{get_output_info, process_file, InputKind};
+use spectrogram_converter::preset::Presets;
+use spectrogram_converter::project::{is_project, PROJECT_EXTENSION};
+use spectrogram_converter::streaming::is_tile_manifest;
+use spectrogram_converter::{
+    decode_spectrogram, read_audio, spectrograms_to_audio, write_audio, AudioData, BitDepth, CancelToken, Cancelled,
+    ChannelMode, Progress, SpectrogramConfig,
+};
 
-use audio_to_image::audio_to_spectrogram;
-use image_to_audio::spectrogram_to_audio;
-use config::SpectrogramConfig;
+mod cli;
+mod editor;
+mod playback;
+mod queue;
+mod viewer;
+
+use playback::{Player, Track};
+use queue::BatchQueue;
+use viewer::SpectrogramViewer;
 
 #[derive(Clone)]
 enum ProcessingState {
     Idle,
-    Processing { status: String },
-    Complete { output_path: PathBuf },
+    /// `fraction` is the completed part of the current step, when known
+    Processing { status: String, fraction: Option<f32> },
+    /// `reconstruction` is where the decoded result comes from, for playback
+    Complete { output_path: PathBuf, reconstruction: Option<Reconstruction> },
+    Cancelled,
     Error { message: String },
 }
 
+// Decoded result of a job: already in memory, or a file (audio, or an image to decode) read
+// when it is first played
+#[derive(Clone)]
+enum Reconstruction {
+    Decoded(AudioData),
+    File(PathBuf),
+}
+
+// Loading of a track's audio for playback, on a worker thread
+enum TrackLoad {
+    Idle,
+    Loading { path: PathBuf },
+    Loaded(Result<AudioData, String>),
+}
+
 struct SpectrogramApp {
     selected_file: Option<PathBuf>,
     status_message: String,
     config: SpectrogramConfig,
     show_config: bool,
+    // Named presets, the one the config came from (None for spectrogram_config.toml) and the
+    // name to save the current settings under
+    presets: Option<Presets>,
+    preset_names: Vec<String>,
+    preset: Option<String>,
+    new_preset_name: String,
     processing_state: Arc<Mutex<ProcessingState>>,
+    cancel_token: CancelToken,
+    viewer: Option<SpectrogramViewer>,
+    // File to show in the viewer on the next frame
+    view_request: Option<PathBuf>,
+    player: Player,
+    original_load: Arc<Mutex<TrackLoad>>,
+    reconstruction_load: Arc<Mutex<TrackLoad>>,
+    // File the reconstruction is loaded from when first played
+    reconstruction_file: Option<PathBuf>,
+    // Track to play once it has loaded, and from where
+    play_request: Option<(Track, f32)>,
+    batch: BatchQueue,
 }
 
-fn main() -> Result<(), eframe::Error> {
+fn main() -> ExitCode {
+    let args = cli::Cli::parse();
+    if let Some(command) = args.command {
+        return cli::run(command);
+    }
+
+    match run_gui() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run_gui() -> Result<(), eframe::Error> {
     let options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
-            .with_inner_size([600.0, 500.0])
+            .with_inner_size([900.0, 750.0])
             .with_min_inner_size([500.0, 400.0]),
         ..Default::default()
     };
@@ -54,20 +117,287 @@ impl SpectrogramApp {
         
         config.print_info();
         
+        let presets = Presets::user().map_err(|e| eprintln!("Presets unavailable: {}", e)).ok();
         Self {
             selected_file: None,
             status_message: String::new(),
             config,
             show_config: false,
+            preset_names: presets.as_ref().map(Presets::names).unwrap_or_default(),
+            presets,
+            preset: None,
+            new_preset_name: String::new(),
             processing_state: Arc::new(Mutex::new(ProcessingState::Idle)),
+            cancel_token: CancelToken::new(),
+            viewer: None,
+            view_request: None,
+            player: Player::new(),
+            original_load: Arc::new(Mutex::new(TrackLoad::Idle)),
+            reconstruction_load: Arc::new(Mutex::new(TrackLoad::Idle)),
+            reconstruction_file: None,
+            play_request: None,
+            batch: BatchQueue::new(),
+        }
+    }
+    
+    // Select a single file or project; several files, or a folder, go to the batch queue
+    fn open_paths(&mut self, paths: Vec<PathBuf>) {
+        if let [path] = paths.as_slice() {
+            if !path.is_dir() || is_project(path) {
+                self.select_file(path.clone());
+                return;
+            }
+        }
+        match self.batch.add(&paths) {
+            Ok(added) => self.status_message = format!("✓ Queued {} files", added),
+            Err(e) => self.status_message = format!("✗ Could not queue files: {}", e),
+        }
+    }
+    
+    fn select_file(&mut self, path: PathBuf) {
+        self.status_message = String::new(); // Clear previous status
+        self.view_request = Some(path.clone());
+        
+        // Audio files become the original for playback, and the last reconstruction no longer matches
+        self.set_reconstruction(None);
+        if Player::is_supported() && matches!(InputKind::from_path(&path), Ok(InputKind::Audio)) {
+            self.player.set_track(Track::Original, None);
+            self.load_track(Track::Original, path.clone());
+        }
+        self.selected_file = Some(path);
+    }
+    
+    fn track_load(&self, track: Track) -> &Arc<Mutex<TrackLoad>> {
+        match track {
+            Track::Original => &self.original_load,
+            Track::Reconstruction => &self.reconstruction_load,
+        }
+    }
+    
+    // Read (or for images, decode) `path` on a worker thread as the audio of `track`
+    fn load_track(&mut self, track: Track, path: PathBuf) {
+        let load = self.track_load(track).clone();
+        *load.lock().unwrap() = TrackLoad::Loading { path: path.clone() };
+        let config = self.config.clone();
+        thread::spawn(move || {
+            let audio = match InputKind::from_path(&path) {
+                Ok(InputKind::Image) => decode_spectrogram(&path, &config, &Progress::none()),
+                _ => read_audio(&path),
+            };
+            // Files selected since are loaded instead
+            let mut load = load.lock().unwrap();
+            if matches!(&*load, TrackLoad::Loading { path: loading } if *loading == path) {
+                *load = TrackLoad::Loaded(audio.map_err(|e| e.to_string()));
+            }
+        });
+    }
+    
+    // Replace the reconstruction; files are only read once it is played
+    fn set_reconstruction(&mut self, reconstruction: Option<Reconstruction>) {
+        *self.reconstruction_load.lock().unwrap() = TrackLoad::Idle;
+        if self.play_request.is_some_and(|(track, _)| track == Track::Reconstruction) {
+            self.play_request = None;
+        }
+        self.reconstruction_file = None;
+        match reconstruction {
+            Some(Reconstruction::Decoded(audio)) => self.player.set_track(Track::Reconstruction, Some(audio)),
+            Some(Reconstruction::File(path)) => {
+                self.player.set_track(Track::Reconstruction, None);
+                self.reconstruction_file = Some(path);
+            }
+            None => self.player.set_track(Track::Reconstruction, None),
+        }
+    }
+    
+    // Whether `track` has audio, or a file to load it from
+    fn can_play(&self, track: Track) -> bool {
+        self.player.track(track).is_some() || (track == Track::Reconstruction && self.reconstruction_file.is_some())
+    }
+    
+    // Play `track` from `seconds`, loading it first if it is not in memory yet
+    fn play(&mut self, track: Track, seconds: f32) {
+        if self.player.track(track).is_none() {
+            if let Some(path) = self.reconstruction_file.clone().filter(|_| track == Track::Reconstruction) {
+                if !matches!(&*self.reconstruction_load.lock().unwrap(), TrackLoad::Loading { .. }) {
+                    self.status_message = format!("Decoding {} for playback...", path.display());
+                    self.load_track(track, path);
+                }
+                self.play_request = Some((track, seconds));
+            }
+            return;
+        }
+        if let Err(e) = self.player.play(track, seconds) {
+            self.status_message = format!("✗ {}", e);
+        }
+    }
+    
+    // Take the audio of a track whose loading finished, playing it if that was asked for
+    fn poll_track_load(&mut self, ctx: &egui::Context, track: Track) {
+        let load = self.track_load(track).clone();
+        let mut load = load.lock().unwrap();
+        match std::mem::replace(&mut *load, TrackLoad::Idle) {
+            TrackLoad::Loaded(Ok(audio)) => {
+                drop(load);
+                self.player.set_track(track, Some(audio));
+                if let Some((requested, seconds)) = self.play_request.filter(|&(requested, _)| requested == track) {
+                    self.play_request = None;
+                    // Switching tracks continues from where the other one is now
+                    let seconds = self.player.position().unwrap_or(seconds);
+                    self.status_message = String::new();
+                    self.play(requested, seconds);
+                }
+            }
+            TrackLoad::Loaded(Err(e)) => {
+                drop(load);
+                self.status_message = format!("✗ Could not load audio for playback: {}", e);
+                if self.play_request.is_some_and(|(requested, _)| requested == track) {
+                    self.play_request = None;
+                }
+                if track == Track::Reconstruction {
+                    self.reconstruction_file = None;
+                }
+            }
+            loading @ TrackLoad::Loading { .. } => {
+                *load = loading;
+                ctx.request_repaint();
+            }
+            TrackLoad::Idle => {}
+        }
+    }
+    
+    // Run `job` on a worker thread, reporting its progress and outcome through `processing_state`
+    // The job returns the path it wrote and, for playback, where its decoded result comes from
+    fn start_job<F>(&mut self, job: F)
+    where
+        F: FnOnce(&Progress) -> Result<(PathBuf, Option<Reconstruction>), Box<dyn std::error::Error>> + Send + 'static,
+    {
+        self.status_message = String::new(); // Clear previous status
+        let state = self.processing_state.clone();
+        self.cancel_token = CancelToken::new();
+        let cancel_token = self.cancel_token.clone();
+        
+        // Mark as processing before the thread starts so a job can't be started twice
+        *state.lock().unwrap() = ProcessingState::Processing {
+            status: "Processing...".to_string(),
+            fraction: None,
+        };
+        
+        // Start processing in background thread
+        thread::spawn(move || {
+            let report = |status: &str, fraction: Option<f32>| {
+                *state.lock().unwrap() = ProcessingState::Processing {
+                    status: status.to_string(),
+                    fraction,
+                };
+            };
+            let progress = Progress::new(&report, cancel_token);
+            
+            match job(&progress) {
+                Ok((output_path, reconstruction)) => {
+                    *state.lock().unwrap() = ProcessingState::Complete { output_path, reconstruction };
+                }
+                Err(e) if e.downcast_ref::<Cancelled>().is_some() => {
+                    *state.lock().unwrap() = ProcessingState::Cancelled;
+                }
+                Err(e) => {
+                    *state.lock().unwrap() = ProcessingState::Error {
+                        message: e.to_string(),
+                    };
+                }
+            }
+        });
+    }
+    
+    fn export(&mut self, path: PathBuf) {
+        let config = self.config.clone();
+        self.start_job(move |progress| {
+            // The written audio, or the written image decoded when it is first played; tiled
+            // images would be decoded whole into memory, so they are not played
+            let output_path = process_file(&path, None, &config, progress)?;
+            let reconstruction = (Player::is_supported() && !is_tile_manifest(&output_path))
+                .then(|| Reconstruction::File(output_path.clone()));
+            Ok((output_path, reconstruction))
+        });
+    }
+    
+    // Resynthesize the edited spectrogram into `<image>_edited.wav` (or .flac) and make it the reconstruction
+    // Projects are saved, then rendered as their export settings say
+    fn render_edit(&mut self) {
+        let Some(viewer) = &self.viewer else { return };
+        if let Some(dir) = viewer.project_path().map(PathBuf::from) {
+            if !self.save_project() {
+                return;
+            }
+            let Some(viewer) = &self.viewer else { return };
+            let mut project = viewer.to_project();
+            project.metadata.config.apply_decoder_settings(&self.config);
+            self.start_job(move |progress| {
+                let mut written = project.render(&dir, progress)?;
+                let (_, audio_path) = project.export_paths(&dir);
+                let reconstruction = audio_path.filter(|_| Player::is_supported()).map(Reconstruction::File);
+                Ok((written.pop().expect("rendering writes at least one file"), reconstruction))
+            });
+            return;
+        }
+        let spectrograms = viewer.spectrograms().to_vec();
+        let metadata = viewer.metadata().clone();
+        let output_path = edited_audio_path(viewer.image_path(), &self.config);
+        let config = self.config.clone();
+        self.start_job(move |progress| {
+            let audio = spectrograms_to_audio(&spectrograms, &metadata, progress)?;
+            progress.status("Writing audio file...");
+            write_audio(&output_path, &audio.channels, audio.sample_rate, &config)?;
+            Ok((output_path, Some(Reconstruction::Decoded(audio))))
+        });
+    }
+    
+    // Save the viewer's spectrogram and layers to its project, asking where for a new one
+    // Returns whether it was saved
+    fn save_project(&mut self) -> bool {
+        let Some(viewer) = &mut self.viewer else { return false };
+        let dir = match viewer.project_path() {
+            Some(dir) => dir.to_path_buf(),
+            None => {
+                let name = viewer.image_path().file_stem().unwrap_or_default().to_string_lossy();
+                match FileDialog::new().set_file_name(format!("{}.{}", name, PROJECT_EXTENSION)).save_file() {
+                    Some(dir) => dir,
+                    None => return false,
+                }
+            }
+        };
+
+        let mut project = viewer.to_project();
+        project.metadata.config.apply_decoder_settings(&self.config);
+        // Images made from the selected audio file keep it as their source
+        if let Some(path) = self.selected_file.as_ref().filter(|path| matches!(InputKind::from_path(path), Ok(InputKind::Audio))) {
+            project.source = Some(path.clone());
+        }
+        match project.save(&dir) {
+            Ok(()) => {
+                self.status_message = format!("✓ Saved project to: {}", dir.display());
+                viewer.set_project_path(dir);
+                true
+            }
+            Err(e) => {
+                self.status_message = format!("✗ Could not save project: {}", e);
+                false
+            }
         }
     }
     
+    // Load the settings from the selected preset, or spectrogram_config.toml
     fn reload_config(&mut self) {
-        match SpectrogramConfig::load() {
+        let loaded = match (&self.preset, &self.presets) {
+            (Some(name), Some(presets)) => presets.load(name),
+            _ => SpectrogramConfig::load(),
+        };
+        match loaded {
             Ok(config) => {
                 self.config = config;
-                self.status_message = "✓ Config reloaded successfully".to_string();
+                self.status_message = match &self.preset {
+                    Some(name) => format!("✓ Loaded preset {}", name),
+                    None => "✓ Config reloaded successfully".to_string(),
+                };
                 self.config.print_info();
             }
             Err(e) => {
@@ -75,6 +405,23 @@ impl SpectrogramApp {
             }
         }
     }
+    
+    // Save the current settings as the preset named in the preset row, and select it
+    fn save_preset(&mut self) {
+        let Some(presets) = &self.presets else { return };
+        let name = self.new_preset_name.trim().to_string();
+        match presets.save(&name, &self.config) {
+            Ok(path) => {
+                self.status_message = format!("✓ Saved preset to: {}", path.display());
+                self.preset_names = presets.names();
+                self.preset = Some(name);
+                self.new_preset_name.clear();
+            }
+            Err(e) => {
+                self.status_message = format!("✗ Could not save preset: {}", e);
+            }
+        }
+    }
 }
 
 impl Default for SpectrogramApp {
@@ -89,8 +436,19 @@ impl eframe::App for SpectrogramApp {
         let current_state = self.processing_state.lock().unwrap().clone();
         
         match current_state {
-            ProcessingState::Complete { output_path } => {
+            ProcessingState::Complete { output_path, reconstruction } => {
                 self.status_message = format!("✓ Successfully exported to: {}", output_path.display());
+                if Player::is_supported() && is_tile_manifest(&output_path) {
+                    self.status_message.push_str(" (tiled images are not played back; decode them to listen)");
+                }
+                self.set_reconstruction(reconstruction);
+                if matches!(InputKind::from_path(&output_path), Ok(InputKind::Image)) {
+                    self.view_request = Some(output_path);
+                }
+                *self.processing_state.lock().unwrap() = ProcessingState::Idle;
+            }
+            ProcessingState::Cancelled => {
+                self.status_message = "✗ Cancelled".to_string();
                 *self.processing_state.lock().unwrap() = ProcessingState::Idle;
             }
             ProcessingState::Error { message } => {
@@ -103,22 +461,56 @@ impl eframe::App for SpectrogramApp {
             }
             _ => {}
         }
+        // Check the loading of the tracks for playback
+        self.poll_track_load(ctx, Track::Original);
+        self.poll_track_load(ctx, Track::Reconstruction);
+        if self.player.playing().is_some() {
+            // Move the playhead
+            ctx.request_repaint();
+        }
+        
+        // Show selected and exported spectrogram images
+        if let Some(path) = self.view_request.take() {
+            self.viewer = None;
+            if matches!(InputKind::from_path(&path), Ok(InputKind::Image | InputKind::Project)) {
+                match SpectrogramViewer::load(ctx, &path, &self.config) {
+                    Ok(viewer) => self.viewer = Some(viewer),
+                    Err(e) => self.status_message = format!("✗ Could not show spectrogram: {}", e),
+                }
+            }
+        }
         
         egui::CentralPanel::default().show(ctx, |ui| {
             ui.heading("Spectrogram Converter");
             ui.add_space(10.0);
             
-            // Show spinner if processing
-            if let ProcessingState::Processing { status } = &*self.processing_state.lock().unwrap() {
+            // Show progress bar (or spinner while the step's length is unknown) if processing
+            let processing = match &*self.processing_state.lock().unwrap() {
+                ProcessingState::Processing { status, fraction } => Some((status.clone(), *fraction)),
+                _ => None,
+            };
+            if let Some((status, fraction)) = processing {
                 ui.add_space(5.0);
                 ui.horizontal(|ui| {
-                    ui.spinner();
+                    match fraction {
+                        Some(fraction) => {
+                            ui.add(egui::ProgressBar::new(fraction)
+                                .desired_width(200.0)
+                                .show_percentage());
+                        }
+                        None => {
+                            ui.spinner();
+                        }
+                    }
                     ui.label(status);
+                    if ui.add_enabled(!self.cancel_token.is_cancelled(), egui::Button::new("Cancel")).clicked() {
+                        self.cancel_token.cancel();
+                    }
                 });
                 ui.add_space(10.0);
             }
             
-            ui.label("Drop a file here or click to select:");
+            ui.label("Drop files or folders here or click to select:");
             ui.add_space(5.0);
 
             // Config toggle
@@ -132,14 +524,48 @@ impl eframe::App for SpectrogramApp {
                 }
                 
                 if ui.button("📝 Open Config File").clicked() {
-                    if let Err(e) = open::that("spectrogram_config.toml") {
-                        self.status_message = format!("✗ Could not open config file: {}", e);
-                    } else {
-                        self.status_message = "✓ Opened config file in default editor".to_string();
+                    // Saved presets open their own file; built-in ones have none
+                    let file = match (&self.preset, &self.presets) {
+                        (Some(name), Some(presets)) if presets.is_saved(name) => presets.path(name).ok(),
+                        (Some(_), _) => None,
+                        (None, _) => Some(PathBuf::from("spectrogram_config.toml")),
+                    };
+                    match file.map(open::that) {
+                        Some(Ok(())) => self.status_message = "✓ Opened config file in default editor".to_string(),
+                        Some(Err(e)) => self.status_message = format!("✗ Could not open config file: {}", e),
+                        None => self.status_message = "✗ Built-in presets have no file: save it as a preset to change it".to_string(),
                     }
                 }
             });
             
+            // Preset picker, and saving the current settings as a preset
+            if self.presets.is_some() {
+                ui.horizontal(|ui| {
+                    ui.label("Preset:");
+                    let mut selected = self.preset.clone();
+                    egui::ComboBox::from_id_salt("preset")
+                        .selected_text(selected.as_deref().unwrap_or("spectrogram_config.toml"))
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut selected, None, "spectrogram_config.toml");
+                            for name in &self.preset_names {
+                                ui.selectable_value(&mut selected, Some(name.clone()), name);
+                            }
+                        });
+                    if selected != self.preset {
+                        self.preset = selected;
+                        self.reload_config();
+                    }
+                    
+                    ui.add(egui::TextEdit::singleline(&mut self.new_preset_name).hint_text("new preset name").desired_width(140.0));
+                    let name = self.new_preset_name.trim();
+                    let replaces = self.presets.as_ref().is_some_and(|presets| presets.is_saved(name));
+                    let label = if replaces { "💾 Replace Preset" } else { "💾 Save as Preset" };
+                    if ui.add_enabled(!name.is_empty(), egui::Button::new(label)).clicked() {
+                        self.save_preset();
+                    }
+                });
+            }
+            
             if self.show_config {
                 ui.add_space(5.0);
                 egui::ScrollArea::vertical()
@@ -172,8 +598,27 @@ impl eframe::App for SpectrogramApp {
                             });
                             
                             ui.horizontal(|ui| {
-                                ui.label("Min Frequency:");
-                                ui.label(format!("{} Hz", self.config.min_freq));
+                                ui.label("Padding:");
+                                ui.label(self.config.padding.to_string());
+                            });
+                            
+                            ui.horizontal(|ui| {
+                                ui.label("Window:");
+                                ui.label(self.config.window_description());
+                            });
+                            
+                            ui.horizontal(|ui| {
+                                ui.label("Image Height:");
+                                ui.label(format!("{} bins", self.config.image_height()));
+                            });
+                            
+                            ui.horizontal(|ui| {
+                                ui.label("Frequency Range:");
+                                if self.config.max_freq > 0.0 {
+                                    ui.label(format!("{} - {} Hz", self.config.min_freq, self.config.max_freq));
+                                } else {
+                                    ui.label(format!("{} Hz - Nyquist", self.config.min_freq));
+                                }
                             });
                             
                             ui.horizontal(|ui| {
@@ -192,21 +637,63 @@ impl eframe::App for SpectrogramApp {
                             
                             ui.horizontal(|ui| {
                                 ui.label("Frequency Scale:");
-                                ui.label(if self.config.use_log_scale { 
-                                    "Logarithmic (musical)" 
-                                } else { 
-                                    "Linear (technical)" 
-                                });
+                                ui.label(self.config.frequency_scale.to_string());
+                            });
+                            
+                            ui.horizontal(|ui| {
+                                ui.label("Image Format:");
+                                ui.label(self.config.image_format.to_string());
+                                if self.config.tile_width > 0 {
+                                    ui.label(format!("(tiles of {} frames)", self.config.tile_width));
+                                }
+                            });
+                            
+                            ui.horizontal(|ui| {
+                                ui.label("Channels:");
+                                ui.label(self.config.channel_mode.to_string());
+                                if self.config.channel_mode != ChannelMode::Mono {
+                                    ui.label(if self.config.stack_channels {
+                                        "(stacked in one image)"
+                                    } else {
+                                        "(one image per channel)"
+                                    });
+                                }
+                            });
+                            
+                            ui.horizontal(|ui| {
+                                ui.label("Output Level:");
+                                ui.label(self.config.level_mode.to_string());
+                            });
+                            
+                            ui.horizontal(|ui| {
+                                ui.label("Peak Protection:");
+                                ui.label(format!("{} (ceiling {} dBTP)", self.config.peak_protection, self.config.peak_ceiling_db));
+                            });
+                            
+                            ui.horizontal(|ui| {
+                                ui.label("Output Format:");
+                                ui.label(format!("{} {}", self.config.output_format, self.config.bit_depth));
+                                if self.config.dither && self.config.bit_depth == BitDepth::Int16 {
+                                    ui.label("(TPDF dither)");
+                                }
                             });
                             
                             if !self.config.use_phase_encoding {
                                 ui.horizontal(|ui| {
-                                    ui.label("Griffin-Lim Iterations:");
-                                    ui.label(format!("{}", self.config.griffin_lim_iterations));
+                                    ui.label("Phase Retrieval:");
+                                    ui.label(self.config.phase_retrieval_description());
                                 });
                             }
                             
-                            ui.label("Edit spectrogram_config.toml to change these values");
+                            ui.horizontal(|ui| {
+                                ui.label("Threads:");
+                                ui.label(if self.config.num_threads == 0 { "one per CPU core".to_string() } else { self.config.num_threads.to_string() });
+                            });
+                            
+                            ui.label(match &self.preset {
+                                Some(name) => format!("From preset {}; open its file to change these values", name),
+                                None => "Edit spectrogram_config.toml to change these values".to_string(),
+                            });
                         });
                     });
             }
@@ -217,14 +704,19 @@ impl eframe::App for SpectrogramApp {
             let is_processing = matches!(*self.processing_state.lock().unwrap(), ProcessingState::Processing { .. });
             
             ui.add_enabled_ui(!is_processing, |ui| {
-                if ui.button("📁 Select File").clicked() {
+                if ui.button("📁 Select Files").clicked() {
                     // Use non-blocking file dialog
-                    if let Some(path) = FileDialog::new()
-                        .add_filter("Audio/Image", &["wav", "png", "jpg", "jpeg"])
-                        .pick_file()
+                    if let Some(paths) = FileDialog::new()
+                        .add_filter("Audio/Image", &["wav", "flac", "mp3", "ogg", "oga", "opus", "aif", "aiff", "aifc", "png", "jpg", "jpeg", "tif", "tiff", "exr", "toml"])
+                        .pick_files()
                     {
-                        self.selected_file = Some(path);
-                        self.status_message = String::new(); // Clear previous status
+                        self.open_paths(paths);
+                    }
+                }
+                // Projects open; other folders queue their files
+                if ui.button("📂 Open Folder").clicked() {
+                    if let Some(path) = FileDialog::new().pick_folder() {
+                        self.open_paths(vec![path]);
                     }
                 }
             });
@@ -234,7 +726,7 @@ impl eframe::App for SpectrogramApp {
             // Display selected file with scrollable path
             if let Some(ref path) = self.selected_file {
                 egui::ScrollArea::horizontal()
-                    .id_source("file_path_scroll")
+                    .id_salt("file_path_scroll")
                     .show(ui, |ui| {
                         ui.label(format!("Selected: {}", path.display()));
                     });
@@ -243,7 +735,7 @@ impl eframe::App for SpectrogramApp {
                 // Show what the output will be named and estimated size
                 if let Ok((output_path, est_width)) = get_output_info(path, &self.config) {
                     egui::ScrollArea::horizontal()
-                        .id_source("output_path_scroll")
+                        .id_salt("output_path_scroll")
                         .show(ui, |ui| {
                             ui.label(format!("Will export to: {}", output_path.display()));
                         });
@@ -255,69 +747,117 @@ impl eframe::App for SpectrogramApp {
                 ui.add_space(10.0);
                 
                 // Export button
-                ui.add_enabled_ui(!is_processing, |ui| {
-                    if ui.button("🚀 Export").clicked() {
-                        self.status_message = String::new(); // Clear previous status
-                        
-                        let path = path.clone();
-                        let config = self.config.clone();
-                        let state = self.processing_state.clone();
-                        
-                        // Start processing in background thread
-                        thread::spawn(move || {
-                            *state.lock().unwrap() = ProcessingState::Processing {
-                                status: "Processing...".to_string(),
-                            };
-                            
-                            match process_file(&path, &config, state.clone()) {
-                                Ok(output_path) => {
-                                    *state.lock().unwrap() = ProcessingState::Complete { output_path };
-                                }
-                                Err(e) => {
-                                    *state.lock().unwrap() = ProcessingState::Error {
-                                        message: e.to_string(),
-                                    };
-                                }
-                            }
-                        });
-                    }
-                });
-            } else {
+                let export = ui.add_enabled(!is_processing, egui::Button::new("🚀 Export")).clicked();
+                if export {
+                    self.export(path.clone());
+                }
+            } else if self.batch.is_empty() {
                 ui.label("No file selected");
             }
             
+            // Batch queue; clicking a file selects it
+            if !self.batch.is_empty() {
+                ui.separator();
+                if let Some(path) = self.batch.show(ui, &self.config) {
+                    self.select_file(path);
+                }
+            }
+            
             ui.add_space(10.0);
             
             // Status message
             if !self.status_message.is_empty() {
                 ui.separator();
                 egui::ScrollArea::horizontal()
-                    .id_source("status_scroll")
+                    .id_salt("status_scroll")
                     .show(ui, |ui| {
                         ui.label(&self.status_message);
                     });
             }
             
+            // Playback controls: pick a track to play it, or to switch to it at the same position
+            if self.can_play(Track::Original) || self.can_play(Track::Reconstruction) {
+                ui.separator();
+                ui.horizontal(|ui| {
+                    ui.label("Playback:");
+                    let playing = self.player.playing();
+                    for (track, label) in [(Track::Original, "▶ Original"), (Track::Reconstruction, "▶ Reconstruction")] {
+                        let button = egui::SelectableLabel::new(playing == Some(track), label);
+                        if ui.add_enabled(self.can_play(track), button).clicked() {
+                            let position = self.player.position().unwrap_or(0.0);
+                            self.play(track, position);
+                        }
+                    }
+                    let both = self.can_play(Track::Original) && self.can_play(Track::Reconstruction);
+                    if ui.add_enabled(playing.is_some() && both, egui::Button::new("A/B")).clicked() {
+                        if let (Some(track), Some(position)) = (playing, self.player.position()) {
+                            self.play(track.other(), position);
+                        }
+                    }
+                    if ui.add_enabled(playing.is_some(), egui::Button::new("⏹ Stop")).clicked() {
+                        self.player.stop();
+                    }
+                    if let Some(position) = self.player.position() {
+                        ui.label(format!("{:.2} s", position));
+                    }
+                });
+            } else if !Player::is_supported() && self.selected_file.is_some() {
+                ui.label("Playback is not available in this build (build with --features playback)");
+            }
+            
+            // Spectrogram viewer; clicking it plays from there
+            let mut render_edit = false;
+            let mut save_project = false;
+            if let Some(viewer) = &mut self.viewer {
+                ui.separator();
+                if viewer.is_editable() {
+                    ui.horizontal(|ui| {
+                        save_project = ui.add_enabled(!is_processing, egui::Button::new("💾 Save Project")).clicked();
+                        if let Some(dir) = viewer.project_path() {
+                            render_edit = ui.add_enabled(!is_processing, egui::Button::new("🔊 Render Project")).clicked();
+                            ui.label(format!("Saves and renders {} as its export settings say", dir.display()));
+                        } else if viewer.is_edited() {
+                            render_edit = ui.add_enabled(!is_processing, egui::Button::new("🔊 Render Edit")).clicked();
+                            ui.label(format!("Resynthesizes the edited spectrogram into {}", edited_audio_path(viewer.image_path(), &self.config).display()));
+                        }
+                    });
+                }
+                if let Some(seconds) = viewer.show(ui, self.player.position()) {
+                    let track = self.player.playing()
+                        .or_else(|| [Track::Reconstruction, Track::Original].into_iter().find(|&t| self.can_play(t)));
+                    if let Some(track) = track {
+                        self.play(track, seconds);
+                    }
+                }
+            }
+            
+            if save_project {
+                self.save_project();
+            }
+            if render_edit {
+                self.render_edit();
+            }
+            
             // File drop zone
             preview_files_being_dropped(ctx);
             
             // Handle dropped files
             if !is_processing {
-                ctx.input(|i| {
-                    if !i.raw.dropped_files.is_empty() {
-                        if let Some(dropped_file) = i.raw.dropped_files.first() {
-                            if let Some(path) = &dropped_file.path {
-                                self.selected_file = Some(path.clone());
-                                self.status_message = String::new(); // Clear previous status
-                            }
-                        }
-                    }
-                });
+                let dropped: Vec<PathBuf> = ctx.input(|i| i.raw.dropped_files.iter().filter_map(|file| file.path.clone()).collect());
+                if !dropped.is_empty() {
+                    self.open_paths(dropped);
+                }
             }
         });
     }
 }
 
+// Audio path for an edited spectrogram: `<image stem>_edited` with the output format's extension
+fn edited_audio_path(image_path: &std::path::Path, config: &SpectrogramConfig) -> PathBuf {
+    let stem = image_path.file_stem().unwrap_or_default().to_string_lossy();
+    image_path.with_file_name(format!("{}_edited.{}", stem, config.output_format.extension()))
+}
+
 fn preview_files_being_dropped(ctx: &egui::Context) {
     use egui::*;
     
@@ -344,104 +884,3 @@ fn preview_files_being_dropped(ctx: &egui::Context) {
         );
     }
 }
-
-fn get_output_info(
-    path: &PathBuf,
-    config: &SpectrogramConfig,
-) -> Result<(PathBuf, Option<usize>), Box<dyn std::error::Error>> {
-    let extension = path.extension()
-        .and_then(|s| s.to_str())
-        .ok_or("Unable to determine file extension")?
-        .to_lowercase();
-
-    match extension.as_str() {
-        "wav" => {
-            // For WAV files, we need to read the sample rate and calculate estimated width
-            let reader = hound::WavReader::open(path)?;
-            let spec = reader.spec();
-            let sample_rate = spec.sample_rate;
-            let scale_suffix = if config.use_log_scale { "_LOG" } else { "_LIN" };
-            let phase_suffix = if config.use_phase_encoding { "_PHASE" } else { "_MAG" };
-            
-            // Calculate estimated width
-            let total_samples = reader.duration() as usize;
-            let mono_samples = if spec.channels == 2 {
-                total_samples / 2
-            } else {
-                total_samples
-            };
-            let est_width = (mono_samples - config.fft_size) / config.hop_size + 1;
-
-            if let Some(stem) = path.file_stem() {
-                let parent = path.parent().unwrap_or(Path::new(""));
-                let output_path = parent.join(format!("{}_SR{}{}{}.png", stem.to_string_lossy(), sample_rate, scale_suffix, phase_suffix));
-                Ok((output_path, Some(est_width)))
-            } else {
-                Ok((path.with_extension("png"), Some(est_width)))
-            }
-        }
-        "png" | "jpg" | "jpeg" => {
-            Ok((path.with_extension("wav"), None))
-        }
-        _ => Err("Unsupported file format".into())
-    }
-}
-
-fn process_file(
-    path: &PathBuf,
-    config: &SpectrogramConfig,
-    progress_state: Arc<Mutex<ProcessingState>>,
-) -> Result<PathBuf, Box<dyn std::error::Error>> {
-    let extension = path.extension()
-        .and_then(|s| s.to_str())
-        .ok_or("Unable to determine file extension")?
-        .to_lowercase();
-
-    match extension.as_str() {
-        "wav" => {
-            *progress_state.lock().unwrap() = ProcessingState::Processing {
-                status: "Reading audio file...".to_string(),
-            };
-            
-            let output_path = path.with_extension("png");
-            
-            *progress_state.lock().unwrap() = ProcessingState::Processing {
-                status: "Computing spectrogram...".to_string(),
-            };
-            
-            let result = audio_to_spectrogram(path, &output_path, config)?;
-            
-            Ok(result)
-        }
-        "png" | "jpg" | "jpeg" => {
-            *progress_state.lock().unwrap() = ProcessingState::Processing {
-                status: "Reading image file...".to_string(),
-            };
-            
-            let output_path = path.with_extension("wav");
-            
-            // Check if Griffin-Lim will be used
-            let use_griffin_lim = if let Some(stem) = path.file_stem() {
-                let stem_str = stem.to_string_lossy();
-                stem_str.contains("_MAG")
-            } else {
-                false
-            };
-            
-            if use_griffin_lim {
-                *progress_state.lock().unwrap() = ProcessingState::Processing {
-                    status: "Reconstructing audio (Griffin-Lim algorithm running...)".to_string(),
-                };
-            } else {
-                *progress_state.lock().unwrap() = ProcessingState::Processing {
-                    status: "Reconstructing audio...".to_string(),
-                };
-            }
-            
-            spectrogram_to_audio(path, &output_path, config)?;
-            
-            Ok(output_path)
-        }
-        _ => Err("Unsupported file format. Use WAV for audio or PNG/JPG for images.".into())
-    }
-}
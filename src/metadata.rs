@@ -0,0 +1,366 @@
+use image::Rgb32FImage;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read};
+use std::path::Path;
+use crate::config::{ChannelMode, FrequencyScale, ImageFormat, SpectrogramConfig};
+use crate::spectrogram::Spectrogram;
+
+// PNG text chunk keywords
+const KEY_CONFIG: &str = "SpectrogramConfig";
+const KEY_SAMPLE_RATE: &str = "SampleRate";
+const KEY_NUM_SAMPLES: &str = "NumSamples";
+const KEY_PEAK_MAGNITUDE: &str = "PeakMagnitude";
+const KEY_CHANNELS: &str = "Channels";
+const KEY_CHANNEL: &str = "Channel";
+const KEY_TILE: &str = "Tile";
+const KEY_TILES: &str = "Tiles";
+
+// TIFF ImageDescription / OpenEXR attribute holding all metadata as one TOML document
+const KEY_METADATA: &str = "SpectrogramMetadata";
+
+/// Everything the decoder needs to invert an encoded image
+/// Stored in PNG text chunks (or the TIFF description / an OpenEXR attribute) so that
+/// renamed images still decode correctly
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpectrogramMetadata {
+    /// Sample rate of the encoded audio (Hz)
+    pub sample_rate: u32,
+
+    /// Length of the encoded audio in samples
+    #[serde(default)]
+    pub num_samples: usize,
+
+    /// Magnitude that maps to 0 dB in the image (the spectrogram's peak)
+    /// None for legacy images, whose absolute level is unknown
+    pub peak_magnitude: Option<f32>,
+
+    /// Number of encoded channels (image bands or separate images, see `config.stack_channels`)
+    #[serde(default = "default_channels")]
+    pub channels: usize,
+
+    /// Index of the channel held by this image when channels are saved as separate images
+    #[serde(default)]
+    pub channel: usize,
+
+    /// Index of this image in a tiled sequence (`config.tile_width` frames per tile)
+    #[serde(default)]
+    pub tile: usize,
+
+    /// Number of tiles the file was split into (1 for a single image)
+    #[serde(default = "default_tiles")]
+    pub tiles: usize,
+
+    /// Settings the encoder used
+    pub config: SpectrogramConfig,
+}
+
+fn default_channels() -> usize { 1 }
+fn default_tiles() -> usize { 1 }
+
+impl SpectrogramMetadata {
+    /// Metadata describing how a spectrogram will be encoded
+    pub fn from_spectrogram(spectrogram: &Spectrogram) -> Self {
+        Self {
+            sample_rate: spectrogram.sample_rate,
+            num_samples: spectrogram.num_samples,
+            peak_magnitude: Some(spectrogram.max_magnitude()),
+            channels: 1,
+            channel: 0,
+            tile: 0,
+            tiles: 1,
+            config: spectrogram.config.clone(),
+        }
+    }
+
+    /// Read metadata embedded in a PNG, TIFF or OpenEXR file
+    /// Returns Ok(None) for images without metadata (legacy images, JPEGs, stripped by an editor)
+    pub fn read(image_path: &Path) -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        let mut header = [0u8; 8];
+        let read = File::open(image_path)?.read(&mut header)?;
+        let header = &header[..read];
+
+        if header.starts_with(PNG_SIGNATURE) {
+            Self::read_png(image_path)
+        } else if header.starts_with(b"II*\0") || header.starts_with(b"MM\0*") {
+            Self::read_tiff(image_path)
+        } else if header.starts_with(EXR_MAGIC) {
+            Self::read_exr(image_path)
+        } else {
+            // No metadata in other formats
+            Ok(None)
+        }
+    }
+
+    fn read_png(image_path: &Path) -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        let decoder = png::Decoder::new(BufReader::new(File::open(image_path)?)).read_info()?;
+        let info = decoder.info();
+
+        let mut config = None;
+        let mut sample_rate = None;
+        let mut num_samples = None;
+        let mut peak_magnitude = None;
+        let mut channels = None;
+        let mut channel = None;
+        let mut tile = None;
+        let mut tiles = None;
+
+        for chunk in &info.utf8_text {
+            if chunk.keyword == KEY_CONFIG {
+                config = Some(encoder_config(toml::from_str(&chunk.get_text()?)?)?);
+            }
+        }
+        for chunk in &info.uncompressed_latin1_text {
+            match chunk.keyword.as_str() {
+                KEY_SAMPLE_RATE => sample_rate = Some(chunk.text.parse()?),
+                KEY_NUM_SAMPLES => num_samples = Some(chunk.text.parse()?),
+                KEY_PEAK_MAGNITUDE => peak_magnitude = Some(chunk.text.parse()?),
+                KEY_CHANNELS => channels = Some(chunk.text.parse()?),
+                KEY_CHANNEL => channel = Some(chunk.text.parse()?),
+                KEY_TILE => tile = Some(chunk.text.parse()?),
+                KEY_TILES => tiles = Some(chunk.text.parse()?),
+                _ => {}
+            }
+        }
+
+        match (config, sample_rate) {
+            (Some(config), Some(sample_rate)) => Ok(Some(Self {
+                sample_rate,
+                num_samples: num_samples.unwrap_or(0),
+                peak_magnitude,
+                channels: channels.unwrap_or(1),
+                channel: channel.unwrap_or(0),
+                tile: tile.unwrap_or(0),
+                tiles: tiles.unwrap_or(1),
+                config,
+            })),
+            _ => Ok(None),
+        }
+    }
+
+    fn read_tiff(image_path: &Path) -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        let mut decoder = tiff::decoder::Decoder::new(BufReader::new(File::open(image_path)?))?;
+        match decoder.get_tag_ascii_string(tiff::tags::Tag::ImageDescription) {
+            Ok(description) => Ok(Self::from_toml(&description)),
+            // No description, or one written by another program
+            Err(_) => Ok(None),
+        }
+    }
+
+    fn read_exr(image_path: &Path) -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        let meta = exr::meta::MetaData::read_from_file(image_path, false)?;
+        let key = exr::meta::attribute::Text::from(KEY_METADATA);
+        // Single-layer files may report image attributes as layer attributes
+        let text = meta.headers.first()
+            .and_then(|header| header.shared_attributes.other.get(&key).or_else(|| header.own_attributes.other.get(&key)))
+            .and_then(|value| match value {
+                exr::meta::attribute::AttributeValue::Text(text) => Some(text.to_string()),
+                _ => None,
+            });
+        Ok(text.and_then(|text| Self::from_toml(&text)))
+    }
+
+    // Metadata stored as one TOML document, None if the text is not ours
+    fn from_toml(text: &str) -> Option<Self> {
+        let mut table: toml::Table = toml::from_str(text).ok()?;
+        let config = match table.remove("config")? {
+            toml::Value::Table(config) => encoder_config(config).ok()?,
+            _ => return None,
+        };
+        table.insert("config".to_string(), toml::Value::try_from(config).ok()?);
+        toml::Value::Table(table).try_into().ok()
+    }
+
+    /// Recover metadata from a legacy filename (`name_SR44100_LOG_PHASE.png`)
+    /// Values the filename does not carry are taken from `config`
+    pub fn from_filename(image_path: &Path, config: &SpectrogramConfig) -> Self {
+        // Extract sample rate, scale mode, and phase encoding from filename
+        let (sample_rate, frequency_scale, use_phase_encoding) = if let Some(stem) = image_path.file_stem() {
+            let stem_str = stem.to_string_lossy();
+            println!("Filename stem: {}", stem_str);
+
+            let sample_rate = if let Some(sr_pos) = stem_str.rfind("_SR") {
+                let after_sr = &stem_str[sr_pos + 3..];
+                let sr_str: String = after_sr.chars().take_while(|c| c.is_numeric()).collect();
+                let parsed_sr = sr_str.parse::<u32>().unwrap_or(44100);
+                println!("Extracted sample rate from filename: {}", parsed_sr);
+                parsed_sr
+            } else {
+                println!("No _SR found in filename, using default 44100");
+                44100
+            };
+
+            // Filenames without a scale tag are linear, so `_LIN` is only the fallback
+            let frequency_scale = FrequencyScale::ALL.iter()
+                .rev()
+                .copied()
+                .find(|scale| stem_str.contains(&format!("_{}", scale.suffix())))
+                .unwrap_or(FrequencyScale::Linear);
+            println!("frequency_scale: {}", frequency_scale);
+
+            let use_phase_encoding = if stem_str.contains("_PHASE") {
+                println!("Phase encoding: ENABLED");
+                true
+            } else if stem_str.contains("_MAG") {
+                println!("Phase encoding: DISABLED");
+                false
+            } else {
+                println!("Phase encoding: ENABLED (legacy)");
+                true
+            };
+
+            (sample_rate, frequency_scale, use_phase_encoding)
+        } else {
+            (44100, FrequencyScale::Linear, true)
+        };
+
+        let mut config = config.clone();
+        if frequency_scale != config.frequency_scale {
+            // The filename only carries the scale; the old fixed ranges were min_freq - Nyquist for
+            // log and 0 Hz - Nyquist for linear
+            config.frequency_scale = frequency_scale;
+            config.max_freq = 0.0;
+            if frequency_scale == FrequencyScale::Linear {
+                config.min_freq = 0.0;
+            }
+        }
+        config.use_phase_encoding = use_phase_encoding;
+        config.channel_mode = ChannelMode::Mono;
+        // Legacy images have one row per FFT bin
+        config.num_bins = 0;
+
+        Self {
+            sample_rate,
+            num_samples: 0,
+            peak_magnitude: None,
+            channels: 1,
+            channel: 0,
+            tile: 0,
+            tiles: 1,
+            config,
+        }
+    }
+
+    /// Embedded metadata if present, otherwise the legacy filename convention
+    /// Fails for metadata the image cannot be decoded with (no channels, or channel bands too
+    /// low to hold a spectrum)
+    pub fn load(image_path: &Path, config: &SpectrogramConfig) -> Result<Self, Box<dyn std::error::Error>> {
+        let metadata = match Self::read(image_path)? {
+            Some(mut metadata) => {
+                println!("Using metadata embedded in {}", image_path.display());
+                metadata.config.apply_decoder_settings(config);
+                metadata
+            }
+            None => {
+                println!("No embedded metadata, falling back to filename");
+                Self::from_filename(image_path, config)
+            }
+        };
+
+        if metadata.channels == 0 {
+            return Err(format!("{} declares 0 channels", image_path.display()).into());
+        }
+        // Legacy images take their FFT size from the height: 2 rows make the smallest FFT
+        let (_, height) = image::ImageReader::open(image_path)?.with_guessed_format()?.into_dimensions()?;
+        let bands = if metadata.config.stack_channels { metadata.channels } else { 1 };
+        if (height as usize) / bands < 2 {
+            return Err(format!("{} is {} pixels high for {} channels; each channel needs at least 2 rows", image_path.display(), height, bands).into());
+        }
+        Ok(metadata)
+    }
+}
+
+// Encoder settings stored in an image, upgraded from older versions
+// Images from before padding was configurable were framed without it
+fn encoder_config(mut table: toml::Table) -> Result<SpectrogramConfig, toml::de::Error> {
+    SpectrogramConfig::upgrade_table(&mut table);
+    table.entry("padding").or_insert_with(|| "none".into());
+    toml::Value::Table(table).try_into()
+}
+
+// File signatures
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+const EXR_MAGIC: &[u8] = &[0x76, 0x2f, 0x31, 0x01];
+
+/// Save an image in `metadata.config.image_format` with the metadata embedded
+/// Channel values in [0, 1] are quantized to the format's bit depth (OpenEXR keeps full precision)
+pub fn save_image(img: &Rgb32FImage, path: &Path, metadata: &SpectrogramMetadata) -> Result<(), Box<dyn std::error::Error>> {
+    match metadata.config.image_format {
+        ImageFormat::Png => {
+            let data: Vec<u8> = img.as_raw().iter().map(|&v| (v.clamp(0.0, 1.0) * 255.0).round() as u8).collect();
+            save_png(&data, img.width(), img.height(), png::BitDepth::Eight, path, metadata)
+        }
+        ImageFormat::Png16 => {
+            // PNG stores 16-bit samples big-endian
+            let data: Vec<u8> = img.as_raw().iter().flat_map(|&v| to_u16(v).to_be_bytes()).collect();
+            save_png(&data, img.width(), img.height(), png::BitDepth::Sixteen, path, metadata)
+        }
+        ImageFormat::Tiff16 => save_tiff(img, path, metadata),
+        ImageFormat::Exr => save_exr(img, path, metadata),
+    }
+}
+
+// PNG with the metadata in tEXt/iTXt chunks
+fn save_png(data: &[u8], width: u32, height: u32, depth: png::BitDepth, path: &Path, metadata: &SpectrogramMetadata) -> Result<(), Box<dyn std::error::Error>> {
+    let writer = BufWriter::new(File::create(path)?);
+    let mut encoder = png::Encoder::new(writer, width, height);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(depth);
+
+    encoder.add_itxt_chunk(KEY_CONFIG.to_string(), toml::to_string(&metadata.config)?)?;
+    encoder.add_text_chunk(KEY_SAMPLE_RATE.to_string(), metadata.sample_rate.to_string())?;
+    encoder.add_text_chunk(KEY_NUM_SAMPLES.to_string(), metadata.num_samples.to_string())?;
+    if let Some(peak_magnitude) = metadata.peak_magnitude {
+        encoder.add_text_chunk(KEY_PEAK_MAGNITUDE.to_string(), peak_magnitude.to_string())?;
+    }
+    encoder.add_text_chunk(KEY_CHANNELS.to_string(), metadata.channels.to_string())?;
+    encoder.add_text_chunk(KEY_CHANNEL.to_string(), metadata.channel.to_string())?;
+    if metadata.tiles > 1 {
+        encoder.add_text_chunk(KEY_TILE.to_string(), metadata.tile.to_string())?;
+        encoder.add_text_chunk(KEY_TILES.to_string(), metadata.tiles.to_string())?;
+    }
+
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(data)?;
+    writer.finish()?;
+    Ok(())
+}
+
+// 16-bit RGB TIFF with the metadata as TOML in the ImageDescription tag
+fn save_tiff(img: &Rgb32FImage, path: &Path, metadata: &SpectrogramMetadata) -> Result<(), Box<dyn std::error::Error>> {
+    let data: Vec<u16> = img.as_raw().iter().map(|&v| to_u16(v)).collect();
+
+    let mut encoder = tiff::encoder::TiffEncoder::new(BufWriter::new(File::create(path)?))?
+        .with_compression(tiff::encoder::Compression::Deflate(tiff::encoder::compression::DeflateLevel::Balanced))
+        .with_predictor(tiff::tags::Predictor::Horizontal);
+    let mut image = encoder.new_image::<tiff::encoder::colortype::RGB16>(img.width(), img.height())?;
+    image.encoder().write_tag(tiff::tags::Tag::ImageDescription, toml::to_string(metadata)?.as_str())?;
+    image.write_data(&data)?;
+    Ok(())
+}
+
+// 32-bit float RGB OpenEXR with the metadata as TOML in a custom header attribute
+fn save_exr(img: &Rgb32FImage, path: &Path, metadata: &SpectrogramMetadata) -> Result<(), Box<dyn std::error::Error>> {
+    use exr::prelude::*;
+
+    let pixels = SpecificChannels::rgb(|position: Vec2<usize>| {
+        let pixel = img.get_pixel(position.x() as u32, position.y() as u32);
+        (pixel[0], pixel[1], pixel[2])
+    });
+    let layer = Layer::new(
+        (img.width() as usize, img.height() as usize),
+        LayerAttributes::default(),
+        Encoding::SMALL_LOSSLESS,
+        pixels,
+    );
+
+    let mut image = Image::from_layer(layer);
+    let text = Text::new_or_none(toml::to_string(metadata)?).ok_or("Metadata is not valid EXR text")?;
+    image.attributes.other.insert(Text::from(KEY_METADATA), AttributeValue::Text(text));
+    image.write().to_file(path)?;
+    Ok(())
+}
+
+fn to_u16(value: f32) -> u16 {
+    (value.clamp(0.0, 1.0) * 65535.0).round() as u16
+}
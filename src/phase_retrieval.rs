@@ -0,0 +1,413 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rayon::prelude::*;
+use rustfft::num_complex::Complex;
+use std::collections::BinaryHeap;
+use crate::config::{PhaseInit, PhaseRetrieval, SpectrogramConfig};
+use crate::progress::{Cancelled, Progress};
+use crate::stft::{istft, stft_complex, Window};
+
+// Bins this far below the loudest one (relative magnitude) get random phases in PGHI
+const PGHI_TOLERANCE: f32 = 1e-5;
+
+// Seed of the random phases (random initialization, quiet bins in PGHI, silence in RTISI-LA)
+const RANDOM_PHASE_SEED: u64 = 0x5eed;
+
+/// Estimate phases for a magnitude-only spectrogram with the algorithm chosen in `config`
+/// `magnitude` is indexed as [bin][frame] with fft_size / 2 + 1 bins; returns phases in the same layout
+pub fn reconstruct_phase(
+    magnitude: &[Vec<f32>],
+    window: &Window,
+    config: &SpectrogramConfig,
+    progress: &Progress,
+) -> Result<Vec<Vec<f32>>, Cancelled> {
+    reconstruct_phase_continuing(magnitude, window, config, &[], progress)
+}
+
+/// `reconstruct_phase` for a block continuing audio whose phases are already known
+/// The first frames of `magnitude` overlap the previous block and keep the phases in `known_phase`
+/// ([bin][frame], possibly empty), so the new frames join them without a seam
+pub fn reconstruct_phase_continuing(
+    magnitude: &[Vec<f32>],
+    window: &Window,
+    config: &SpectrogramConfig,
+    known_phase: &[Vec<f32>],
+    progress: &Progress,
+) -> Result<Vec<Vec<f32>>, Cancelled> {
+    let iterations = config.griffin_lim_iterations;
+    let num_known = known_phase.first().map_or(0, |row| row.len());
+    match config.phase_retrieval {
+        PhaseRetrieval::GriffinLim => {
+            let initial = seeded_initial_phase(magnitude, window, config.phase_init, known_phase, progress);
+            griffin_lim_from(magnitude, window, initial, iterations, 0.0, num_known, progress)
+        }
+        PhaseRetrieval::FastGriffinLim => {
+            let initial = seeded_initial_phase(magnitude, window, config.phase_init, known_phase, progress);
+            griffin_lim_from(magnitude, window, initial, iterations, config.griffin_lim_momentum, num_known, progress)
+        }
+        PhaseRetrieval::RtisiLa => rtisi_la_from(magnitude, window, iterations, config.rtisi_lookahead, known_phase, progress),
+        PhaseRetrieval::Pghi => {
+            println!("Integrating phase gradients (PGHI)...");
+            progress.status("Integrating phase gradients (PGHI)...");
+            Ok(pghi_from(magnitude, window, known_phase))
+        }
+    }
+}
+
+/// Starting phases for the Griffin-Lim variants
+pub fn initial_phase(magnitude: &[Vec<f32>], window: &Window, init: PhaseInit, progress: &Progress) -> Vec<Vec<f32>> {
+    seeded_initial_phase(magnitude, window, init, &[], progress)
+}
+
+// Starting phases with the first frames set to `known_phase`
+fn seeded_initial_phase(
+    magnitude: &[Vec<f32>],
+    window: &Window,
+    init: PhaseInit,
+    known_phase: &[Vec<f32>],
+    progress: &Progress,
+) -> Vec<Vec<f32>> {
+    let num_frames = magnitude.first().map_or(0, |row| row.len());
+    let mut phase = match init {
+        PhaseInit::Zero => vec![vec![0.0f32; num_frames]; magnitude.len()],
+        PhaseInit::Random => random_phase(magnitude.len(), num_frames),
+        PhaseInit::Pghi => {
+            progress.status("Integrating phase gradients (PGHI)...");
+            return pghi_from(magnitude, window, known_phase);
+        }
+    };
+    for (row, known) in phase.iter_mut().zip(known_phase) {
+        row[..known.len()].copy_from_slice(known);
+    }
+    phase
+}
+
+/// Griffin-Lim phase reconstruction, accelerated with `momentum` (Fast Griffin-Lim, 0 = plain)
+/// Iteratively estimates phases that produce a consistent signal
+pub fn griffin_lim(
+    magnitude: &[Vec<f32>],
+    window: &Window,
+    initial_phase: Vec<Vec<f32>>,
+    num_iterations: usize,
+    momentum: f32,
+    progress: &Progress,
+) -> Result<Vec<Vec<f32>>, Cancelled> {
+    griffin_lim_from(magnitude, window, initial_phase, num_iterations, momentum, 0, progress)
+}
+
+// Griffin-Lim updating only the frames from `first_frame` on; earlier frames keep their initial phases
+fn griffin_lim_from(
+    magnitude: &[Vec<f32>],
+    window: &Window,
+    initial_phase: Vec<Vec<f32>>,
+    num_iterations: usize,
+    momentum: f32,
+    first_frame: usize,
+    progress: &Progress,
+) -> Result<Vec<Vec<f32>>, Cancelled> {
+    if momentum > 0.0 {
+        println!("Running Fast Griffin-Lim with {} iterations (momentum {})...", num_iterations, momentum);
+    } else {
+        println!("Running Griffin-Lim algorithm with {} iterations...", num_iterations);
+    }
+
+    let mut phase = initial_phase;
+    let mut previous: Option<Vec<Vec<Complex<f32>>>> = None;
+
+    for iteration in 0..num_iterations {
+        progress.check()?;
+        progress.update("Reconstructing phase (Griffin-Lim)", iteration, num_iterations);
+
+        // Step 1: Reconstruct time-domain signal with current phases
+        let time_signal = istft(magnitude, &phase, window);
+
+        // Step 2: Re-analyze it; the closest consistent spectrogram to the current estimate
+        let projected = stft_complex(&time_signal, window);
+
+        // Step 3: Extrapolate along the last step and keep only the phase (magnitudes stay the original)
+        let last = iteration == num_iterations - 1;
+        phase.par_iter_mut().enumerate().for_each(|(bin, row)| {
+            for (frame, value) in row.iter_mut().enumerate().skip(first_frame) {
+                let current = projected[bin][frame];
+                *value = match &previous {
+                    Some(previous) if !last => (current + (current - previous[bin][frame]) * momentum).arg(),
+                    _ => current.arg(),
+                };
+            }
+        });
+        if momentum > 0.0 {
+            previous = Some(projected);
+        }
+
+        if iteration % 10 == 0 || last {
+            println!("  Iteration {}/{} complete", iteration + 1, num_iterations);
+        }
+    }
+
+    println!("Griffin-Lim complete!");
+    Ok(phase)
+}
+
+/// Real-Time Iterative Spectrogram Inversion with Look-Ahead (RTISI-LA)
+/// Frames are reconstructed in order: each frame's phase is refined for `num_iterations` iterations
+/// against the committed signal so far and the next `lookahead` frames, then committed
+pub fn rtisi_la(
+    magnitude: &[Vec<f32>],
+    window: &Window,
+    num_iterations: usize,
+    lookahead: usize,
+    progress: &Progress,
+) -> Result<Vec<Vec<f32>>, Cancelled> {
+    rtisi_la_from(magnitude, window, num_iterations, lookahead, &[], progress)
+}
+
+// RTISI-LA with the first frames already committed with the phases in `known_phase`
+fn rtisi_la_from(
+    magnitude: &[Vec<f32>],
+    window: &Window,
+    num_iterations: usize,
+    lookahead: usize,
+    known_phase: &[Vec<f32>],
+    progress: &Progress,
+) -> Result<Vec<Vec<f32>>, Cancelled> {
+    let fft_size = window.len();
+    let hop_size = window.hop_size;
+    let num_bins = magnitude.len();
+    let num_frames = magnitude.first().map_or(0, |row| row.len());
+
+    println!("Running RTISI-LA with {} iterations per frame, {} frames lookahead...", num_iterations, lookahead);
+
+    let fft = window.fft();
+    let ifft = window.ifft();
+
+    // Overlap-added output of committed frames, and the windowed estimates of frames in the lookahead
+    let mut committed = vec![0.0f32; num_frames.saturating_sub(1) * hop_size + fft_size];
+    let mut estimates: Vec<Option<Vec<f32>>> = vec![None; num_frames];
+    // Used where the signal so far is silent and gives no phase
+    let fallback = random_phase(num_bins, num_frames);
+    let mut phase = vec![vec![0.0f32; num_frames]; num_bins];
+
+    // Commit the known frames as they are
+    let num_known = known_phase.first().map_or(0, |row| row.len()).min(num_frames);
+    for frame in 0..num_known {
+        let mut buffer = vec![Complex::new(0.0f32, 0.0); fft_size];
+        for bin in 0..num_bins.min(fft_size / 2 + 1) {
+            phase[bin][frame] = known_phase[bin][frame];
+            buffer[bin] = Complex::from_polar(magnitude[bin][frame], known_phase[bin][frame]);
+        }
+        for bin in 1..fft_size / 2 {
+            buffer[fft_size - bin] = buffer[bin].conj();
+        }
+        ifft.process(&mut buffer);
+
+        let start = frame * hop_size;
+        for ((sample, value), &w) in committed[start..start + fft_size].iter_mut().zip(&buffer).zip(&window.synthesis) {
+            *sample += value.re * w / fft_size as f32;
+        }
+    }
+
+    let mut update_frame = |frame: usize, committed: &[f32], estimates: &mut [Option<Vec<f32>>]| {
+        let start = frame * hop_size;
+
+        // Current reconstruction under this frame
+        let mut buffer: Vec<Complex<f32>> = committed[start..start + fft_size]
+            .iter()
+            .map(|&s| Complex::new(s, 0.0))
+            .collect();
+        let first = frame.saturating_sub(lookahead);
+        for (other, estimate) in estimates.iter().enumerate().take(frame + lookahead + 1).skip(first) {
+            let Some(estimate) = estimate else { continue };
+            let other_start = other * hop_size;
+            for (i, value) in buffer.iter_mut().enumerate() {
+                let offset = (start + i).wrapping_sub(other_start);
+                if offset < fft_size {
+                    value.re += estimate[offset];
+                }
+            }
+        }
+
+        for (value, &w) in buffer.iter_mut().zip(&window.analysis) {
+            value.re *= w;
+        }
+        fft.process(&mut buffer);
+
+        // Keep the phase, impose the target magnitude
+        for bin in 0..num_bins.min(fft_size / 2 + 1) {
+            let value = buffer[bin];
+            let bin_phase = if value.norm() > 1e-12 { value.arg() } else { fallback[bin][frame] };
+            phase[bin][frame] = bin_phase;
+            buffer[bin] = Complex::from_polar(magnitude[bin][frame], bin_phase);
+        }
+        for bin in 1..fft_size / 2 {
+            buffer[fft_size - bin] = buffer[bin].conj();
+        }
+        ifft.process(&mut buffer);
+
+        estimates[frame] = Some(
+            buffer.iter()
+                .zip(&window.synthesis)
+                .map(|(value, &w)| value.re * w / fft_size as f32)
+                .collect(),
+        );
+    };
+
+    for frame in num_known..num_frames {
+        progress.check()?;
+        progress.update("Reconstructing phase (RTISI-LA)", frame, num_frames);
+
+        let newest = (frame + lookahead).min(num_frames - 1);
+        for _ in 0..num_iterations.max(1) {
+            for active in frame..=newest {
+                update_frame(active, &committed, &mut estimates);
+            }
+        }
+
+        // Commit the oldest frame
+        if let Some(estimate) = estimates[frame].take() {
+            let start = frame * hop_size;
+            for (sample, value) in committed[start..start + fft_size].iter_mut().zip(estimate) {
+                *sample += value;
+            }
+        }
+
+        if frame % 1000 == 0 || frame == num_frames - 1 {
+            println!("  Frame {}/{} committed", frame + 1, num_frames);
+        }
+    }
+
+    println!("RTISI-LA complete!");
+    Ok(phase)
+}
+
+/// Phase Gradient Heuristic Integration (Průša et al., 2017)
+/// Phase derivatives follow from the log-magnitude gradients (exact for Gaussian windows, a close
+/// approximation for other smooth windows); phases are integrated outwards from the loudest bins
+pub fn pghi(magnitude: &[Vec<f32>], window: &Window) -> Vec<Vec<f32>> {
+    pghi_from(magnitude, window, &[])
+}
+
+// PGHI integrating outwards from the first frames, whose phases are given in `known_phase`
+fn pghi_from(magnitude: &[Vec<f32>], window: &Window, known_phase: &[Vec<f32>]) -> Vec<Vec<f32>> {
+    use std::f32::consts::PI;
+
+    let fft_size = window.len() as f32;
+    let hop_size = window.hop_size as f32;
+    let num_bins = magnitude.len();
+    let num_frames = magnitude.first().map_or(0, |row| row.len());
+    let lambda = window_lambda(window);
+
+    let max_magnitude = magnitude.iter().flatten().cloned().fold(0.0f32, f32::max);
+    let tolerance = max_magnitude * PGHI_TOLERANCE;
+    let log_magnitude: Vec<Vec<f32>> = magnitude.par_iter()
+        .map(|row| row.iter().map(|&m| m.max(tolerance).max(f32::MIN_POSITIVE).ln()).collect())
+        .collect();
+
+    // Centered differences, one-sided at the edges
+    let diff = |values: &dyn Fn(usize) -> f32, index: usize, len: usize| -> f32 {
+        match (index.checked_sub(1), index + 1 < len) {
+            (Some(before), true) => (values(index + 1) - values(before)) / 2.0,
+            (Some(before), false) => values(index) - values(before),
+            (None, true) => values(index + 1) - values(index),
+            (None, false) => 0.0,
+        }
+    };
+
+    // Phase advance per frame and per bin, in radians
+    let gradients: Vec<(Vec<f32>, Vec<f32>)> = (0..num_bins)
+        .into_par_iter()
+        .map(|bin| {
+            (0..num_frames)
+                .map(|frame| {
+                    let along_bins = diff(&|b| log_magnitude[b][frame], bin, num_bins);
+                    let along_frames = diff(&|f| log_magnitude[bin][f], frame, num_frames) / hop_size;
+                    let time = hop_size * (fft_size / lambda * along_bins + 2.0 * PI * bin as f32 / fft_size);
+                    // Frames are windowed around their center, which adds π per bin
+                    let frequency = -lambda / fft_size * along_frames - PI;
+                    (time, frequency)
+                })
+                .unzip()
+        })
+        .collect();
+    let (time_gradient, frequency_gradient): (Vec<Vec<f32>>, Vec<Vec<f32>>) = gradients.into_iter().unzip();
+
+    let mut phase = random_phase(num_bins, num_frames);
+    let mut done: Vec<Vec<bool>> = magnitude.iter()
+        .map(|row| row.iter().map(|&m| m <= tolerance).collect())
+        .collect();
+
+    // Loudest bins first: each integrates the phase into its undone neighbours
+    let mut order: Vec<(usize, usize)> = (0..num_bins)
+        .flat_map(|bin| (0..num_frames).map(move |frame| (bin, frame)))
+        .filter(|&(bin, frame)| !done[bin][frame])
+        .collect();
+    order.sort_unstable_by(|a, b| magnitude[b.0][b.1].total_cmp(&magnitude[a.0][a.1]));
+
+    // Known frames are where the integration starts
+    let mut heap = BinaryHeap::new();
+    for (bin, known) in known_phase.iter().enumerate() {
+        for (frame, &value) in known.iter().enumerate().take(num_frames) {
+            phase[bin][frame] = value;
+            if !done[bin][frame] {
+                heap.push((magnitude[bin][frame].to_bits(), bin, frame));
+            }
+            done[bin][frame] = true;
+        }
+    }
+
+    let mut order = order.into_iter();
+    loop {
+        while let Some((_, bin, frame)) = heap.pop() {
+            let mut visit = |to_bin: usize, to_frame: usize, step: f32| {
+                if !done[to_bin][to_frame] {
+                    phase[to_bin][to_frame] = phase[bin][frame] + step;
+                    done[to_bin][to_frame] = true;
+                    heap.push((magnitude[to_bin][to_frame].to_bits(), to_bin, to_frame));
+                }
+            };
+            // Trapezoidal rule between the two bins
+            if frame + 1 < num_frames {
+                visit(bin, frame + 1, (time_gradient[bin][frame] + time_gradient[bin][frame + 1]) / 2.0);
+            }
+            if frame > 0 {
+                visit(bin, frame - 1, -(time_gradient[bin][frame] + time_gradient[bin][frame - 1]) / 2.0);
+            }
+            if bin + 1 < num_bins {
+                visit(bin + 1, frame, (frequency_gradient[bin][frame] + frequency_gradient[bin + 1][frame]) / 2.0);
+            }
+            if bin > 0 {
+                visit(bin - 1, frame, -(frequency_gradient[bin][frame] + frequency_gradient[bin - 1][frame]) / 2.0);
+            }
+        }
+
+        // Start of a new region: its phase is arbitrary
+        let Some((bin, frame)) = order.find(|&(bin, frame)| !done[bin][frame]) else { break };
+        phase[bin][frame] = 0.0;
+        done[bin][frame] = true;
+        heap.push((magnitude[bin][frame].to_bits(), bin, frame));
+    }
+
+    // Wrap to [-π, π]
+    for value in phase.iter_mut().flatten() {
+        *value = (*value + PI).rem_euclid(2.0 * PI) - PI;
+    }
+    phase
+}
+
+// Time-frequency spread λ of the Gaussian closest to the analysis window (exp(-π t² / λ)),
+// matched on the variance of the squared window
+fn window_lambda(window: &Window) -> f32 {
+    let center = window.len() as f64 / 2.0;
+    let (moment, energy) = window.analysis.iter().enumerate().fold((0.0f64, 0.0f64), |(moment, energy), (i, &w)| {
+        let w2 = (w as f64) * (w as f64);
+        (moment + (i as f64 - center).powi(2) * w2, energy + w2)
+    });
+    (4.0 * std::f64::consts::PI * moment / energy.max(1e-30)) as f32
+}
+
+// Uniformly random phases from a fixed seed, so decoding the same image always gives the same audio
+fn random_phase(num_bins: usize, num_frames: usize) -> Vec<Vec<f32>> {
+    let mut rng = StdRng::seed_from_u64(RANDOM_PHASE_SEED);
+    (0..num_bins)
+        .map(|_| (0..num_frames).map(|_| rng.random_range(-std::f32::consts::PI..std::f32::consts::PI)).collect())
+        .collect()
+}
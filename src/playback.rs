@@ -0,0 +1,159 @@
+use spectrogram_converter::AudioData;
+
+/// Which of the two tracks to play
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Track {
+    /// The selected audio file
+    Original,
+    /// The decoded result of the last export
+    Reconstruction,
+}
+
+impl Track {
+    pub fn other(self) -> Self {
+        match self {
+            Track::Original => Track::Reconstruction,
+            Track::Reconstruction => Track::Original,
+        }
+    }
+}
+
+/// Plays the original audio and its reconstruction, switching between them at the same position
+pub struct Player {
+    original: Option<AudioData>,
+    reconstruction: Option<AudioData>,
+    output: output::Output,
+    playing: Option<Track>,
+}
+
+impl Player {
+    pub fn new() -> Self {
+        Self { original: None, reconstruction: None, output: output::Output::default(), playing: None }
+    }
+
+    /// Whether this build can play audio at all
+    pub fn is_supported() -> bool {
+        output::SUPPORTED
+    }
+
+    /// Replace a track, stopping it if it is playing
+    pub fn set_track(&mut self, track: Track, audio: Option<AudioData>) {
+        if self.playing() == Some(track) {
+            self.stop();
+        }
+        match track {
+            Track::Original => self.original = audio,
+            Track::Reconstruction => self.reconstruction = audio,
+        }
+    }
+
+    pub fn track(&self, track: Track) -> Option<&AudioData> {
+        match track {
+            Track::Original => self.original.as_ref(),
+            Track::Reconstruction => self.reconstruction.as_ref(),
+        }
+    }
+
+    /// Start playing `track` at `seconds`
+    pub fn play(&mut self, track: Track, seconds: f32) -> Result<(), String> {
+        let audio = self.track(track).ok_or("Nothing to play")?;
+        let sample_rate = audio.sample_rate;
+        let start = ((seconds.max(0.0) * sample_rate as f32) as usize).min(audio.channels[0].len());
+
+        // Interleave the frames from `start` on
+        let num_channels = audio.channels.len();
+        let mut samples = Vec::with_capacity((audio.channels[0].len() - start) * num_channels);
+        for frame in start..audio.channels[0].len() {
+            samples.extend(audio.channels.iter().map(|channel| channel[frame]));
+        }
+
+        self.output.play(num_channels as u16, sample_rate, samples, start as f32 / sample_rate as f32)?;
+        self.playing = Some(track);
+        Ok(())
+    }
+
+    pub fn stop(&mut self) {
+        self.output.stop();
+        self.playing = None;
+    }
+
+    /// The track playing, None once it has finished
+    pub fn playing(&self) -> Option<Track> {
+        self.playing.filter(|_| self.output.is_playing())
+    }
+
+    /// Playhead in seconds while playing
+    pub fn position(&self) -> Option<f32> {
+        self.playing().map(|_| self.output.position())
+    }
+}
+
+#[cfg(feature = "playback")]
+mod output {
+    use rodio::buffer::SamplesBuffer;
+    use rodio::{OutputStream, OutputStreamHandle, Sink};
+
+    pub const SUPPORTED: bool = true;
+
+    // Default output device, opened on first use, and the sink playing on it
+    #[derive(Default)]
+    pub struct Output {
+        stream: Option<(OutputStream, OutputStreamHandle)>,
+        sink: Option<Sink>,
+        start_seconds: f32,
+    }
+
+    impl Output {
+        pub fn play(&mut self, channels: u16, sample_rate: u32, samples: Vec<f32>, start_seconds: f32) -> Result<(), String> {
+            self.stop();
+            if self.stream.is_none() {
+                self.stream = Some(OutputStream::try_default().map_err(|e| format!("No audio output: {}", e))?);
+            }
+            let (_, handle) = self.stream.as_ref().expect("opened above");
+            let sink = Sink::try_new(handle).map_err(|e| format!("Could not play: {}", e))?;
+            sink.append(SamplesBuffer::new(channels, sample_rate, samples));
+            self.sink = Some(sink);
+            self.start_seconds = start_seconds;
+            Ok(())
+        }
+
+        pub fn stop(&mut self) {
+            if let Some(sink) = self.sink.take() {
+                sink.stop();
+            }
+        }
+
+        pub fn is_playing(&self) -> bool {
+            self.sink.as_ref().is_some_and(|sink| !sink.empty())
+        }
+
+        pub fn position(&self) -> f32 {
+            self.start_seconds + self.sink.as_ref().map_or(0.0, |sink| sink.get_pos().as_secs_f32())
+        }
+    }
+}
+
+#[cfg(not(feature = "playback"))]
+mod output {
+    pub const SUPPORTED: bool = false;
+
+    // Stand-in for builds without the `playback` feature
+    #[derive(Default)]
+    pub struct Output {}
+
+    impl Output {
+        pub fn play(&mut self, _channels: u16, _sample_rate: u32, _samples: Vec<f32>, _start_seconds: f32) -> Result<(), String> {
+            Err("This build has no audio playback (build with --features playback)".to_string())
+        }
+
+        pub fn stop(&mut self) {}
+
+        pub fn is_playing(&self) -> bool {
+            false
+        }
+
+        pub fn position(&self) -> f32 {
+            0.0
+        }
+    }
+}
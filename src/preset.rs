@@ -0,0 +1,143 @@
+//! Named configuration presets
+//!
+//! A few presets are built in; saved presets are configuration files named `<name>.toml` in a
+//! presets folder, and take the place of a built-in preset of the same name.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::config::{FrequencyScale, SpectrogramConfig};
+
+/// Names of the built-in presets
+pub const BUILTIN_PRESETS: [&str; 4] = ["music-hifi", "speech", "fast-edit", "max-quality"];
+
+// Environment variable overriding the presets folder
+const PRESETS_DIR_VAR: &str = "SPECTROGRAM_PRESETS_DIR";
+
+/// Presets saved in one folder, along with the built-in ones
+#[derive(Debug, Clone)]
+pub struct Presets {
+    dir: PathBuf,
+}
+
+impl Presets {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    /// Presets in the user's configuration folder: `$SPECTROGRAM_PRESETS_DIR` if set, else
+    /// `spectrogram-converter/presets` in `$XDG_CONFIG_HOME` (or `~/.config`), in
+    /// `~/Library/Application Support` on macOS and in `%APPDATA%` on Windows
+    pub fn user() -> Result<Self, String> {
+        if let Some(dir) = std::env::var_os(PRESETS_DIR_VAR) {
+            return Ok(Self::new(PathBuf::from(dir)));
+        }
+        let var = |name: &str| std::env::var_os(name).filter(|value| !value.is_empty()).map(PathBuf::from);
+        let config_dir = if cfg!(windows) {
+            var("APPDATA")
+        } else if cfg!(target_os = "macos") {
+            var("HOME").map(|home| home.join("Library").join("Application Support"))
+        } else {
+            var("XDG_CONFIG_HOME").or_else(|| var("HOME").map(|home| home.join(".config")))
+        };
+        config_dir
+            .map(|dir| Self::new(dir.join("spectrogram-converter").join("presets")))
+            .ok_or_else(|| format!("No user configuration folder found; set {} to keep presets somewhere", PRESETS_DIR_VAR))
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Names of the built-in and saved presets, built-in ones first
+    pub fn names(&self) -> Vec<String> {
+        let mut saved: Vec<String> = fs::read_dir(&self.dir)
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| {
+                let path = entry.ok()?.path();
+                let name = path.file_stem()?.to_str()?.to_string();
+                (path.extension()? == "toml" && valid_name(&name)).then_some(name)
+            })
+            .filter(|name| !BUILTIN_PRESETS.contains(&name.as_str()))
+            .collect();
+        saved.sort();
+        BUILTIN_PRESETS.iter().map(|name| name.to_string()).chain(saved).collect()
+    }
+
+    /// File a preset is saved in (whether or not it exists)
+    pub fn path(&self, name: &str) -> Result<PathBuf, String> {
+        if !valid_name(name) {
+            return Err(format!("Invalid preset name \"{}\": use letters, digits, '-' and '_'", name));
+        }
+        Ok(self.dir.join(format!("{}.toml", name)))
+    }
+
+    /// Whether a preset has been saved under `name`
+    pub fn is_saved(&self, name: &str) -> bool {
+        self.path(name).is_ok_and(|path| path.is_file())
+    }
+
+    /// Load a saved preset, or else the built-in one
+    pub fn load(&self, name: &str) -> Result<SpectrogramConfig, Box<dyn std::error::Error>> {
+        let path = self.path(name)?;
+        let config = if path.is_file() {
+            SpectrogramConfig::load_from(&path)?
+        } else {
+            builtin_preset(name).ok_or_else(|| format!("No preset named \"{}\" (presets: {})", name, self.names().join(", ")))?
+        };
+        config.validate().map_err(|e| format!("Preset \"{}\": {}", name, e))?;
+        Ok(config)
+    }
+
+    /// Save settings as a preset, replacing any saved under the same name
+    /// Returns the file written
+    pub fn save(&self, name: &str, config: &SpectrogramConfig) -> Result<PathBuf, Box<dyn std::error::Error>> {
+        let path = self.path(name)?;
+        fs::create_dir_all(&self.dir)?;
+        fs::write(&path, toml::to_string_pretty(config)?)?;
+        Ok(path)
+    }
+}
+
+/// Settings of a built-in preset: the defaults with a few changes
+pub fn builtin_preset(name: &str) -> Option<SpectrogramConfig> {
+    let default = SpectrogramConfig::default();
+    match name {
+        // High-detail music visualization
+        "music-hifi" => Some(SpectrogramConfig {
+            fft_size: 4096,
+            hop_size: 64,
+            frequency_scale: FrequencyScale::Log,
+            ..default
+        }),
+        // Speech band on a compact mel-scale image
+        "speech" => Some(SpectrogramConfig {
+            frequency_scale: FrequencyScale::Mel,
+            min_freq: 0.0,
+            max_freq: 8000.0,
+            num_bins: 512,
+            ..default
+        }),
+        // Small grayscale images for image editors
+        "fast-edit" => Some(SpectrogramConfig {
+            fft_size: 2048,
+            hop_size: 256,
+            use_phase_encoding: false,
+            ..default
+        }),
+        // Maximum quality (slow)
+        "max-quality" => Some(SpectrogramConfig {
+            fft_size: 8192,
+            hop_size: 128,
+            use_phase_encoding: true,
+            griffin_lim_iterations: 50,
+            ..default
+        }),
+        _ => None,
+    }
+}
+
+// Names that make plain file names everywhere
+fn valid_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
@@ -0,0 +1,88 @@
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+
+// Smallest change in progress (in thousandths) passed on to the callback
+const REPORT_STEP: u32 = 5;
+
+/// Shared flag asking a running conversion to stop
+/// Clones share the flag, so the GUI can keep one and hand another to the worker thread
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ask the conversion to stop at its next check
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Error returned by a conversion stopped through its `CancelToken`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Conversion cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+/// Progress reporting and cancellation handed to long-running functions
+/// The callback receives a status line and the completed fraction of that step (None while
+/// the step's length is unknown); updates are throttled to steps of 0.5%
+pub struct Progress<'a> {
+    report: &'a (dyn Fn(&str, Option<f32>) + Sync),
+    cancel: CancelToken,
+    last_reported: AtomicU32,
+}
+
+impl<'a> Progress<'a> {
+    pub fn new(report: &'a (dyn Fn(&str, Option<f32>) + Sync), cancel: CancelToken) -> Self {
+        Self { report, cancel, last_reported: AtomicU32::new(u32::MAX) }
+    }
+
+    /// No reporting, never cancelled
+    pub fn none() -> Progress<'static> {
+        Progress::new(&|_, _| {}, CancelToken::new())
+    }
+
+    /// Same cancellation without reporting, for work inside a step that reports its own progress
+    pub fn quiet(&self) -> Progress<'static> {
+        Progress::new(&|_, _| {}, self.cancel.clone())
+    }
+
+    /// Start a step whose length is unknown
+    pub fn status(&self, status: &str) {
+        self.last_reported.store(u32::MAX, Ordering::Relaxed);
+        (self.report)(status, None);
+    }
+
+    /// Report `done` of `total` units of the current step
+    pub fn update(&self, status: &str, done: usize, total: usize) {
+        let permille = (done.min(total) * 1000 / total.max(1)) as u32;
+        let last = self.last_reported.load(Ordering::Relaxed);
+        if last == u32::MAX || permille < last || permille >= last + REPORT_STEP || permille == 1000 {
+            self.last_reported.store(permille, Ordering::Relaxed);
+            (self.report)(status, Some(permille as f32 / 1000.0));
+        }
+    }
+
+    /// Err(Cancelled) once cancellation was requested
+    pub fn check(&self) -> Result<(), Cancelled> {
+        if self.cancel.is_cancelled() {
+            Err(Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+}
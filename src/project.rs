@@ -0,0 +1,298 @@
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Component, Path, PathBuf};
+use crate::audio_io::{read_audio, write_audio, AudioData};
+use crate::audio_to_image::save_spectrograms;
+use crate::config::{ChannelMode, SpectrogramConfig};
+use crate::edit::{Layer, Layers};
+use crate::image_to_audio::{image_to_spectrogram, read_bands, spectrograms_to_audio};
+use crate::metadata::SpectrogramMetadata;
+use crate::progress::Progress;
+use crate::spectrogram::{analyze_with_progress, Spectrogram};
+
+/// Extension of project folders
+pub const PROJECT_EXTENSION: &str = "specproj";
+
+// Files inside a project folder
+const PROJECT_FILE: &str = "project.toml";
+const DATA_FILE: &str = "spectrogram.f32";
+
+// Version of the project file layout
+const PROJECT_VERSION: u32 = 1;
+
+/// Spectrogram project: the analyzed channels of a source file, the edit layers over them and
+/// what rendering it produces
+/// Saved as a folder holding `project.toml` (source, settings, layers, export settings) and
+/// `spectrogram.f32` (the analysis as little-endian floats: per channel the magnitudes bin by bin,
+/// then the phases if present)
+#[derive(Debug, Clone)]
+pub struct Project {
+    /// Audio file (or image) the spectrogram was made from, if known
+    pub source: Option<PathBuf>,
+    /// Sample rate, length, level reference, channel count and settings of the analysis
+    pub metadata: SpectrogramMetadata,
+    /// One spectrogram per encoded channel, before edits
+    pub spectrograms: Vec<Spectrogram>,
+    /// Edit layers, bottom first
+    pub layers: Vec<Layer>,
+    pub export: ExportSettings,
+}
+
+/// What rendering a project writes
+/// Relative paths are relative to the project folder; unset paths default to `export.<ext>` in it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExportSettings {
+    /// Render the edited spectrogram as an image (in the configured image format)
+    #[serde(default = "default_true")]
+    pub image: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image_path: Option<PathBuf>,
+    /// Resynthesize the edited spectrogram into audio (in the configured output format)
+    #[serde(default = "default_true")]
+    pub audio: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audio_path: Option<PathBuf>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl Default for ExportSettings {
+    fn default() -> Self {
+        Self { image: true, image_path: None, audio: true, audio_path: None }
+    }
+}
+
+// Layout of project.toml
+#[derive(Serialize, Deserialize)]
+struct ProjectFile {
+    version: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    source: Option<PathBuf>,
+    num_bins: usize,
+    num_frames: usize,
+    has_phase: bool,
+    #[serde(default)]
+    export: ExportSettings,
+    metadata: SpectrogramMetadata,
+    #[serde(default)]
+    layers: Vec<Layer>,
+}
+
+impl Project {
+    /// Analyze an audio file into a new project with one empty layer
+    pub fn from_audio(audio_path: &Path, config: &SpectrogramConfig, progress: &Progress) -> Result<Self, Box<dyn std::error::Error>> {
+        progress.status("Reading audio file...");
+        let AudioData { channels, sample_rate } = read_audio(audio_path)?;
+        if config.channel_mode == ChannelMode::Mono && channels.len() > 1 {
+            println!("Converting {} channels to mono by averaging", channels.len());
+        }
+        let signals = config.channel_mode.split(channels)?;
+        let spectrograms = signals.iter()
+            .map(|signal| analyze_with_progress(signal, sample_rate, config, progress))
+            .collect::<Result<Vec<Spectrogram>, _>>()?;
+
+        // All channels share one reference, as in encoded images
+        let mut metadata = SpectrogramMetadata::from_spectrogram(&spectrograms[0]);
+        metadata.peak_magnitude = Some(spectrograms.iter().map(|s| s.max_magnitude()).fold(0.0f32, f32::max));
+        metadata.channels = spectrograms.len();
+
+        Ok(Self::new(Some(absolute(audio_path)), metadata, spectrograms))
+    }
+
+    /// Decode a spectrogram image (with its sibling channel images) into a new project
+    /// Tiled sequences are not supported
+    pub fn from_image(image_path: &Path, config: &SpectrogramConfig) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut metadata = SpectrogramMetadata::load(image_path, config)?;
+        if metadata.tiles > 1 {
+            return Err(format!("{} is one tile of a tiled sequence, which cannot be made into a project", image_path.display()).into());
+        }
+        let spectrograms: Vec<Spectrogram> = read_bands(image_path, &metadata)?
+            .iter()
+            .map(|band| image_to_spectrogram(band, &metadata))
+            .collect::<Result<_, _>>()?;
+
+        // The analysis as decoded: legacy and resized images get their FFT size or height from the image
+        metadata.config = spectrograms[0].config.clone();
+        metadata.num_samples = spectrograms[0].num_samples;
+        metadata.channel = 0;
+
+        Ok(Self::new(Some(absolute(image_path)), metadata, spectrograms))
+    }
+
+    fn new(source: Option<PathBuf>, metadata: SpectrogramMetadata, spectrograms: Vec<Spectrogram>) -> Self {
+        Self { source, metadata, spectrograms, layers: Layers::new().layers().to_vec(), export: ExportSettings::default() }
+    }
+
+    /// Load a project from its folder (or the `project.toml` in it)
+    pub fn load(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let dir = project_dir(path);
+        let file_path = dir.join(PROJECT_FILE);
+        let file: ProjectFile = toml::from_str(&fs::read_to_string(&file_path)?)
+            .map_err(|e| format!("Invalid project file {}: {}", file_path.display(), e))?;
+        if file.version > PROJECT_VERSION {
+            return Err(format!("{} was saved by a newer version (project version {})", file_path.display(), file.version).into());
+        }
+
+        if file.metadata.channels == 0 || file.num_bins < 2 {
+            return Err(format!("{} holds {} channels of {} bins; at least 1 channel of 2 bins is needed", file_path.display(), file.metadata.channels, file.num_bins).into());
+        }
+
+        // The analysis, channel by channel
+        let data = fs::read(dir.join(DATA_FILE))?;
+        let matrices_per_channel = if file.has_phase { 2 } else { 1 };
+        let matrix_len = file.num_bins * file.num_frames;
+        let expected = file.metadata.channels * matrices_per_channel * matrix_len * 4;
+        if data.len() != expected {
+            return Err(format!("{} holds {} bytes, expected {}", dir.join(DATA_FILE).display(), data.len(), expected).into());
+        }
+        let mut values = data.chunks_exact(4).map(|bytes| f32::from_le_bytes(bytes.try_into().expect("4 bytes")));
+        let mut matrix = || -> Vec<Vec<f32>> {
+            (0..file.num_bins).map(|_| values.by_ref().take(file.num_frames).collect()).collect()
+        };
+        let spectrograms = (0..file.metadata.channels)
+            .map(|_| Spectrogram {
+                magnitude: matrix(),
+                phase: file.has_phase.then(&mut matrix),
+                sample_rate: file.metadata.sample_rate,
+                num_samples: file.metadata.num_samples,
+                config: file.metadata.config.clone(),
+            })
+            .collect();
+
+        let mut export = file.export;
+        export.image_path = export.image_path.map(|path| dir.join(path));
+        export.audio_path = export.audio_path.map(|path| dir.join(path));
+        Ok(Self {
+            source: file.source.map(|source| dir.join(source)),
+            metadata: file.metadata,
+            spectrograms,
+            layers: file.layers,
+            export,
+        })
+    }
+
+    /// Save the project as a folder, created if needed; paths inside it are stored relative to it
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        let dir = project_dir(path);
+        fs::create_dir_all(&dir)?;
+        let first = &self.spectrograms[0];
+
+        let mut export = self.export.clone();
+        export.image_path = export.image_path.map(|path| relative_path(&path, &dir));
+        export.audio_path = export.audio_path.map(|path| relative_path(&path, &dir));
+        let file = ProjectFile {
+            version: PROJECT_VERSION,
+            source: self.source.as_ref().map(|source| relative_path(source, &dir)),
+            num_bins: first.num_bins(),
+            num_frames: first.num_frames(),
+            has_phase: first.phase.is_some(),
+            export,
+            metadata: SpectrogramMetadata { channels: self.spectrograms.len(), ..self.metadata.clone() },
+            layers: self.layers.clone(),
+        };
+        fs::write(dir.join(PROJECT_FILE), toml::to_string_pretty(&file)?)?;
+
+        let mut writer = BufWriter::new(File::create(dir.join(DATA_FILE))?);
+        for spectrogram in &self.spectrograms {
+            let matrices = std::iter::once(&spectrogram.magnitude).chain(spectrogram.phase.as_ref().filter(|_| file.has_phase));
+            for value in matrices.flatten().flatten() {
+                writer.write_all(&value.to_le_bytes())?;
+            }
+        }
+        writer.flush()?;
+        println!("Saved project to: {}", dir.display());
+        Ok(())
+    }
+
+    /// The spectrograms with the visible layers applied
+    pub fn edited_spectrograms(&self) -> Vec<Spectrogram> {
+        Layers::from_layers(self.layers.clone()).render(&self.spectrograms, self.reference_magnitude())
+    }
+
+    /// Magnitude 0 dB stands for in edits and rendered images
+    pub fn reference_magnitude(&self) -> f32 {
+        self.metadata.peak_magnitude.unwrap_or(1.0)
+    }
+
+    /// Where rendering the project saved in `dir` writes the image and the audio
+    pub fn export_paths(&self, dir: &Path) -> (Option<PathBuf>, Option<PathBuf>) {
+        let dir = project_dir(dir);
+        let image = self.export.image.then(|| {
+            self.export.image_path.clone()
+                .unwrap_or_else(|| dir.join(format!("export.{}", self.metadata.config.image_format.extension())))
+        });
+        let audio = self.export.audio.then(|| {
+            self.export.audio_path.clone()
+                .unwrap_or_else(|| dir.join(format!("export.{}", self.metadata.config.output_format.extension())))
+        });
+        (image, audio)
+    }
+
+    /// Render the edited spectrogram as set in `export`, for the project saved in `dir`
+    /// Returns the paths written
+    pub fn render(&self, dir: &Path, progress: &Progress) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+        let spectrograms = self.edited_spectrograms();
+        let (image_path, audio_path) = self.export_paths(dir);
+        let mut written = Vec::new();
+
+        if let Some(image_path) = image_path {
+            progress.status("Saving image...");
+            let mut metadata = self.metadata.clone();
+            written.extend(save_spectrograms(&spectrograms, self.reference_magnitude(), &mut metadata, &image_path)?);
+        }
+        if let Some(audio_path) = audio_path {
+            let AudioData { channels, sample_rate } = spectrograms_to_audio(&spectrograms, &self.metadata, progress)?;
+            progress.status("Writing audio file...");
+            write_audio(&audio_path, &channels, sample_rate, &self.metadata.config)?;
+            println!("Saved {}-channel audio to: {}", channels.len(), audio_path.display());
+            written.push(audio_path);
+        }
+        if written.is_empty() {
+            return Err("The project's export settings render neither an image nor audio".into());
+        }
+        Ok(written)
+    }
+}
+
+/// Whether `path` is a project folder (or the `project.toml` in one)
+pub fn is_project(path: &Path) -> bool {
+    if path.is_dir() {
+        path.join(PROJECT_FILE).is_file()
+    } else {
+        path.file_name().is_some_and(|name| name == PROJECT_FILE)
+    }
+}
+
+/// Project folder of a folder or `project.toml` path
+pub fn project_dir(path: &Path) -> PathBuf {
+    if path.file_name().is_some_and(|name| name == PROJECT_FILE) {
+        path.parent().unwrap_or(Path::new("")).to_path_buf()
+    } else {
+        path.to_path_buf()
+    }
+}
+
+// Absolute path, resolving symlinks when it exists
+fn absolute(path: &Path) -> PathBuf {
+    fs::canonicalize(path)
+        .or_else(|_| std::path::absolute(path))
+        .unwrap_or_else(|_| path.to_path_buf())
+}
+
+// `path` relative to `dir` when both are on the same root, so projects can be moved along with
+// their source files
+fn relative_path(path: &Path, dir: &Path) -> PathBuf {
+    let (path, dir) = (absolute(path), absolute(dir));
+    let path_components: Vec<Component> = path.components().collect();
+    let dir_components: Vec<Component> = dir.components().collect();
+    let common = path_components.iter().zip(&dir_components).take_while(|(a, b)| a == b).count();
+    if common == 0 {
+        return path;
+    }
+    let mut relative: PathBuf = dir_components[common..].iter().map(|_| Component::ParentDir).collect();
+    relative.extend(&path_components[common..]);
+    relative
+}
@@ -0,0 +1,151 @@
+use eframe::egui;
+use egui::Color32;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use spectrogram_converter::batch::{collect_inputs, run_batch, BatchSummary, JobStatus};
+use spectrogram_converter::{CancelToken, SpectrogramConfig};
+
+/// Files queued for conversion, converted on a pool of workers with each job's status listed
+pub struct BatchQueue {
+    inputs: Vec<PathBuf>,
+    // One status per input, updated by the workers
+    statuses: Arc<Mutex<Vec<JobStatus>>>,
+    // Set while the workers run
+    running: Arc<AtomicBool>,
+    cancel_token: CancelToken,
+    // Files converted at once (0 = one per CPU core)
+    workers: usize,
+}
+
+impl BatchQueue {
+    pub fn new() -> Self {
+        Self {
+            inputs: Vec::new(),
+            statuses: Arc::new(Mutex::new(Vec::new())),
+            running: Arc::new(AtomicBool::new(false)),
+            cancel_token: CancelToken::new(),
+            workers: 0,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inputs.is_empty()
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::Relaxed)
+    }
+
+    /// Queue files, and the files in folders; returns how many were added
+    /// Files already queued are left where they are
+    pub fn add(&mut self, paths: &[PathBuf]) -> Result<usize, Box<dyn std::error::Error>> {
+        let mut statuses = self.statuses.lock().unwrap();
+        let mut added = 0;
+        for input in collect_inputs(paths)? {
+            if !self.inputs.contains(&input) {
+                self.inputs.push(input);
+                statuses.push(JobStatus::Queued);
+                added += 1;
+            }
+        }
+        Ok(added)
+    }
+
+    // Convert every file that has not been converted yet, on a background thread
+    fn start(&mut self, config: &SpectrogramConfig) {
+        let mut statuses = self.statuses.lock().unwrap();
+        let pending: Vec<usize> = (0..self.inputs.len()).filter(|&i| !matches!(statuses[i], JobStatus::Done { .. })).collect();
+        if pending.is_empty() {
+            return;
+        }
+        for &i in &pending {
+            statuses[i] = JobStatus::Queued;
+        }
+        drop(statuses);
+
+        let inputs: Vec<PathBuf> = pending.iter().map(|&i| self.inputs[i].clone()).collect();
+        let statuses = self.statuses.clone();
+        let running = self.running.clone();
+        self.cancel_token = CancelToken::new();
+        let cancel_token = self.cancel_token.clone();
+        let config = config.clone();
+        let workers = self.workers;
+        running.store(true, Ordering::Relaxed);
+        thread::spawn(move || {
+            let report = |index: usize, status: JobStatus| statuses.lock().unwrap()[pending[index]] = status;
+            run_batch(&inputs, &config, workers, &cancel_token, &report);
+            running.store(false, Ordering::Relaxed);
+        });
+    }
+
+    /// Show the queue with its controls
+    /// Returns the file clicked in the list, to select it
+    pub fn show(&mut self, ui: &mut egui::Ui, config: &SpectrogramConfig) -> Option<PathBuf> {
+        let running = self.is_running();
+        if running {
+            ui.ctx().request_repaint();
+        }
+        let statuses = self.statuses.lock().unwrap().clone();
+        let pending = statuses.iter().any(|status| !matches!(status, JobStatus::Done { .. }));
+
+        ui.horizontal(|ui| {
+            ui.label(format!("Batch: {} files", self.inputs.len()));
+            if ui.add_enabled(!running && pending, egui::Button::new("🚀 Convert All")).clicked() {
+                self.start(config);
+            }
+            if ui.add_enabled(running && !self.cancel_token.is_cancelled(), egui::Button::new("Cancel")).clicked() {
+                self.cancel_token.cancel();
+            }
+            if ui.add_enabled(!running, egui::Button::new("Clear")).clicked() {
+                self.inputs.clear();
+                self.statuses.lock().unwrap().clear();
+            }
+            ui.add_enabled(!running, egui::DragValue::new(&mut self.workers).range(0..=64));
+            ui.label(if self.workers == 0 { "at once (one per CPU core)" } else { "at once" });
+        });
+
+        let mut clicked = None;
+        egui::ScrollArea::vertical()
+            .id_salt("batch_scroll")
+            .max_height(150.0)
+            .show(ui, |ui| {
+                for (input, status) in self.inputs.iter().zip(&statuses) {
+                    ui.horizontal(|ui| {
+                        let name = input.file_name().unwrap_or_default().to_string_lossy();
+                        if ui.link(name).on_hover_text(input.display().to_string()).clicked() {
+                            clicked = Some(input.clone());
+                        }
+                        match status {
+                            JobStatus::Queued => {
+                                ui.label("Queued");
+                            }
+                            JobStatus::Running { status, fraction } => {
+                                match fraction {
+                                    Some(fraction) => ui.add(egui::ProgressBar::new(*fraction).desired_width(100.0)),
+                                    None => ui.spinner(),
+                                };
+                                ui.label(status);
+                            }
+                            JobStatus::Done { output_path } => {
+                                ui.label(format!("✓ {}", output_path.display()));
+                            }
+                            JobStatus::Failed { message } => {
+                                ui.colored_label(Color32::from_rgb(255, 100, 100), format!("✗ {}", message));
+                            }
+                            JobStatus::Cancelled => {
+                                ui.label("Cancelled");
+                            }
+                        }
+                    });
+                }
+            });
+
+        if !running && statuses.iter().any(JobStatus::is_finished) {
+            ui.label(format!("Summary: {}", BatchSummary::of(&statuses)));
+        }
+        clicked
+    }
+}
@@ -0,0 +1,347 @@
+use rayon::prelude::*;
+use crate::config::{FrequencyScale, Padding, SpectrogramConfig};
+use crate::phase_retrieval::reconstruct_phase;
+use crate::progress::{Cancelled, Progress};
+use crate::stft::{istft, pad_signal, stft_with_progress, Window};
+
+/// Magnitude/phase spectrogram in image layout
+/// Matrices are indexed as [bin][frame], bin 0 being the lowest frequency of the
+/// configured scale (linear, log, mel, ...), so one bin maps to one image row
+#[derive(Debug, Clone)]
+pub struct Spectrogram {
+    /// Linear magnitude per bin and frame
+    pub magnitude: Vec<Vec<f32>>,
+
+    /// Phase in radians per bin and frame
+    /// None when only magnitude is known - Griffin-Lim estimates it on synthesis
+    pub phase: Option<Vec<Vec<f32>>>,
+
+    /// Sample rate of the analyzed audio (Hz)
+    pub sample_rate: u32,
+
+    /// Length of the analyzed audio in samples
+    pub num_samples: usize,
+
+    /// Settings used for analysis (FFT size, hop size, frequency scale, ...)
+    pub config: SpectrogramConfig,
+}
+
+impl Spectrogram {
+    /// Number of frequency bins (image height)
+    pub fn num_bins(&self) -> usize {
+        self.magnitude.len()
+    }
+
+    /// Number of STFT frames (image width)
+    pub fn num_frames(&self) -> usize {
+        self.magnitude.first().map_or(0, |row| row.len())
+    }
+
+    /// Center frequency of a bin in Hz, following the configured frequency scale
+    pub fn bin_frequency(&self, bin: usize) -> f32 {
+        FrequencyAxis::new(&self.config, self.sample_rate, self.num_bins()).frequency(bin as f32)
+    }
+
+    /// Fractional bin of a frequency in Hz, following the configured frequency scale
+    /// None outside the spectrogram's frequency range
+    pub fn frequency_bin(&self, freq: f32) -> Option<f32> {
+        FrequencyAxis::new(&self.config, self.sample_rate, self.num_bins()).bin(freq)
+    }
+
+    /// Time in seconds of the center of a (fractional) frame
+    pub fn frame_time(&self, frame: f32) -> f32 {
+        // Unpadded frames start at the first sample instead of being centered on it
+        let first_center = if self.config.padding == Padding::None { self.config.fft_size / 2 } else { 0 };
+        (frame * self.config.hop_size as f32 + first_center as f32) / self.sample_rate as f32
+    }
+
+    /// High-frequency boost applied to a bin in the image (dB)
+    pub fn boost_db(&self, bin: usize) -> f32 {
+        let freq = self.bin_frequency(bin);
+        if freq > self.config.boost_start_freq {
+            self.config.boost_db_per_octave * (freq / self.config.boost_start_freq).log2()
+        } else {
+            0.0
+        }
+    }
+
+    /// Largest magnitude in the spectrogram
+    pub fn max_magnitude(&self) -> f32 {
+        self.magnitude.iter()
+            .flat_map(|row| row.iter())
+            .cloned()
+            .fold(0.0f32, f32::max)
+    }
+}
+
+/// Analyze mono samples into a spectrogram using the given configuration
+pub fn analyze(samples: &[f32], sample_rate: u32, config: &SpectrogramConfig) -> Spectrogram {
+    // Progress::none() is never cancelled
+    analyze_with_progress(samples, sample_rate, config, &Progress::none()).expect("analysis without cancellation")
+}
+
+/// `analyze` reporting progress per STFT frame, stopping early when cancelled
+pub fn analyze_with_progress(
+    samples: &[f32],
+    sample_rate: u32,
+    config: &SpectrogramConfig,
+    progress: &Progress,
+) -> Result<Spectrogram, Cancelled> {
+    with_threads(config, || analyze_frames(samples, sample_rate, config, progress))
+}
+
+fn analyze_frames(
+    samples: &[f32],
+    sample_rate: u32,
+    config: &SpectrogramConfig,
+    progress: &Progress,
+) -> Result<Spectrogram, Cancelled> {
+    // Center the first frame on the first sample and extend the end to a whole frame
+    let padded;
+    let signal = if config.padding == Padding::None {
+        samples
+    } else {
+        let before = config.fft_size / 2;
+        let padded_len = (config.num_frames(samples.len()) - 1) * config.hop_size + config.fft_size;
+        padded = pad_signal(samples, config.padding, before, padded_len - before - samples.len());
+        &padded
+    };
+
+    analyze_segment(signal, sample_rate, samples.len(), config, progress)
+}
+
+// Spectrogram of the frames of an already padded signal (or a segment of one, for tiles)
+// `num_samples` is the length of the whole unpadded signal
+pub(crate) fn analyze_segment(
+    signal: &[f32],
+    sample_rate: u32,
+    num_samples: usize,
+    config: &SpectrogramConfig,
+    progress: &Progress,
+) -> Result<Spectrogram, Cancelled> {
+    let (mag_linear, phase_linear) = stft_with_progress(signal, &Window::new(config), progress)?;
+    let num_bins_linear = mag_linear.len();
+    let num_frames = mag_linear[0].len();
+
+    let axis = FrequencyAxis::new(config, sample_rate, config.image_height());
+
+    let (magnitude, phase) = if axis.is_fft_bins(num_bins_linear) {
+        // Image rows are the FFT bins
+        (mag_linear, phase_linear)
+    } else {
+        let mut magnitude = vec![vec![0.0f32; num_frames]; axis.num_bins];
+        let mut phase = vec![vec![0.0f32; num_frames]; axis.num_bins];
+
+        magnitude.par_iter_mut().zip(phase.par_iter_mut()).enumerate().for_each(|(bin, (magnitude, phase))| {
+            // Fractional FFT bin at this row's frequency
+            let bin_linear_float = axis.frequency(bin as f32) / axis.nyquist * (num_bins_linear - 1) as f32;
+
+            // Interpolate magnitude and phase from linear bins
+            for frame_idx in 0..num_frames {
+                let (mag, ph) = interpolate_spectrum(&mag_linear, &phase_linear, bin_linear_float, frame_idx);
+                magnitude[frame_idx] = mag;
+                phase[frame_idx] = ph;
+            }
+        });
+
+        (magnitude, phase)
+    };
+
+    Ok(Spectrogram {
+        magnitude,
+        phase: Some(phase),
+        sample_rate,
+        num_samples,
+        config: config.clone(),
+    })
+}
+
+/// Resynthesize mono samples from a spectrogram
+/// Missing phase is reconstructed with `config.phase_retrieval`; the output is not normalized and has
+/// `num_samples` samples
+pub fn synthesize(spectrogram: &Spectrogram) -> Vec<f32> {
+    // Progress::none() is never cancelled
+    synthesize_with_progress(spectrogram, &Progress::none()).expect("synthesis without cancellation")
+}
+
+/// `synthesize` reporting phase retrieval progress, stopping early when cancelled
+pub fn synthesize_with_progress(spectrogram: &Spectrogram, progress: &Progress) -> Result<Vec<f32>, Cancelled> {
+    with_threads(&spectrogram.config, || synthesize_frames(spectrogram, progress))
+}
+
+fn synthesize_frames(spectrogram: &Spectrogram, progress: &Progress) -> Result<Vec<f32>, Cancelled> {
+    let config = &spectrogram.config;
+    let fft_size = config.fft_size;
+    let (mag_linear, phase_linear) = linear_spectrum(spectrogram);
+
+    let window = Window::new(config);
+
+    // Estimate phase (Griffin-Lim, PGHI, ...) if it was not stored
+    let phase_linear = match phase_linear {
+        Some(phase_linear) => phase_linear,
+        None => reconstruct_phase(&mag_linear, &window, config, progress)?,
+    };
+
+    let mut output = istft(&mag_linear, &phase_linear, &window);
+
+    // Drop the padding and give back exactly the analyzed length
+    if config.padding != Padding::None {
+        output.drain(..(fft_size / 2).min(output.len()));
+    }
+    output.resize(spectrogram.num_samples, 0.0);
+    Ok(output)
+}
+
+// Magnitude and phase (None if not stored) per FFT bin, mapped back from the image rows
+pub(crate) fn linear_spectrum(spectrogram: &Spectrogram) -> (Vec<Vec<f32>>, Option<Vec<Vec<f32>>>) {
+    let config = &spectrogram.config;
+    let num_bins_linear = config.fft_size / 2 + 1;
+    let num_bins_image = spectrogram.num_bins();
+    let num_frames = spectrogram.num_frames();
+
+    let zero_phase;
+    let phase_image = match &spectrogram.phase {
+        Some(phase) => phase,
+        None => {
+            zero_phase = vec![vec![0.0f32; num_frames]; num_bins_image];
+            &zero_phase
+        }
+    };
+
+    let axis = FrequencyAxis::new(config, spectrogram.sample_rate, num_bins_image);
+
+    // Map image rows back to FFT bins
+    let (mag_linear, phase_linear) = if axis.is_fft_bins(num_bins_linear) {
+        (spectrogram.magnitude.clone(), phase_image.clone())
+    } else {
+        let mut mag_linear = vec![vec![0.0f32; num_frames]; num_bins_linear];
+        let mut phase_linear = vec![vec![0.0f32; num_frames]; num_bins_linear];
+
+        mag_linear.par_iter_mut().zip(phase_linear.par_iter_mut()).enumerate().for_each(|(linear_bin, (mag_linear, phase_linear))| {
+            let freq_linear = (linear_bin as f32 / (num_bins_linear - 1) as f32) * axis.nyquist;
+            // FFT bins outside the image's frequency range stay silent
+            let Some(image_bin_float) = axis.bin(freq_linear) else {
+                return;
+            };
+
+            for frame_idx in 0..num_frames {
+                let (mag, phase) = interpolate_spectrum(&spectrogram.magnitude, phase_image, image_bin_float, frame_idx);
+                mag_linear[frame_idx] = mag;
+                phase_linear[frame_idx] = phase;
+            }
+        });
+
+        (mag_linear, phase_linear)
+    };
+
+    (mag_linear, spectrogram.phase.is_some().then_some(phase_linear))
+}
+
+// Run `op` on a pool of `config.num_threads` threads (one per CPU core when 0)
+// Work is split the same way for any thread count, so results do not depend on it
+fn with_threads<R: Send>(config: &SpectrogramConfig, op: impl FnOnce() -> R + Send) -> R {
+    in_pool(thread_pool(config).as_ref(), op)
+}
+
+// Pool of `config.num_threads` threads, None if it cannot be created
+pub(crate) fn thread_pool(config: &SpectrogramConfig) -> Option<rayon::ThreadPool> {
+    rayon::ThreadPoolBuilder::new().num_threads(config.num_threads).build().ok()
+}
+
+// Run `op` on `pool`, or on the calling thread (and the global pool) without one
+pub(crate) fn in_pool<R: Send>(pool: Option<&rayon::ThreadPool>, op: impl FnOnce() -> R + Send) -> R {
+    match pool {
+        Some(pool) => pool.install(op),
+        None => op(),
+    }
+}
+
+// Mapping between image rows and frequencies: rows are evenly spaced on the
+// configured scale between min and max frequency
+struct FrequencyAxis {
+    scale: FrequencyScale,
+    min_freq: f32,
+    max_freq: f32,
+    nyquist: f32,
+    num_bins: usize,
+    // Scale positions of the lowest and highest row
+    low: f32,
+    high: f32,
+}
+
+impl FrequencyAxis {
+    fn new(config: &SpectrogramConfig, sample_rate: u32, num_bins: usize) -> Self {
+        let (min_freq, max_freq) = config.frequency_range(sample_rate);
+        let scale = config.frequency_scale;
+        Self {
+            scale,
+            min_freq,
+            max_freq,
+            nyquist: sample_rate as f32 / 2.0,
+            num_bins,
+            low: scale.warp(min_freq),
+            high: scale.warp(max_freq),
+        }
+    }
+
+    // Frequency (Hz) of a fractional row
+    fn frequency(&self, bin: f32) -> f32 {
+        let t = bin / (self.num_bins - 1).max(1) as f32;
+        self.scale.unwarp(self.low + t * (self.high - self.low))
+    }
+
+    // Fractional row of a frequency, None outside the image's range
+    fn bin(&self, freq: f32) -> Option<f32> {
+        if freq < self.min_freq || freq > self.max_freq || self.high <= self.low {
+            return None;
+        }
+        let t = (self.scale.warp(freq) - self.low) / (self.high - self.low);
+        Some((t * (self.num_bins - 1) as f32).clamp(0.0, (self.num_bins - 1) as f32))
+    }
+
+    // Whether rows coincide with the FFT bins (linear 0 Hz - Nyquist at FFT resolution)
+    fn is_fft_bins(&self, num_bins_linear: usize) -> bool {
+        self.scale == FrequencyScale::Linear && self.min_freq == 0.0 && self.max_freq == self.nyquist
+            && self.num_bins == num_bins_linear
+    }
+}
+
+// Interpolate magnitude and phase from a spectrum at a fractional bin index
+fn interpolate_spectrum(
+    mag: &[Vec<f32>],
+    phase: &[Vec<f32>],
+    bin_float: f32,
+    frame: usize,
+) -> (f32, f32) {
+    let bin_floor = bin_float.floor() as usize;
+    let bin_ceil = (bin_float.ceil() as usize).min(mag.len() - 1);
+    let frac = bin_float - bin_floor as f32;
+
+    if bin_floor >= mag.len() {
+        return (0.0, 0.0);
+    }
+
+    if bin_floor == bin_ceil {
+        return (mag[bin_floor][frame], phase[bin_floor][frame]);
+    }
+
+    // Linear interpolation for magnitude
+    let mag_interp = mag[bin_floor][frame] * (1.0 - frac) + mag[bin_ceil][frame] * frac;
+
+    // Phase interpolation (handle wraparound)
+    let phase1 = phase[bin_floor][frame];
+    let phase2 = phase[bin_ceil][frame];
+    let mut phase_diff = phase2 - phase1;
+
+    // Wrap phase difference to [-π, π]
+    while phase_diff > std::f32::consts::PI {
+        phase_diff -= 2.0 * std::f32::consts::PI;
+    }
+    while phase_diff < -std::f32::consts::PI {
+        phase_diff += 2.0 * std::f32::consts::PI;
+    }
+
+    let phase_interp = phase1 + phase_diff * frac;
+
+    (mag_interp, phase_interp)
+}
@@ -0,0 +1,375 @@
+use rayon::prelude::*;
+use rustfft::{Fft, FftPlanner, num_complex::Complex};
+use std::fmt;
+use std::sync::Arc;
+use crate::config::{Padding, SpectrogramConfig, WindowFunction};
+use crate::progress::{Cancelled, Progress};
+
+// Frames transformed in parallel between progress reports and cancellation checks
+const FRAMES_PER_BLOCK: usize = 256;
+
+/// Analysis and synthesis windows for one FFT size and hop size, computed once
+/// together with the forward and inverse FFT plans (shared by all threads)
+#[derive(Clone)]
+pub struct Window {
+    /// Applied to each frame before the FFT
+    pub analysis: Vec<f32>,
+
+    /// Applied to each inverse FFT frame before overlap-add
+    /// Dual of `analysis`: overlapping analysis * synthesis products sum to 1 at `hop_size`
+    pub synthesis: Vec<f32>,
+
+    /// Distance between consecutive frames in samples
+    pub hop_size: usize,
+
+    forward: Arc<dyn Fft<f32>>,
+    inverse: Arc<dyn Fft<f32>>,
+}
+
+impl Window {
+    /// Windows for the FFT size, hop size and window function of `config`
+    pub fn new(config: &SpectrogramConfig) -> Self {
+        let fft_size = config.fft_size;
+        let hop_size = config.hop_size;
+        let analysis: Vec<f32> = (0..fft_size)
+            .map(|i| window_value(config, i, fft_size))
+            .collect();
+
+        // Every sample is covered by the frames whose offsets are congruent modulo the hop size;
+        // dividing by their summed squared windows makes overlap-add of analysis * synthesis exact
+        let mut overlap = vec![0.0f32; hop_size];
+        for (i, &w) in analysis.iter().enumerate() {
+            overlap[i % hop_size] += w * w;
+        }
+        let synthesis = analysis.iter()
+            .enumerate()
+            .map(|(i, &w)| {
+                let sum = overlap[i % hop_size];
+                if sum > 1e-8 { w / sum } else { 0.0 }
+            })
+            .collect();
+
+        let mut planner = FftPlanner::new();
+        let forward = planner.plan_fft_forward(fft_size);
+        let inverse = planner.plan_fft_inverse(fft_size);
+
+        Self { analysis, synthesis, hop_size, forward, inverse }
+    }
+
+    /// Frame length in samples (the FFT size)
+    pub fn len(&self) -> usize {
+        self.analysis.len()
+    }
+
+    /// Whether the frame length is zero
+    pub fn is_empty(&self) -> bool {
+        self.analysis.is_empty()
+    }
+
+    /// Forward FFT of `fft_size` points
+    pub fn fft(&self) -> &Arc<dyn Fft<f32>> {
+        &self.forward
+    }
+
+    /// Inverse FFT of `fft_size` points (unnormalized)
+    pub fn ifft(&self) -> &Arc<dyn Fft<f32>> {
+        &self.inverse
+    }
+}
+
+impl fmt::Debug for Window {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Window")
+            .field("analysis", &self.analysis)
+            .field("synthesis", &self.synthesis)
+            .field("hop_size", &self.hop_size)
+            .finish_non_exhaustive()
+    }
+}
+
+// Value of the periodic window at sample i of an fft_size window
+fn window_value(config: &SpectrogramConfig, i: usize, fft_size: usize) -> f32 {
+    use std::f64::consts::PI;
+    let n = fft_size as f64;
+    let phase = 2.0 * PI * i as f64 / n;
+    let value = match config.window {
+        WindowFunction::Hann => 0.5 - 0.5 * phase.cos(),
+        WindowFunction::Hamming => 0.54 - 0.46 * phase.cos(),
+        WindowFunction::BlackmanHarris => {
+            0.35875 - 0.48829 * phase.cos() + 0.14128 * (2.0 * phase).cos() - 0.01168 * (3.0 * phase).cos()
+        }
+        WindowFunction::Kaiser => {
+            let beta = config.kaiser_beta as f64;
+            let x = 2.0 * i as f64 / n - 1.0;
+            bessel_i0(beta * (1.0 - x * x).max(0.0).sqrt()) / bessel_i0(beta)
+        }
+        WindowFunction::Gaussian => {
+            let x = (i as f64 - n / 2.0) / (config.gaussian_sigma as f64 * n / 2.0);
+            (-0.5 * x * x).exp()
+        }
+        WindowFunction::Rectangular => 1.0,
+    };
+    value as f32
+}
+
+// Zeroth-order modified Bessel function of the first kind (power series)
+fn bessel_i0(x: f64) -> f64 {
+    let half_x_squared = (x / 2.0) * (x / 2.0);
+    let mut term = 1.0;
+    let mut sum = 1.0;
+    for k in 1..200 {
+        term *= half_x_squared / (k * k) as f64;
+        sum += term;
+        if term < sum * 1e-12 {
+            break;
+        }
+    }
+    sum
+}
+
+/// Extend a signal by `before` samples at the start and `after` samples at the end
+/// Reflection repeats as often as needed for signals shorter than the padding
+pub fn pad_signal(samples: &[f32], padding: Padding, before: usize, after: usize) -> Vec<f32> {
+    let len = samples.len() as isize;
+    let value = |i: isize| -> f32 {
+        if (0..len).contains(&i) {
+            return samples[i as usize];
+        }
+        match padding {
+            _ if len == 0 => 0.0,
+            Padding::None | Padding::Zero => 0.0,
+            Padding::Edge => samples[i.clamp(0, len - 1) as usize],
+            Padding::Reflect if len == 1 => samples[0],
+            Padding::Reflect => {
+                // Mirror without repeating the end samples: period 2 * (len - 1)
+                let period = 2 * (len - 1);
+                let i = i.rem_euclid(period);
+                samples[(if i < len { i } else { period - i }) as usize]
+            }
+        }
+    };
+    (-(before as isize)..len + after as isize).map(value).collect()
+}
+
+// (magnitude, phase) matrices indexed as [bin][frame]
+type Polar = (Vec<Vec<f32>>, Vec<Vec<f32>>);
+
+/// Short-time Fourier transform of a mono signal
+/// Returns (magnitude, phase) matrices indexed as [bin][frame], positive frequencies only
+pub fn stft(samples: &[f32], window: &Window) -> Polar {
+    polar(stft_complex(samples, window))
+}
+
+/// `stft` reporting progress per frame, stopping early when cancelled
+pub fn stft_with_progress(
+    samples: &[f32],
+    window: &Window,
+    progress: &Progress,
+) -> Result<Polar, Cancelled> {
+    Ok(polar(stft_complex_with_progress(samples, window, progress)?))
+}
+
+// Split a complex spectrogram into magnitude and phase
+fn polar(spectrum: Vec<Vec<Complex<f32>>>) -> Polar {
+    let magnitude = spectrum.iter().map(|row| row.iter().map(|value| value.norm()).collect()).collect();
+    let phase = spectrum.iter().map(|row| row.iter().map(|value| value.arg()).collect()).collect();
+    (magnitude, phase)
+}
+
+/// Short-time Fourier transform as complex values indexed as [bin][frame], positive frequencies only
+pub fn stft_complex(samples: &[f32], window: &Window) -> Vec<Vec<Complex<f32>>> {
+    // Progress::none() is never cancelled
+    stft_complex_with_progress(samples, window, &Progress::none()).expect("STFT without cancellation")
+}
+
+// `stft_complex` reporting progress per frame, stopping early when cancelled
+fn stft_complex_with_progress(
+    samples: &[f32],
+    window: &Window,
+    progress: &Progress,
+) -> Result<Vec<Vec<Complex<f32>>>, Cancelled> {
+    let fft_size = window.len();
+    let hop_size = window.hop_size;
+    let fft = window.fft();
+
+    // Signals shorter than one frame are analyzed as one zero-filled frame
+    let num_frames = samples.len().saturating_sub(fft_size) / hop_size + 1;
+    let num_bins = fft_size / 2 + 1; // Only positive frequencies (no mirror)
+
+    let mut spectrum = vec![vec![Complex::new(0.0f32, 0.0); num_frames]; num_bins];
+
+    for block_start in (0..num_frames).step_by(FRAMES_PER_BLOCK) {
+        progress.check()?;
+        progress.update("Computing spectrogram", block_start, num_frames);
+
+        // Transform the block's frames in parallel, each thread with its own scratch buffer
+        let block_end = (block_start + FRAMES_PER_BLOCK).min(num_frames);
+        let frames: Vec<Vec<Complex<f32>>> = (block_start..block_end)
+            .into_par_iter()
+            .map_init(
+                || vec![Complex::new(0.0, 0.0); fft.get_inplace_scratch_len()],
+                |scratch, frame_idx| {
+                    let start = frame_idx * hop_size;
+                    let end = (start + fft_size).min(samples.len());
+
+                    // Apply the analysis window and prepare FFT input
+                    let mut buffer = vec![Complex::new(0.0, 0.0); fft_size];
+                    for ((value, &s), &w) in buffer.iter_mut().zip(&samples[start..end]).zip(&window.analysis) {
+                        *value = Complex::new(s * w, 0.0);
+                    }
+
+                    fft.process_with_scratch(&mut buffer, scratch);
+                    buffer.truncate(num_bins);
+                    buffer
+                },
+            )
+            .collect();
+
+        // Store the spectrum (only positive frequencies)
+        for (frame_idx, frame) in (block_start..block_end).zip(frames) {
+            for (row, value) in spectrum.iter_mut().zip(frame) {
+                row[frame_idx] = value;
+            }
+        }
+    }
+
+    Ok(spectrum)
+}
+
+/// Inverse STFT by windowed overlap-add
+/// `magnitude` and `phase` are indexed as [bin][frame] with fft_size / 2 + 1 bins
+pub fn istft(magnitude: &[Vec<f32>], phase: &[Vec<f32>], window: &Window) -> Vec<f32> {
+    let num_frames = magnitude[0].len();
+    let mut overlap_add = OverlapAdd::new(window.clone(), num_frames);
+    overlap_add.add(0, magnitude, phase);
+    overlap_add.finish()
+}
+
+/// Inverse STFT fed a block of frames at a time, for signals too long to hold as one spectrogram
+/// Samples are handed out as soon as no later frame overlaps them; the output is the same as
+/// `istft` of all frames at once
+pub struct OverlapAdd {
+    window: Window,
+    num_frames: usize,
+    floor: f32,
+    // Overlap-added samples and window products from absolute sample `start` on
+    output: Vec<f32>,
+    window_sum: Vec<f32>,
+    start: usize,
+}
+
+impl OverlapAdd {
+    /// Overlap-add of a signal of `num_frames` frames in total
+    pub fn new(window: Window, num_frames: usize) -> Self {
+        Self {
+            floor: normalization_floor(&window, num_frames),
+            window,
+            num_frames,
+            output: Vec::new(),
+            window_sum: Vec::new(),
+            start: 0,
+        }
+    }
+
+    /// Add frames `first_frame..` (indexed as [bin][frame] like `istft`)
+    /// Blocks must come in order; samples already handed out are not touched again
+    pub fn add(&mut self, first_frame: usize, magnitude: &[Vec<f32>], phase: &[Vec<f32>]) {
+        let window = &self.window;
+        let fft_size = window.len();
+        let hop_size = window.hop_size;
+        let num_bins = magnitude.len();
+        let block_frames = magnitude.first().map_or(0, |row| row.len());
+        let ifft = window.ifft();
+
+        let end = (first_frame + block_frames).min(self.num_frames);
+        if end <= first_frame {
+            return;
+        }
+        let output_end = (end - 1) * hop_size + fft_size - self.start;
+        if self.output.len() < output_end {
+            self.output.resize(output_end, 0.0);
+            self.window_sum.resize(output_end, 0.0);
+        }
+
+        for block_start in (first_frame..end).step_by(FRAMES_PER_BLOCK) {
+            // Inverse transform the block's frames in parallel, each thread with its own scratch buffer
+            let block_end = (block_start + FRAMES_PER_BLOCK).min(end);
+            let frames: Vec<Vec<Complex<f32>>> = (block_start..block_end)
+                .into_par_iter()
+                .map_init(
+                    || vec![Complex::new(0.0, 0.0); ifft.get_inplace_scratch_len()],
+                    |scratch, frame_idx| {
+                        let column = frame_idx - first_frame;
+                        let mut spectrum = vec![Complex::new(0.0, 0.0); fft_size];
+
+                        // Build complex spectrum from magnitude and phase
+                        let num_bins_to_use = num_bins.min(fft_size / 2 + 1);
+                        for bin in 0..num_bins_to_use {
+                            let mag = magnitude[bin][column];
+                            let phase = phase[bin][column];
+                            spectrum[bin] = Complex::new(mag * phase.cos(), mag * phase.sin());
+                        }
+
+                        // Mirror for negative frequencies
+                        for bin in 1..num_bins_to_use.min(fft_size / 2) {
+                            spectrum[fft_size - bin] = spectrum[bin].conj();
+                        }
+
+                        ifft.process_with_scratch(&mut spectrum, scratch);
+                        spectrum
+                    },
+                )
+                .collect();
+
+            // Overlap-add with the synthesis window, in frame order so the sums do not depend on threading
+            for (frame_idx, frame) in (block_start..block_end).zip(frames) {
+                let start = frame_idx * hop_size;
+                for (i, &value) in frame.iter().enumerate() {
+                    // Samples handed out already are complete
+                    let Some(position) = (start + i).checked_sub(self.start) else { continue };
+                    self.output[position] += value.re * window.synthesis[i] / fft_size as f32;
+                    self.window_sum[position] += window.analysis[i] * window.synthesis[i];
+                }
+            }
+        }
+    }
+
+    /// Take the finished samples: those before frame `next_frame`, the first frame not added yet
+    pub fn take_ready(&mut self, next_frame: usize) -> Vec<f32> {
+        let ready = (next_frame * self.window.hop_size).saturating_sub(self.start).min(self.output.len());
+        self.take(ready)
+    }
+
+    /// Take all remaining samples, once every frame has been added
+    pub fn finish(&mut self) -> Vec<f32> {
+        let remaining = self.output.len();
+        self.take(remaining)
+    }
+
+    // Normalize by the overlapping analysis * synthesis products (1 wherever enough frames overlap)
+    // The floor fades in/out the edges, where too few windows overlap to invert reliably
+    fn take(&mut self, count: usize) -> Vec<f32> {
+        let mut samples: Vec<f32> = self.output.drain(..count).collect();
+        for (sample, sum) in samples.iter_mut().zip(self.window_sum.drain(..count)) {
+            *sample /= sum.max(self.floor);
+        }
+        self.start += count;
+        samples
+    }
+}
+
+// Normalization floor of an overlap-add of `num_frames` frames: a tenth of the largest window sum
+// Beyond fft_size / hop_size + 1 frames only the middle repeats, so at most that many are summed
+fn normalization_floor(window: &Window, num_frames: usize) -> f32 {
+    let fft_size = window.len();
+    let hop_size = window.hop_size;
+    let num_frames = num_frames.min(fft_size.div_ceil(hop_size) + 1);
+    let mut window_sum = vec![0.0f32; num_frames.saturating_sub(1) * hop_size + fft_size];
+    for frame_idx in 0..num_frames {
+        for (i, (&analysis, &synthesis)) in window.analysis.iter().zip(&window.synthesis).enumerate() {
+            window_sum[frame_idx * hop_size + i] += analysis * synthesis;
+        }
+    }
+    let max_sum = window_sum.iter().cloned().fold(0.0f32, f32::max);
+    (max_sum * 0.1).max(1e-8)
+}
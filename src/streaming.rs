@@ -0,0 +1,685 @@
+//! Bounded-memory processing of long files as tiles of `config.tile_width` frames
+//!
+//! The encoder reads the audio a chunk at a time and writes one image per tile
+//! (`name_part001.png`, `name_part002.png`, ...). Every tile holds exactly the frames a
+//! whole-file analysis would give, all rendered against one peak level, which takes a first
+//! pass over the file. A manifest (`name.tiles.toml`) lists the tiles in order.
+//! The decoder reads the tiles in order and overlap-adds them into one continuous signal;
+//! phase retrieval continues from the last frames of the previous tile. Decoding to a file
+//! writes the samples as each tile finishes them.
+
+use image::Rgb32FImage;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use crate::audio_io::{probe_audio, AudioReader, AudioWriter};
+use crate::audio_to_image::{channel_image_path, encoded_image_path, save_spectrograms};
+use crate::config::{ChannelMode, Padding, SpectrogramConfig};
+use crate::image_to_audio::{image_to_spectrogram, open_image, read_bands};
+use crate::level::{OutputLevel, PeakMeter};
+use crate::metadata::SpectrogramMetadata;
+use crate::phase_retrieval::reconstruct_phase_continuing;
+use crate::progress::{Cancelled, Progress};
+use crate::spectrogram::{analyze_segment, in_pool, linear_spectrum, thread_pool, Spectrogram};
+use crate::stft::{pad_signal, OverlapAdd, Window};
+
+// Frames per block read back from the temporary file of decoded samples
+const SPOOL_BLOCK_FRAMES: usize = 65_536;
+
+/// Path of one tile of a tiled image sequence
+/// Appends `_part{n:03}` (1-based) to the file stem, before any `_CH{n}` channel suffix
+pub fn tile_image_path(image_path: &Path, tile: usize) -> PathBuf {
+    let stem = image_path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = image_path.extension().unwrap_or_default().to_string_lossy();
+    image_path.with_file_name(format!("{}_part{:03}.{}", stem, tile + 1, extension))
+}
+
+// Manifest files end in .tiles.toml
+const MANIFEST_EXTENSION: &str = "tiles.toml";
+
+/// Index of a tiled image sequence, saved next to the tiles as `name.tiles.toml`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TileManifest {
+    /// Sample rate of the encoded audio (Hz)
+    pub sample_rate: u32,
+    /// Length of the encoded audio in samples
+    pub num_samples: usize,
+    /// Number of encoded channels
+    pub channels: usize,
+    /// Frames per tile (the last one may be narrower)
+    pub tile_width: usize,
+    /// Tiles in order
+    pub tiles: Vec<TileEntry>,
+}
+
+/// One tile of a `TileManifest`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TileEntry {
+    /// Width of the tile in frames
+    pub frames: usize,
+    /// The tile's image, or one image per channel, relative to the manifest's folder
+    pub images: Vec<String>,
+}
+
+impl TileManifest {
+    /// Load a manifest, or the only manifest in a folder
+    pub fn load(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let path = if path.is_dir() { find_manifest(path)? } else { path.to_path_buf() };
+        let manifest: Self = toml::from_str(&fs::read_to_string(&path)?)
+            .map_err(|e| format!("Invalid tile manifest {}: {}", path.display(), e))?;
+        if manifest.tiles.is_empty() {
+            return Err(format!("Tile manifest {} lists no tiles", path.display()).into());
+        }
+        Ok(manifest)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        fs::write(path, toml::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    // Tiles to decode, with images relative to the manifest at `path` (or the folder holding it)
+    fn tiles(&self, path: &Path) -> Vec<Tile> {
+        let dir = if path.is_dir() { path } else { path.parent().unwrap_or(Path::new("")) };
+        self.tiles.iter()
+            .map(|tile| Tile {
+                images: tile.images.iter().map(|image| dir.join(image)).collect(),
+                frames: Some(tile.frames),
+            })
+            .collect()
+    }
+
+    // Take the sample rate, length, channel count and tiling from the manifest, which stays
+    // complete when tools strip the metadata embedded in the images
+    fn apply_to(&self, metadata: &mut SpectrogramMetadata) -> Result<(), Box<dyn std::error::Error>> {
+        if self.num_samples == 0 || self.channels == 0 || self.sample_rate == 0 {
+            return Err(format!("Tile manifest lists {} samples of {} channels at {} Hz; there is no audio to decode",
+                               self.num_samples, self.channels, self.sample_rate).into());
+        }
+        metadata.sample_rate = self.sample_rate;
+        metadata.num_samples = self.num_samples;
+        metadata.channels = self.channels;
+        metadata.config.tile_width = self.tile_width;
+        metadata.tiles = self.tiles.len();
+        Ok(())
+    }
+}
+
+/// One tile of a sequence to decode: its image, or one image per channel, and its width in
+/// frames when a manifest lists it
+pub(crate) struct Tile {
+    pub images: Vec<PathBuf>,
+    pub frames: Option<usize>,
+}
+
+/// Path of the manifest of a tiled image sequence: the image path with `.tiles.toml`
+pub fn manifest_path(image_path: &Path) -> PathBuf {
+    image_path.with_extension(MANIFEST_EXTENSION)
+}
+
+/// Whether `path` names a tile manifest (`*.tiles.toml`)
+pub fn is_tile_manifest(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.to_lowercase().ends_with(&format!(".{}", MANIFEST_EXTENSION)))
+}
+
+// The only tile manifest in a folder
+fn find_manifest(dir: &Path) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let mut manifests = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if is_tile_manifest(&path) {
+            manifests.push(path);
+        }
+    }
+    match manifests.len() {
+        0 => Err(format!("No tile manifest (*.{}) found in {}", MANIFEST_EXTENSION, dir.display()).into()),
+        1 => Ok(manifests.remove(0)),
+        n => Err(format!("{} tile manifests found in {}, choose one of them", n, dir.display()).into()),
+    }
+}
+
+/// Stream an audio file into spectrogram tiles of `config.tile_width` frames, returning the
+/// manifest listing them
+/// Files that fit in one tile are saved as a single image without the `_part` suffix (and
+/// without manifest), whose path is returned
+pub(crate) fn audio_to_tiles(
+    audio_path: &Path,
+    output_path: &Path,
+    config: &SpectrogramConfig,
+    progress: &Progress,
+) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let pool = thread_pool(config);
+    let estimated_tiles = probe_audio(audio_path).ok()
+        .and_then(|info| info.num_samples)
+        .map(|samples| config.num_frames(samples).div_ceil(config.tile_width));
+
+    // First pass: the peak level all tiles are rendered against
+    let mut reference_magnitude = 0.0f32;
+    let measured = for_each_tile(audio_path, config, pool.as_ref(), progress, |tile, spectrograms| {
+        match estimated_tiles {
+            Some(tiles) => progress.update("Measuring peak level", tile + 1, tiles),
+            None => progress.status("Measuring peak level..."),
+        }
+        for spectrogram in spectrograms {
+            reference_magnitude = reference_magnitude.max(spectrogram.max_magnitude());
+        }
+        Ok(())
+    })?;
+
+    println!("Audio duration: {:.2} seconds", measured.num_samples as f32 / measured.sample_rate as f32);
+    println!("Creating {} tile(s) of up to {} frames, {} spectrogram(s) each",
+             measured.num_tiles, config.tile_width, measured.num_signals);
+    println!("FFT_SIZE: {}, HOP_SIZE: {}, num_bins: {}", config.fft_size, config.hop_size, config.image_height());
+    println!("Max magnitude: {}", reference_magnitude);
+
+    // Second pass: render and save each tile
+    let output_with_sr = encoded_image_path(output_path, measured.sample_rate, config);
+    let mut entries = Vec::with_capacity(measured.num_tiles);
+    for_each_tile(audio_path, config, pool.as_ref(), progress, |tile, spectrograms| {
+        progress.update("Saving tiles", tile + 1, measured.num_tiles);
+        let mut metadata = SpectrogramMetadata::from_spectrogram(&spectrograms[0]);
+        metadata.peak_magnitude = Some(reference_magnitude);
+        metadata.channels = spectrograms.len();
+        metadata.num_samples = measured.num_samples;
+        metadata.tile = tile;
+        metadata.tiles = measured.num_tiles;
+
+        let path = if measured.num_tiles > 1 { tile_image_path(&output_with_sr, tile) } else { output_with_sr.clone() };
+        let frames = spectrograms[0].num_frames();
+        let images = save_spectrograms(&spectrograms, reference_magnitude, &mut metadata, &path)?;
+        entries.push((frames, images));
+        Ok(())
+    })?;
+
+    if measured.num_tiles == 1 {
+        return Ok(entries.remove(0).1.remove(0));
+    }
+
+    let manifest = TileManifest {
+        sample_rate: measured.sample_rate,
+        num_samples: measured.num_samples,
+        channels: measured.num_signals,
+        tile_width: config.tile_width,
+        tiles: entries.into_iter()
+            .map(|(frames, images)| TileEntry {
+                frames,
+                images: images.iter().map(|image| image.file_name().unwrap_or_default().to_string_lossy().into_owned()).collect(),
+            })
+            .collect(),
+    };
+    let manifest_path = manifest_path(&output_with_sr);
+    manifest.save(&manifest_path)?;
+    println!("Saved tile manifest to: {}", manifest_path.display());
+    Ok(manifest_path)
+}
+
+// What a pass over the audio found
+struct TiledAudio {
+    sample_rate: u32,
+    num_samples: usize,
+    num_tiles: usize,
+    num_signals: usize,
+}
+
+// Analyze the file one tile at a time, handing each tile's spectrograms (one per encoded signal)
+// to `on_tile` in order
+fn for_each_tile(
+    audio_path: &Path,
+    config: &SpectrogramConfig,
+    pool: Option<&rayon::ThreadPool>,
+    progress: &Progress,
+    mut on_tile: impl FnMut(usize, Vec<Spectrogram>) -> Result<(), Box<dyn std::error::Error>>,
+) -> Result<TiledAudio, Box<dyn std::error::Error>> {
+    let fft_size = config.fft_size;
+    let hop_size = config.hop_size;
+    let before = if config.padding == Padding::None { 0 } else { fft_size / 2 };
+    // Samples kept beyond a tile's range, enough for reflection padding at the end of the file
+    let margin = fft_size + hop_size;
+
+    let mut stream = SignalStream::open(audio_path, config.channel_mode)?;
+    let sample_rate = stream.reader.sample_rate();
+    let mut tile = 0;
+    loop {
+        progress.check()?;
+        let first_frame = tile * config.tile_width;
+        // Sample range of frames first_frame..end_frame in the unpadded signal
+        let range = |end_frame: usize| {
+            let start = (first_frame * hop_size) as isize - before as isize;
+            let end = ((end_frame - 1) * hop_size + fft_size) as isize - before as isize;
+            (start, end)
+        };
+
+        let full_end = first_frame + config.tile_width;
+        stream.fill_to(range(full_end).1.max(0) as usize + margin)?;
+        // The last tile is known once the end of the file is
+        let end_frame = if stream.finished { full_end.min(config.num_frames(stream.end())) } else { full_end };
+        if end_frame <= first_frame {
+            break;
+        }
+
+        let (start, end) = range(end_frame);
+        let quiet = progress.quiet();
+        let spectrograms = (0..stream.signals.len())
+            .map(|signal| {
+                let segment = stream.segment(signal, start, end, config.padding);
+                in_pool(pool, || analyze_segment(&segment, sample_rate, 0, config, &quiet))
+            })
+            .collect::<Result<Vec<_>, Cancelled>>()?;
+        on_tile(tile, spectrograms)?;
+
+        // Keep what the next tile's frames (and their padding) still need
+        tile += 1;
+        stream.discard_before((full_end * hop_size).saturating_sub(before + margin));
+    }
+
+    Ok(TiledAudio {
+        sample_rate,
+        num_samples: stream.end(),
+        num_tiles: tile,
+        num_signals: stream.signals.len(),
+    })
+}
+
+// Encoded signals (after the channel mode's split) of a file being read a chunk at a time
+struct SignalStream {
+    reader: AudioReader,
+    channel_mode: ChannelMode,
+    // Samples from absolute position `start` on
+    signals: Vec<Vec<f32>>,
+    start: usize,
+    finished: bool,
+}
+
+impl SignalStream {
+    fn open(audio_path: &Path, channel_mode: ChannelMode) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut stream = Self { reader: AudioReader::open(audio_path)?, channel_mode, signals: Vec::new(), start: 0, finished: false };
+        stream.fill_to(1)?;
+        if stream.end() == 0 {
+            return Err(format!("No audio decoded from {}", audio_path.display()).into());
+        }
+        Ok(stream)
+    }
+
+    // Position after the last sample read
+    fn end(&self) -> usize {
+        self.start + self.signals.first().map_or(0, Vec::len)
+    }
+
+    // Read until `end` samples are available or the file ends
+    fn fill_to(&mut self, end: usize) -> Result<(), Box<dyn std::error::Error>> {
+        while !self.finished && self.end() < end {
+            let Some(chunk) = self.reader.next_chunk()? else {
+                self.finished = true;
+                break;
+            };
+            let split = self.channel_mode.split(chunk)?;
+            if self.signals.is_empty() {
+                self.signals = split;
+            } else {
+                for (signal, samples) in self.signals.iter_mut().zip(split) {
+                    signal.extend(samples);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Drop the samples before absolute position `position`
+    fn discard_before(&mut self, position: usize) {
+        let count = position.saturating_sub(self.start).min(self.end() - self.start);
+        for signal in &mut self.signals {
+            signal.drain(..count);
+        }
+        self.start += count;
+    }
+
+    // Samples start..end of one signal, padded like the whole signal would be
+    // Positions before 0 and after the end of the file are padding; everything else must be read
+    fn segment(&self, signal: usize, start: isize, end: isize, padding: Padding) -> Vec<f32> {
+        let buffered_start = self.start as isize;
+        let buffered_end = self.end() as isize;
+        let before = (buffered_start - start).max(0) as usize;
+        let after = (end - buffered_end).max(0) as usize;
+        let padded = pad_signal(&self.signals[signal], padding, before, after);
+        let offset = (start - (buffered_start - before as isize)) as usize;
+        padded[offset..offset + (end - start) as usize].to_vec()
+    }
+}
+
+/// Every tile of the sequence `image_path` belongs to, found by their `_part` names
+/// Each tile lists one image; channel images are found by `read_tile`
+pub(crate) fn sibling_tiles(image_path: &Path, metadata: &SpectrogramMetadata) -> Vec<Tile> {
+    (0..metadata.tiles)
+        .map(|tile| Tile { images: vec![sibling_tile_path(image_path, metadata, tile)], frames: None })
+        .collect()
+}
+
+/// Images of every tile listed in a manifest (or the only manifest in a folder), one list per tile
+pub fn manifest_tiles(path: &Path) -> Result<Vec<Vec<PathBuf>>, Box<dyn std::error::Error>> {
+    Ok(TileManifest::load(path)?.tiles(path).into_iter().map(|tile| tile.images).collect())
+}
+
+/// Load the manifest at `path` (or the only one in a folder) for decoding: its tiles, and
+/// `load_metadata` (given the first tile's first image) with the manifest's audio layout applied
+pub(crate) fn open_manifest(
+    path: &Path,
+    load_metadata: impl FnOnce(&Path) -> Result<SpectrogramMetadata, Box<dyn std::error::Error>>,
+) -> Result<(Vec<Tile>, SpectrogramMetadata), Box<dyn std::error::Error>> {
+    let manifest = TileManifest::load(path)?;
+    let tiles = manifest.tiles(path);
+    let mut metadata = load_metadata(&tiles[0].images[0])?;
+    manifest.apply_to(&mut metadata)?;
+    Ok((tiles, metadata))
+}
+
+/// Decode tiles in order into one signal per encoded channel
+pub(crate) fn tiles_to_signals(
+    tiles: &[Tile],
+    metadata: &SpectrogramMetadata,
+    progress: &Progress,
+) -> Result<Vec<Vec<f32>>, Box<dyn std::error::Error>> {
+    let mut signals: Vec<Vec<f32>> = Vec::new();
+    decode_tiles(tiles, metadata, progress, |block| {
+        if signals.is_empty() {
+            signals = vec![Vec::with_capacity(metadata.num_samples); block.len()];
+        }
+        for (signal, samples) in signals.iter_mut().zip(block) {
+            signal.extend(samples);
+        }
+        Ok(())
+    })?;
+    Ok(signals)
+}
+
+/// Decode tiles in order into a WAV or FLAC file, writing each block of samples as soon as it
+/// has been overlap-added, at the output level of `metadata`
+/// Levels that depend on the peaks of the whole signal take a second pass over the decoded
+/// samples, kept in a temporary file next to the output meanwhile. Nothing is left behind on failure.
+pub(crate) fn tiles_to_file(
+    tiles: &[Tile],
+    metadata: &SpectrogramMetadata,
+    output_path: &Path,
+    config: &SpectrogramConfig,
+    progress: &Progress,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let result = write_tiles(tiles, metadata, output_path, config, progress);
+    if result.is_err() {
+        let _ = fs::remove_file(output_path);
+    }
+    result
+}
+
+fn write_tiles(
+    tiles: &[Tile],
+    metadata: &SpectrogramMetadata,
+    output_path: &Path,
+    config: &SpectrogramConfig,
+    progress: &Progress,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let level_config = &metadata.config;
+    let has_reference = metadata.peak_magnitude.is_some();
+    let num_channels = metadata.channels;
+    let mut writer = AudioWriter::create(output_path, num_channels, metadata.sample_rate, config)?;
+
+    let mut level = if OutputLevel::needs_peaks(level_config, has_reference) {
+        // First pass: decode to the temporary file, measuring the peaks
+        let mut spool = Spool::create(output_path, num_channels)?;
+        let mut meter = PeakMeter::new(num_channels, level_config);
+        decode_tiles(tiles, metadata, progress, |block| {
+            let block = level_config.channel_mode.join(block);
+            meter.push(&block);
+            spool.write(&block)
+        })?;
+        let mut level = OutputLevel::new(num_channels, metadata.sample_rate, level_config, has_reference, Some(meter.finish()));
+
+        // Second pass: write it at the output level
+        progress.status("Writing audio file...");
+        spool.read_back(|block| {
+            progress.check()?;
+            writer.write(&level.process(block))
+        })?;
+        level
+    } else {
+        let mut level = OutputLevel::new(num_channels, metadata.sample_rate, level_config, has_reference, None);
+        decode_tiles(tiles, metadata, progress, |block| {
+            writer.write(&level.process(level_config.channel_mode.join(block)))
+        })?;
+        level
+    };
+
+    writer.write(&level.finish())?;
+    writer.finish()
+}
+
+// Decode tiles in order, handing each block of finished samples (one Vec per encoded channel)
+// to `on_block`
+// Only the current tile's spectrograms and the overlapping end of the previous one are kept
+fn decode_tiles(
+    tiles: &[Tile],
+    metadata: &SpectrogramMetadata,
+    progress: &Progress,
+    mut on_block: impl FnMut(Vec<Vec<f32>>) -> Result<(), Box<dyn std::error::Error>>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if metadata.num_samples == 0 {
+        return Err("The length of the tiled audio is unknown (the images carry no metadata); decode the sequence through its tile manifest".into());
+    }
+    let pool = thread_pool(&metadata.config);
+    let mut decoders: Vec<TileDecoder> = Vec::new();
+    let mut first_frame = 0;
+
+    for (index, tile) in tiles.iter().enumerate() {
+        progress.check()?;
+        progress.update("Reconstructing audio", index, tiles.len());
+
+        let bands = read_tile(&tile.images, metadata)
+            .map_err(|e| format!("Could not read tile {}: {}", index + 1, e))?;
+        let spectrograms: Vec<Spectrogram> = bands
+            .iter()
+            .map(|band| image_to_spectrogram(band, metadata))
+            .collect::<Result<_, _>>()
+            .map_err(|e| format!("Could not decode tile {}: {}", index + 1, e))?;
+        if let Some(frames) = tile.frames.filter(|&frames| spectrograms.iter().any(|s| s.num_frames() != frames)) {
+            return Err(format!("Tile {} is {} frames wide, but its manifest lists {}", index + 1, spectrograms[0].num_frames(), frames).into());
+        }
+        if decoders.is_empty() {
+            println!("FFT size: {}, HOP_SIZE: {}", spectrograms[0].config.fft_size, spectrograms[0].config.hop_size);
+            decoders = spectrograms.iter().map(|s| TileDecoder::new(&s.config, metadata.num_samples)).collect();
+        }
+
+        let quiet = progress.quiet();
+        for (decoder, spectrogram) in decoders.iter_mut().zip(&spectrograms) {
+            in_pool(pool.as_ref(), || decoder.push(spectrogram, first_frame, &quiet))?;
+        }
+        first_frame += spectrograms[0].num_frames();
+        on_block(decoders.iter_mut().map(TileDecoder::take_output).collect())?;
+    }
+
+    on_block(decoders.into_iter().map(TileDecoder::finish).collect())
+}
+
+// Decoded samples kept in a temporary file next to the output (interleaved little-endian
+// floats) until the level of the whole signal is known; removed when dropped
+struct Spool {
+    path: PathBuf,
+    file: BufWriter<File>,
+    num_channels: usize,
+}
+
+impl Spool {
+    fn create(output_path: &Path, num_channels: usize) -> Result<Self, Box<dyn std::error::Error>> {
+        let name = output_path.file_name().unwrap_or_default().to_string_lossy();
+        let path = output_path.with_file_name(format!(".{}.decoding", name));
+        let file = BufWriter::new(File::create(&path)?);
+        Ok(Self { path, file, num_channels })
+    }
+
+    fn write(&mut self, channels: &[Vec<f32>]) -> Result<(), Box<dyn std::error::Error>> {
+        let num_frames = channels.iter().map(Vec::len).min().unwrap_or(0);
+        let mut bytes = Vec::with_capacity(num_frames * self.num_channels * 4);
+        for i in 0..num_frames {
+            for channel in channels {
+                bytes.extend_from_slice(&channel[i].to_le_bytes());
+            }
+        }
+        self.file.write_all(&bytes)?;
+        Ok(())
+    }
+
+    // Hand the samples back in order, a block at a time
+    fn read_back(
+        mut self,
+        mut on_block: impl FnMut(Vec<Vec<f32>>) -> Result<(), Box<dyn std::error::Error>>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.file.flush()?;
+        let mut reader = BufReader::new(File::open(&self.path)?);
+        let frame_bytes = self.num_channels * 4;
+        let mut bytes = vec![0u8; SPOOL_BLOCK_FRAMES * frame_bytes];
+        loop {
+            let read = read_up_to(&mut reader, &mut bytes)?;
+            let num_frames = read / frame_bytes;
+            if num_frames == 0 {
+                return Ok(());
+            }
+            let mut channels = vec![Vec::with_capacity(num_frames); self.num_channels];
+            for frame in bytes[..num_frames * frame_bytes].chunks_exact(frame_bytes) {
+                for (channel, sample) in channels.iter_mut().zip(frame.chunks_exact(4)) {
+                    channel.push(f32::from_le_bytes([sample[0], sample[1], sample[2], sample[3]]));
+                }
+            }
+            on_block(channels)?;
+        }
+    }
+}
+
+impl Drop for Spool {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+// Fill `buffer` as far as the reader goes, returning the bytes read
+fn read_up_to(reader: &mut impl Read, buffer: &mut [u8]) -> std::io::Result<usize> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match reader.read(&mut buffer[filled..])? {
+            0 => break,
+            read => filled += read,
+        }
+    }
+    Ok(filled)
+}
+
+// One image per encoded channel: the listed channel images, or the bands of one image
+fn read_tile(images: &[PathBuf], metadata: &SpectrogramMetadata) -> Result<Vec<Rgb32FImage>, Box<dyn std::error::Error>> {
+    match images {
+        [image] => read_bands(image, metadata),
+        _ => images.iter()
+            .map(|image| open_image(image).map_err(|e| format!("{}: {}", image.display(), e).into()))
+            .collect(),
+    }
+}
+
+// Path of tile `tile` of the sequence, given the path of the image holding `metadata.tile`
+fn sibling_tile_path(image_path: &Path, metadata: &SpectrogramMetadata, tile: usize) -> PathBuf {
+    let stem = image_path.file_stem().unwrap_or_default().to_string_lossy();
+    let separate_channels = metadata.channels > 1 && !metadata.config.stack_channels;
+    let channel_suffix = format!("_CH{}", metadata.channel + 1);
+    let stem = if separate_channels { stem.strip_suffix(channel_suffix.as_str()).unwrap_or(&stem) } else { &stem };
+    let tile_suffix = format!("_part{:03}", metadata.tile + 1);
+    let base = stem.strip_suffix(tile_suffix.as_str()).unwrap_or(stem);
+
+    let extension = image_path.extension().unwrap_or_default().to_string_lossy();
+    let path = tile_image_path(&image_path.with_file_name(format!("{}.{}", base, extension)), tile);
+    if separate_channels { channel_image_path(&path, metadata.channel) } else { path }
+}
+
+// Resynthesis of one encoded channel, tile by tile
+struct TileDecoder {
+    config: SpectrogramConfig,
+    window: Window,
+    overlap_add: OverlapAdd,
+    // Last frames of the previous tile (linear magnitude and phase), which phase retrieval continues from
+    context_magnitude: Vec<Vec<f32>>,
+    context_phase: Vec<Vec<f32>>,
+    // Padding samples still to drop from the start
+    skip: usize,
+    num_samples: usize,
+    // Samples finished since the last `take_output`, and how many were finished in all
+    output: Vec<f32>,
+    emitted: usize,
+}
+
+impl TileDecoder {
+    fn new(config: &SpectrogramConfig, num_samples: usize) -> Self {
+        let window = Window::new(config);
+        Self {
+            overlap_add: OverlapAdd::new(window.clone(), config.num_frames(num_samples)),
+            window,
+            context_magnitude: Vec::new(),
+            context_phase: Vec::new(),
+            skip: if config.padding == Padding::None { 0 } else { config.fft_size / 2 },
+            num_samples,
+            output: Vec::new(),
+            emitted: 0,
+            config: config.clone(),
+        }
+    }
+
+    // Add the frames of the tile starting at frame `first_frame`
+    fn push(&mut self, spectrogram: &Spectrogram, first_frame: usize, progress: &Progress) -> Result<(), Cancelled> {
+        let (magnitude, phase) = linear_spectrum(spectrogram);
+        let num_frames = spectrogram.num_frames();
+
+        let phase = match phase {
+            Some(phase) => phase,
+            None => {
+                // Retrieve phase for the context and the tile together, keeping the context's phase
+                let num_known = self.context_phase.first().map_or(0, Vec::len);
+                let joined: Vec<Vec<f32>> = self.context_magnitude.iter().zip(&magnitude)
+                    .map(|(context, row)| context.iter().chain(row).copied().collect())
+                    .collect();
+                let joined = if num_known > 0 { &joined } else { &magnitude };
+                reconstruct_phase_continuing(joined, &self.window, &self.config, &self.context_phase, progress)?
+                    .into_iter()
+                    .map(|row| row[num_known..].to_vec())
+                    .collect()
+            }
+        };
+
+        self.overlap_add.add(first_frame, &magnitude, &phase);
+        let ready = self.overlap_add.take_ready(first_frame + num_frames);
+        self.emit(ready);
+
+        let keep = self.config.frames_per_window().min(num_frames);
+        self.context_magnitude = magnitude.into_iter().map(|row| row[num_frames - keep..].to_vec()).collect();
+        self.context_phase = phase.into_iter().map(|row| row[num_frames - keep..].to_vec()).collect();
+        Ok(())
+    }
+
+    // Append samples past the padding, up to the original length
+    fn emit(&mut self, samples: Vec<f32>) {
+        let skipped = self.skip.min(samples.len());
+        self.skip -= skipped;
+        let room = self.num_samples - self.emitted;
+        let before = self.output.len();
+        self.output.extend(samples.into_iter().skip(skipped).take(room));
+        self.emitted += self.output.len() - before;
+    }
+
+    // Samples finished since the last call
+    fn take_output(&mut self) -> Vec<f32> {
+        std::mem::take(&mut self.output)
+    }
+
+    // The last samples, with silence up to the original length
+    fn finish(mut self) -> Vec<f32> {
+        let remaining = self.overlap_add.finish();
+        self.emit(remaining);
+        let missing = self.num_samples - self.emitted;
+        self.output.resize(self.output.len() + missing, 0.0);
+        self.output
+    }
+}
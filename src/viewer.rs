@@ -0,0 +1,510 @@
+use eframe::egui;
+use egui::{Align2, Color32, FontId, Key, Modifiers, Pos2, Rect, Sense, Stroke, Vec2};
+use image::Rgb32FImage;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use spectrogram_converter::audio_to_image::spectrogram_to_image_with_reference;
+use spectrogram_converter::streaming::{is_tile_manifest, manifest_tiles};
+use spectrogram_converter::edit::changed_frames;
+use spectrogram_converter::project::{is_project, project_dir, ExportSettings};
+use spectrogram_converter::{
+    image_to_spectrogram, Change, Edit, FrequencyScale, Layers, Project, Spectrogram, SpectrogramConfig, SpectrogramMetadata,
+};
+
+use crate::editor::{layer_panel, LayerAction, SpectralEditor};
+
+// Room for the frequency labels left of the plot and the time labels below it
+const AXIS_WIDTH: f32 = 64.0;
+const AXIS_HEIGHT: f32 = 20.0;
+
+// Closest zoom in: screen points per image pixel
+const MAX_ZOOM: f32 = 32.0;
+
+const NOTE_NAMES: [&str; 12] = ["C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B"];
+
+/// Spectrogram image shown with zoom, pan, time and frequency axes and a cursor readout
+/// Axes and readout use the mapping stored in the image's metadata, like the decoder
+/// In edit mode the spectrogram is painted on directly and the textures follow the edits
+/// Projects open with their layers, and editable images can be saved as projects
+pub struct SpectrogramViewer {
+    title: String,
+    // Image shown, or the project folder
+    image_path: PathBuf,
+    metadata: SpectrogramMetadata,
+    // The image in pieces no larger than the GPU allows, with their rects in image pixels
+    textures: Vec<(Rect, egui::TextureHandle)>,
+    image_size: Vec2,
+    // One spectrogram per channel band of the image, top band first, as decoded and with the
+    // visible edit layers applied
+    base: Vec<Spectrogram>,
+    bands: Vec<Spectrogram>,
+    peak_magnitude: f32,
+    // Screen points per image pixel, and the image position shown at the plot's top-left corner
+    zoom: Vec2,
+    offset: Vec2,
+    fitted: bool,
+    show_notes: bool,
+    // Images holding every channel in one piece can be edited
+    editable: bool,
+    editing: bool,
+    editor: SpectralEditor,
+    layers: Layers,
+    // Layer new edits go to
+    active_layer: usize,
+    // Project opened or last saved to, and the rest of what saving it again keeps
+    project_path: Option<PathBuf>,
+    source: Option<PathBuf>,
+    export: ExportSettings,
+}
+
+impl SpectrogramViewer {
+    /// Load a spectrogram image (or the first tile of a manifest or folder of tiles), or a project
+    pub fn load(ctx: &egui::Context, path: &Path, config: &SpectrogramConfig) -> Result<Self, Box<dyn std::error::Error>> {
+        if is_project(path) {
+            return Ok(Self::from_project(ctx, Project::load(path)?, &project_dir(path)));
+        }
+        let image_path = if path.is_dir() || is_tile_manifest(path) {
+            manifest_tiles(path)?.remove(0).remove(0)
+        } else {
+            path.to_path_buf()
+        };
+        let metadata = SpectrogramMetadata::load(&image_path, config)?;
+        let img = image::ImageReader::open(&image_path)?.with_guessed_format()?.decode()?.to_rgb32f();
+        let (width, height) = img.dimensions();
+
+        let num_bands = if metadata.config.stack_channels { metadata.channels.max(1) as u32 } else { 1 };
+        let editable = metadata.tiles <= 1 && num_bands as usize == metadata.channels.max(1);
+        let band_height = height / num_bands;
+        let bands: Vec<Spectrogram> = (0..num_bands)
+            .map(|band| {
+                let band = image::imageops::crop_imm(&img, 0, band * band_height, width, band_height).to_image();
+                image_to_spectrogram(&band, &metadata)
+            })
+            .collect::<Result<_, _>>()?;
+
+        let mut viewer = Self::new(ctx, image_path.clone(), metadata, &img, bands, editable);
+        viewer.source = Some(image_path);
+        Ok(viewer)
+    }
+
+    /// Show a project saved in `dir`, rendering its layers over the analysis
+    pub fn from_project(ctx: &egui::Context, project: Project, dir: &Path) -> Self {
+        let peak_magnitude = project.reference_magnitude();
+        let bands = project.edited_spectrograms();
+        let band_height = bands[0].num_bins() as u32;
+        let mut img = Rgb32FImage::new(bands[0].num_frames() as u32, band_height * bands.len() as u32);
+        for (band, spectrogram) in bands.iter().enumerate() {
+            let rendered = spectrogram_to_image_with_reference(spectrogram, peak_magnitude);
+            image::imageops::replace(&mut img, &rendered, 0, (band as u32 * band_height) as i64);
+        }
+
+        let mut viewer = Self::new(ctx, dir.to_path_buf(), project.metadata, &img, project.spectrograms, true);
+        viewer.bands = bands;
+        viewer.layers = Layers::from_layers(project.layers);
+        viewer.project_path = Some(dir.to_path_buf());
+        viewer.source = project.source;
+        viewer.export = project.export;
+        viewer
+    }
+
+    fn new(ctx: &egui::Context, image_path: PathBuf, metadata: SpectrogramMetadata, img: &Rgb32FImage, base: Vec<Spectrogram>, editable: bool) -> Self {
+        let (width, height) = img.dimensions();
+        let max_side = ctx.input(|i| i.max_texture_side).max(1) as u32;
+        let mut textures = Vec::new();
+        for y in (0..height).step_by(max_side as usize) {
+            for x in (0..width).step_by(max_side as usize) {
+                let piece = image::imageops::crop_imm(img, x, y, max_side.min(width - x), max_side.min(height - y)).to_image();
+                let texture = ctx.load_texture(format!("spectrogram_{}_{}", x, y), color_image(&piece), egui::TextureOptions::NEAREST);
+                let rect = Rect::from_min_size(Pos2::new(x as f32, y as f32), Vec2::new(piece.width() as f32, piece.height() as f32));
+                textures.push((rect, texture));
+            }
+        }
+
+        Self {
+            title: image_path.file_name().unwrap_or_default().to_string_lossy().into_owned(),
+            image_path,
+            textures,
+            image_size: Vec2::new(width as f32, height as f32),
+            bands: base.clone(),
+            base,
+            peak_magnitude: metadata.peak_magnitude.unwrap_or(1.0),
+            metadata,
+            zoom: Vec2::splat(1.0),
+            offset: Vec2::ZERO,
+            fitted: false,
+            show_notes: false,
+            editable,
+            editing: false,
+            editor: SpectralEditor::new(),
+            layers: Layers::new(),
+            active_layer: 0,
+            project_path: None,
+            source: None,
+            export: ExportSettings::default(),
+        }
+    }
+
+    /// Whether any layer holds edits
+    pub fn is_edited(&self) -> bool {
+        self.layers.has_edits()
+    }
+
+    pub fn image_path(&self) -> &Path {
+        &self.image_path
+    }
+
+    pub fn metadata(&self) -> &SpectrogramMetadata {
+        &self.metadata
+    }
+
+    /// Whether what is shown can be edited and saved as a project
+    pub fn is_editable(&self) -> bool {
+        self.editable
+    }
+
+    /// Project folder this was opened from or last saved to
+    pub fn project_path(&self) -> Option<&Path> {
+        self.project_path.as_deref()
+    }
+
+    /// Remember where the project was saved, so saving again goes there
+    pub fn set_project_path(&mut self, dir: PathBuf) {
+        self.title = dir.file_name().unwrap_or_default().to_string_lossy().into_owned();
+        self.project_path = Some(dir);
+    }
+
+    /// The decoded spectrograms, layers and export settings as a project
+    pub fn to_project(&self) -> Project {
+        // The analysis as decoded: legacy and resized images get their FFT size or height from the image
+        let mut metadata = self.metadata.clone();
+        metadata.config = self.base[0].config.clone();
+        metadata.num_samples = self.base[0].num_samples;
+        metadata.channel = 0;
+        Project {
+            source: self.source.clone(),
+            metadata,
+            spectrograms: self.base.clone(),
+            layers: self.layers.layers().to_vec(),
+            export: self.export.clone(),
+        }
+    }
+
+    /// The channel spectrograms with the visible layers applied
+    pub fn spectrograms(&self) -> &[Spectrogram] {
+        &self.bands
+    }
+
+    // Add an edit to the active layer
+    fn add_edit(&mut self, edit: Edit) {
+        let layer = self.active_layer;
+        let layers = self.layers.layers();
+        // Nothing visible is applied after the active layer, so the edit can go straight onto the result
+        let on_top = layers[layer].visible && layers[layer + 1..].iter().all(|above| !above.visible || above.edits.is_empty());
+        self.layers.change(Change::AddEdit { layer, edit: edit.clone() }).expect("the active layer exists");
+        if on_top {
+            let frames = edit.apply(&mut self.bands, self.peak_magnitude);
+            self.redraw(frames);
+        } else {
+            self.rebuild(&[edit]);
+        }
+    }
+
+    fn layer_action(&mut self, action: LayerAction) {
+        let affected = match action {
+            LayerAction::Change(change) => self.layers.change(change).ok(),
+            LayerAction::Undo => self.layers.undo(),
+            LayerAction::Redo => self.layers.redo(),
+        };
+        self.active_layer = self.active_layer.min(self.layers.layers().len() - 1);
+        if let Some(edits) = affected {
+            self.rebuild(&edits);
+        }
+    }
+
+    // Apply the layers to the decoded spectrograms again and redraw where `edits` reach
+    fn rebuild(&mut self, edits: &[Edit]) {
+        self.bands = self.layers.render(&self.base, self.peak_magnitude);
+        self.redraw(changed_frames(edits, self.bands[0].num_frames()));
+    }
+
+    // Render frames of the edited spectrograms into the textures showing them
+    fn redraw(&mut self, frames: Range<usize>) {
+        if frames.is_empty() {
+            return;
+        }
+        let band_height = self.bands[0].num_bins() as u32;
+        let mut img = Rgb32FImage::new(frames.len() as u32, band_height * self.bands.len() as u32);
+        for (band, spectrogram) in self.bands.iter().enumerate() {
+            let part = Spectrogram {
+                magnitude: spectrogram.magnitude.iter().map(|row| row[frames.clone()].to_vec()).collect(),
+                phase: spectrogram.phase.as_ref().map(|phases| phases.iter().map(|row| row[frames.clone()].to_vec()).collect()),
+                sample_rate: spectrogram.sample_rate,
+                num_samples: spectrogram.num_samples,
+                config: spectrogram.config.clone(),
+            };
+            let rendered = spectrogram_to_image_with_reference(&part, self.peak_magnitude);
+            image::imageops::replace(&mut img, &rendered, 0, (band as u32 * band_height) as i64);
+        }
+
+        for (rect, texture) in &mut self.textures {
+            let start = (rect.min.x as usize).max(frames.start);
+            let end = (rect.max.x as usize).min(frames.end);
+            if start >= end {
+                continue;
+            }
+            let piece = image::imageops::crop_imm(&img, (start - frames.start) as u32, rect.min.y as u32, (end - start) as u32, rect.height() as u32).to_image();
+            texture.set_partial([start - rect.min.x as usize, 0], color_image(&piece), egui::TextureOptions::NEAREST);
+        }
+    }
+
+    /// Show the viewer with a playhead at `playhead` seconds
+    /// Returns the time clicked, to play from there
+    pub fn show(&mut self, ui: &mut egui::Ui, playhead: Option<f32>) -> Option<f32> {
+        ui.horizontal(|ui| {
+            ui.label(&self.title);
+            if ui.button("Fit").clicked() {
+                self.fitted = false;
+            }
+            ui.checkbox(&mut self.show_notes, "Notes");
+            ui.add_enabled(self.editable, egui::Checkbox::new(&mut self.editing, "Edit"))
+                .on_disabled_hover_text("Only single images holding every channel can be edited");
+            if !self.editing {
+                ui.label("Drag to pan, scroll to zoom time, Shift+scroll to zoom frequency, click to play from there");
+            }
+        });
+        if self.editing {
+            let shortcut = ui.input_mut(|i| {
+                if i.consume_key(Modifiers::COMMAND | Modifiers::SHIFT, Key::Z) || i.consume_key(Modifiers::COMMAND, Key::Y) {
+                    Some(LayerAction::Redo)
+                } else if i.consume_key(Modifiers::COMMAND, Key::Z) {
+                    Some(LayerAction::Undo)
+                } else {
+                    None
+                }
+            });
+            if let Some(action) = layer_panel(ui, &self.layers, &mut self.active_layer).or(shortcut) {
+                self.layer_action(action);
+            }
+            let has_phase = self.bands[0].phase.is_some();
+            if let Some(edit) = self.editor.toolbar(ui, self.bands.len(), has_phase) {
+                self.add_edit(edit);
+            }
+        }
+
+        let size = Vec2::new(ui.available_width(), ui.available_height().max(200.0));
+        let (response, painter) = ui.allocate_painter(size, Sense::click_and_drag());
+        let plot = Rect::from_min_max(response.rect.min + Vec2::new(AXIS_WIDTH, 0.0), response.rect.max - Vec2::new(0.0, AXIS_HEIGHT));
+        if plot.width() <= 0.0 || plot.height() <= 0.0 {
+            return None;
+        }
+
+        let fit_zoom = plot.size() / self.image_size;
+        if !self.fitted {
+            self.zoom = fit_zoom;
+            self.offset = Vec2::ZERO;
+            self.fitted = true;
+        }
+
+        // Pan by dragging (right-dragging while editing), zoom around the pointer
+        let panning = if self.editing { SpectralEditor::pans(&response) } else { response.dragged() };
+        if panning {
+            self.offset -= response.drag_delta() / self.zoom;
+        }
+        if let Some(pointer) = response.hover_pos().filter(|pos| plot.contains(*pos)) {
+            let (scroll, pinch, shift) = ui.input(|i| (i.smooth_scroll_delta, i.zoom_delta(), i.modifiers.shift));
+            let factor = pinch * ((scroll.x + scroll.y) * 0.003).exp();
+            if factor != 1.0 {
+                let anchor = self.offset + (pointer - plot.min) / self.zoom;
+                let scale = if shift { Vec2::new(1.0, factor) } else if pinch != 1.0 { Vec2::splat(factor) } else { Vec2::new(factor, 1.0) };
+                self.zoom = (self.zoom * scale).max(fit_zoom.min(Vec2::splat(1.0))).min(Vec2::splat(MAX_ZOOM));
+                self.offset = anchor - (pointer - plot.min) / self.zoom;
+            }
+        }
+        let visible = plot.size() / self.zoom;
+        self.offset = self.offset.min(self.image_size - visible).max(Vec2::ZERO);
+
+        // Image
+        let clipped = painter.with_clip_rect(plot);
+        clipped.rect_filled(plot, 0.0, Color32::BLACK);
+        for (rect, texture) in &self.textures {
+            let screen = Rect::from_min_max(self.to_screen(plot, rect.min), self.to_screen(plot, rect.max));
+            if screen.intersects(plot) {
+                let uv = Rect::from_min_max(Pos2::ZERO, Pos2::new(1.0, 1.0));
+                clipped.image(texture.id(), screen, uv, Color32::WHITE);
+            }
+        }
+
+        self.draw_time_axis(ui, &painter, plot);
+        self.draw_frequency_axis(ui, &painter, plot);
+
+        if let Some(seconds) = playhead {
+            let x = self.to_screen(plot, Pos2::new(self.time_x(seconds), 0.0)).x;
+            clipped.line_segment([Pos2::new(x, plot.top()), Pos2::new(x, plot.bottom())], Stroke::new(2.0, Color32::from_rgb(255, 220, 0)));
+        }
+
+        // Crosshair and readout at the pointer
+        if let Some(pointer) = response.hover_pos().filter(|pos| plot.contains(*pos)) {
+            let position = self.offset + (pointer - plot.min) / self.zoom;
+            if let Some(readout) = self.readout(position) {
+                let stroke = Stroke::new(1.0, Color32::from_white_alpha(96));
+                clipped.line_segment([Pos2::new(pointer.x, plot.top()), Pos2::new(pointer.x, plot.bottom())], stroke);
+                clipped.line_segment([Pos2::new(plot.left(), pointer.y), Pos2::new(plot.right(), pointer.y)], stroke);
+                let text_pos = plot.right_top() + Vec2::new(-6.0, 4.0);
+                let galley = painter.layout_no_wrap(readout, FontId::monospace(12.0), Color32::WHITE);
+                let background = Align2::RIGHT_TOP.anchor_size(text_pos, galley.size()).expand(3.0);
+                clipped.rect_filled(background, 2.0, Color32::from_black_alpha(160));
+                clipped.galley(background.min + Vec2::splat(3.0), galley, Color32::WHITE);
+            }
+        }
+
+        if self.editing {
+            let pointer = ui.input(|i| i.pointer.latest_pos()).map(|pos| (self.offset + (pos - plot.min) / self.zoom).to_pos2());
+            let band_height = self.bands[0].num_bins() as f32;
+            if let Some(edit) = self.editor.pointer(ui, &response, pointer, band_height, self.bands.len()) {
+                self.add_edit(edit);
+            }
+            let hover = response.hover_pos().filter(|pos| plot.contains(*pos));
+            self.editor.draw(&clipped, |position| self.to_screen(plot, position), self.zoom, band_height, hover);
+            return None;
+        }
+
+        let clicked = response.interact_pointer_pos().filter(|pos| response.clicked() && plot.contains(*pos))?;
+        let frame = self.offset.x + (clicked.x - plot.min.x) / self.zoom.x - 0.5;
+        Some(self.bands[0].frame_time(frame).max(0.0))
+    }
+
+    // Image x position of a time in seconds (pixel x holds frame x, centered at x + 0.5)
+    fn time_x(&self, seconds: f32) -> f32 {
+        let spectrogram = &self.bands[0];
+        let seconds_per_frame = spectrogram.config.hop_size as f32 / spectrogram.sample_rate as f32;
+        (seconds - spectrogram.frame_time(0.0)) / seconds_per_frame + 0.5
+    }
+
+    // Screen position of an image position (in pixels)
+    fn to_screen(&self, plot: Rect, position: Pos2) -> Pos2 {
+        plot.min + (position.to_vec2() - self.offset) * self.zoom
+    }
+
+    // Time, frequency and level of the pixel at an image position
+    fn readout(&self, position: Vec2) -> Option<String> {
+        let band_height = self.bands[0].num_bins();
+        let (frame, row) = (position.x.floor() as usize, position.y.floor() as usize);
+        let spectrogram = self.bands.get(row / band_height)?;
+        let bin = band_height - 1 - row % band_height;
+        let magnitude = *spectrogram.magnitude.get(bin)?.get(frame)?;
+
+        let freq = spectrogram.bin_frequency(bin);
+        let level = if magnitude > 0.0 && self.peak_magnitude > 0.0 {
+            format!("{:.1} dB", 20.0 * (magnitude / self.peak_magnitude).log10())
+        } else {
+            "-inf dB".to_string()
+        };
+        let channel = if self.bands.len() > 1 { format!("ch {}  ", row / band_height + 1) } else { String::new() };
+        Some(format!("{}{:.3} s  {:.1} Hz ({})  {}", channel, spectrogram.frame_time(frame as f32), freq, note_name(freq), level))
+    }
+
+    fn draw_time_axis(&self, ui: &egui::Ui, painter: &egui::Painter, plot: Rect) {
+        let spectrogram = &self.bands[0];
+        let seconds_per_frame = spectrogram.config.hop_size as f32 / spectrogram.sample_rate as f32;
+        let start = spectrogram.frame_time(0.0);
+
+        // Smallest 1-2-5 step at least 80 points apart
+        let step = nice_steps()
+            .find(|step| step / seconds_per_frame * self.zoom.x >= 80.0)
+            .unwrap_or(3600.0);
+        let decimals = (-step.log10().floor()).max(0.0) as usize;
+
+        let first_time = start + (self.offset.x - 0.5) * seconds_per_frame;
+        let last_time = first_time + plot.width() / self.zoom.x * seconds_per_frame;
+        let color = ui.visuals().text_color();
+        let mut time = (first_time / step).ceil() * step;
+        while time <= last_time {
+            let x = self.to_screen(plot, Pos2::new(self.time_x(time), 0.0)).x;
+            painter.line_segment([Pos2::new(x, plot.bottom()), Pos2::new(x, plot.bottom() + 4.0)], Stroke::new(1.0, color));
+            painter.text(Pos2::new(x, plot.bottom() + 4.0), Align2::CENTER_TOP, format!("{:.*} s", decimals, time), FontId::proportional(11.0), color);
+            time += step;
+        }
+    }
+
+    fn draw_frequency_axis(&self, ui: &egui::Ui, painter: &egui::Painter, plot: Rect) {
+        let color = ui.visuals().text_color();
+        let band_height = self.bands[0].num_bins() as f32;
+
+        for (band, spectrogram) in self.bands.iter().enumerate() {
+            let (min_freq, max_freq) = spectrogram.config.frequency_range(spectrogram.sample_rate);
+            let labels: Vec<(f32, String)> = if self.show_notes {
+                // Every C, with A4 for reference
+                (0..=10)
+                    .map(|octave| (midi_frequency(12 * (octave + 1)), format!("C{}", octave)))
+                    .chain(std::iter::once((440.0, "A4".to_string())))
+                    .collect()
+            } else {
+                let mut freqs: Vec<f32> = nice_steps().take_while(|&f| f <= max_freq).filter(|&f| f >= 10.0).collect();
+                if spectrogram.config.frequency_scale == FrequencyScale::Linear {
+                    // Evenly spaced labels at least 30 points apart
+                    let points_per_hz = band_height * self.zoom.y / (max_freq - min_freq).max(1.0);
+                    if let Some(step) = nice_steps().find(|step| step * points_per_hz >= 30.0) {
+                        freqs.extend((1..).map(|i| i as f32 * step).take_while(|&f| f <= max_freq));
+                    }
+                }
+                freqs.into_iter().map(|f| (f, format_frequency(f))).collect()
+            };
+
+            let mut positions: Vec<(f32, String)> = labels.into_iter()
+                .filter_map(|(freq, label)| {
+                    let bin = spectrogram.frequency_bin(freq)?;
+                    let row = band as f32 * band_height + band_height - 1.0 - bin + 0.5;
+                    Some((self.to_screen(plot, Pos2::new(0.0, row)).y, label))
+                })
+                .filter(|(y, _)| (plot.top()..=plot.bottom()).contains(y))
+                .collect();
+            positions.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+            // Skip labels closer than 14 points to the previous one
+            let mut last_y = f32::INFINITY;
+            for (y, label) in positions {
+                if last_y - y < 14.0 {
+                    continue;
+                }
+                last_y = y;
+                painter.line_segment([Pos2::new(plot.left() - 4.0, y), Pos2::new(plot.left(), y)], Stroke::new(1.0, color));
+                painter.text(Pos2::new(plot.left() - 6.0, y), Align2::RIGHT_CENTER, label, FontId::proportional(11.0), color);
+            }
+        }
+    }
+}
+
+// Texture pixels of an image piece
+fn color_image(piece: &Rgb32FImage) -> egui::ColorImage {
+    let pixels: Vec<u8> = piece.as_raw().iter().map(|&v| (v.clamp(0.0, 1.0) * 255.0).round() as u8).collect();
+    egui::ColorImage::from_rgb([piece.width() as usize, piece.height() as usize], &pixels)
+}
+
+// 1-2-5 series from 1 ms up
+fn nice_steps() -> impl Iterator<Item = f32> {
+    (-3..6).flat_map(|exponent| [1.0, 2.0, 5.0].map(|m| m * 10f32.powi(exponent)))
+}
+
+fn format_frequency(freq: f32) -> String {
+    if freq >= 1000.0 {
+        format!("{}k", freq / 1000.0)
+    } else {
+        format!("{}", freq)
+    }
+}
+
+fn midi_frequency(note: i32) -> f32 {
+    440.0 * 2f32.powf((note - 69) as f32 / 12.0)
+}
+
+// Nearest note with the deviation in cents, e.g. "A4 +3¢"
+fn note_name(freq: f32) -> String {
+    if freq <= 0.0 {
+        return "-".to_string();
+    }
+    let midi = 69.0 + 12.0 * (freq / 440.0).log2();
+    let note = midi.round() as i32;
+    let cents = ((midi - note as f32) * 100.0).round() as i32;
+    format!("{}{} {:+}¢", NOTE_NAMES[note.rem_euclid(12) as usize], note.div_euclid(12) - 1, cents)
+}
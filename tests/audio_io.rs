@@ -0,0 +1,182 @@
+use spectrogram_converter::convert::{process_file, InputKind};
+use spectrogram_converter::{read_audio, write_audio, BitDepth, OutputFormat, Progress, SpectrogramConfig};
+
+const SAMPLE_RATE: u32 = 48000;
+
+// Stereo test channels: a sweep to full scale, silence and deterministic noise, over several
+// FLAC blocks and ending in a partial one
+fn test_channels() -> Vec<Vec<f32>> {
+    let len = 10_007;
+    let mut state = 0x8765_4321u32;
+    let sweep = (0..len).map(|i| (i as f32 / len as f32 * 2.0 - 1.0) * (0.001 * i as f32).sin()).collect();
+    let noise = (0..len)
+        .map(|i| {
+            state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            if (4096..8192).contains(&i) { 0.0 } else { (state >> 8) as f32 / (1u32 << 23) as f32 - 1.0 }
+        })
+        .collect();
+    vec![sweep, noise]
+}
+
+fn write_and_read(path: &std::path::Path, channels: &[Vec<f32>], config: &SpectrogramConfig) -> Vec<Vec<f32>> {
+    write_audio(path, channels, SAMPLE_RATE, config).unwrap();
+    let audio = read_audio(path).unwrap();
+    assert_eq!(audio.sample_rate, SAMPLE_RATE);
+    audio.channels
+}
+
+#[test]
+fn flac_decodes_to_the_samples_written() {
+    let dir = std::env::temp_dir().join(format!("spectrogram_flac_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let channels = test_channels();
+
+    for bit_depth in [BitDepth::Int16, BitDepth::Int24] {
+        let config = |output_format| SpectrogramConfig { output_format, bit_depth, dither: false, ..SpectrogramConfig::default() };
+        let wav = write_and_read(&dir.join("output.wav"), &channels, &config(OutputFormat::Wav));
+        let flac_path = dir.join("output.flac");
+        let flac = write_and_read(&flac_path, &channels, &config(OutputFormat::Flac));
+        assert!(flac == wav, "{} FLAC decodes differently from WAV", bit_depth);
+
+        // STREAMINFO carries the MD5 of the interleaved little-endian samples
+        let bytes_per_sample = bit_depth.bits() as usize / 8;
+        let full_scale = (1i64 << (bit_depth.bits() - 1)) as f32;
+        let mut context = md5::Context::new();
+        for i in 0..wav[0].len() {
+            for channel in &wav {
+                context.consume(&((channel[i] * full_scale) as i32).to_le_bytes()[..bytes_per_sample]);
+            }
+        }
+        let file = std::fs::read(&flac_path).unwrap();
+        assert_eq!(file[26..42], context.compute().0, "{} FLAC MD5", bit_depth);
+    }
+
+    let float = SpectrogramConfig { output_format: OutputFormat::Flac, bit_depth: BitDepth::Float32, ..SpectrogramConfig::default() };
+    assert!(write_audio(&dir.join("float.flac"), &channels, SAMPLE_RATE, &float).is_err());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn mono_flac_with_short_blocks_decodes_exactly() {
+    let dir = std::env::temp_dir().join(format!("spectrogram_flac_mono_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    // One sample, less than one block, and one block plus a single sample; the noise spans
+    // full scale in both directions
+    for len in [1, 1000, 4097] {
+        let mut state = 0x1234_5678u32;
+        let mono: Vec<f32> = (0..len)
+            .map(|i| match i % 7 {
+                0 => -1.0,
+                1 => 1.0,
+                _ => {
+                    state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                    (state >> 8) as f32 / (1u32 << 23) as f32 - 1.0
+                }
+            })
+            .collect();
+        for bit_depth in [BitDepth::Int16, BitDepth::Int24] {
+            let config = |output_format| SpectrogramConfig { output_format, bit_depth, dither: false, ..SpectrogramConfig::default() };
+            let wav = write_and_read(&dir.join("mono.wav"), std::slice::from_ref(&mono), &config(OutputFormat::Wav));
+            let flac = write_and_read(&dir.join("mono.flac"), std::slice::from_ref(&mono), &config(OutputFormat::Flac));
+            assert_eq!(flac.len(), 1);
+            assert_eq!(flac[0].len(), len, "{} FLAC of {} samples", bit_depth, len);
+            assert!(flac == wav, "{} FLAC of {} samples decodes differently from WAV", bit_depth, len);
+        }
+    }
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn bit_depths_quantize_and_dither() {
+    let dir = std::env::temp_dir().join(format!("spectrogram_bit_depth_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("output.wav");
+    let channels = test_channels();
+
+    for bit_depth in BitDepth::ALL {
+        let config = SpectrogramConfig { bit_depth, dither: false, ..SpectrogramConfig::default() };
+        let output = write_and_read(&path, &channels, &config);
+
+        // Rounded to the nearest step, apart from the top step of full scale; floats are exact
+        let step = match bit_depth {
+            BitDepth::Float32 => 0.0,
+            _ => 1.0 / (1i64 << (bit_depth.bits() - 1)) as f32,
+        };
+        for (input, output) in channels.iter().zip(&output) {
+            assert_eq!(output.len(), input.len());
+            let error = input.iter().zip(output).map(|(a, b)| (a - b).abs()).fold(0.0f32, f32::max);
+            assert!(error <= step, "{}: error {} over a step of {}", bit_depth, error, step);
+        }
+    }
+
+    // Dither only applies to 16 bits, and stays within 1.5 LSB
+    let undithered = write_and_read(&path, &channels, &SpectrogramConfig { bit_depth: BitDepth::Int16, dither: false, ..SpectrogramConfig::default() });
+    let dithered = write_and_read(&path, &channels, &SpectrogramConfig { bit_depth: BitDepth::Int16, dither: true, ..SpectrogramConfig::default() });
+    assert!(dithered != undithered);
+    // Silence turns into noise
+    assert!(dithered[1][4096..8192].iter().any(|&s| s != 0.0));
+    let lsb = 1.0 / 32768.0;
+    for (input, output) in channels.iter().zip(&dithered) {
+        let error = input.iter().zip(output).map(|(a, b)| (a - b).abs()).fold(0.0f32, f32::max);
+        assert!(error <= 1.5 * lsb + 1e-7, "dither error {} LSB", error / lsb);
+    }
+    let config = |dither| SpectrogramConfig { bit_depth: BitDepth::Int24, dither, ..SpectrogramConfig::default() };
+    assert!(write_and_read(&path, &channels, &config(true)) == write_and_read(&path, &channels, &config(false)));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn compressed_inputs_encode_like_wav() {
+    let dir = std::env::temp_dir().join(format!("spectrogram_compressed_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let channels = test_channels();
+    let config = |output_format| SpectrogramConfig { output_format, bit_depth: BitDepth::Int16, dither: false, ..SpectrogramConfig::default() };
+    let wav = dir.join("input.wav");
+    write_audio(&wav, &channels, SAMPLE_RATE, &config(OutputFormat::Wav)).unwrap();
+    let flac = dir.join("input.flac");
+    write_audio(&flac, &channels, SAMPLE_RATE, &config(OutputFormat::Flac)).unwrap();
+
+    // 16-bit big-endian AIFF of the same samples
+    let samples = read_audio(&wav).unwrap().channels;
+    let num_frames = samples[0].len();
+    let mut comm = Vec::new();
+    comm.extend_from_slice(&2u16.to_be_bytes());
+    comm.extend_from_slice(&(num_frames as u32).to_be_bytes());
+    comm.extend_from_slice(&16u16.to_be_bytes());
+    comm.extend_from_slice(&[0x40, 0x0E, 0xBB, 0x80, 0, 0, 0, 0, 0, 0]); // 48000 as an 80-bit float
+    let mut ssnd = vec![0u8; 8];
+    for i in 0..num_frames {
+        for channel in &samples {
+            ssnd.extend_from_slice(&((channel[i] * 32768.0) as i16).to_be_bytes());
+        }
+    }
+    let mut form = b"AIFF".to_vec();
+    for (id, chunk) in [(b"COMM", &comm), (b"SSND", &ssnd)] {
+        form.extend_from_slice(id);
+        form.extend_from_slice(&(chunk.len() as u32).to_be_bytes());
+        form.extend_from_slice(chunk);
+    }
+    let aiff = dir.join("input.aiff");
+    std::fs::write(&aiff, [b"FORM".as_slice(), &(form.len() as u32).to_be_bytes(), &form].concat()).unwrap();
+
+    // Found by extension or by contents, decoded to the same samples, and encoded alike
+    let unnamed = dir.join("input");
+    std::fs::copy(&flac, &unnamed).unwrap();
+    let encode = |input: &std::path::Path| {
+        assert_eq!(InputKind::from_path(input).unwrap(), InputKind::Audio, "{}", input.display());
+        assert!(read_audio(input).unwrap().channels == samples, "{} decodes differently", input.display());
+        let name = format!("{}_encoded", input.file_name().unwrap().to_string_lossy().replace('.', "_"));
+        let image = process_file(input, Some(&dir.join(name)), &SpectrogramConfig::default(), &Progress::none()).unwrap();
+        image::open(image).unwrap().to_rgb8()
+    };
+    let from_wav = encode(&wav);
+    for input in [&flac, &aiff, &unnamed] {
+        assert!(encode(input) == from_wav, "{} encodes differently from WAV", input.display());
+    }
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,60 @@
+use std::path::PathBuf;
+use std::sync::Mutex;
+use spectrogram_converter::batch::{collect_inputs, run_batch, BatchSummary, JobStatus};
+use spectrogram_converter::{write_wav, BitDepth, CancelToken, ChannelMode, SpectrogramConfig};
+
+const SAMPLE_RATE: u32 = 22050;
+
+fn tone(len: usize, freq: f32) -> Vec<f32> {
+    (0..len).map(|i| 0.5 * (2.0 * std::f32::consts::PI * freq * i as f32 / SAMPLE_RATE as f32).sin()).collect()
+}
+
+#[test]
+fn batches_convert_folders_and_report_each_job() {
+    let dir = std::env::temp_dir().join(format!("spectrogram_batch_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    for name in ["a.wav", "b.wav"] {
+        write_wav(&dir.join(name), &[tone(11_025, 440.0), tone(11_025, 880.0)], SAMPLE_RATE, BitDepth::Int16, false).unwrap();
+    }
+    std::fs::write(dir.join("notes.txt"), "not audio").unwrap();
+
+    let config = SpectrogramConfig {
+        channel_mode: ChannelMode::Multichannel,
+        stack_channels: false,
+        tile_width: 32,
+        ..SpectrogramConfig::default()
+    };
+    let inputs = collect_inputs(std::slice::from_ref(&dir)).unwrap();
+    assert_eq!(inputs, vec![dir.join("a.wav"), dir.join("b.wav")]);
+
+    let missing = dir.join("missing.wav");
+    let jobs: Vec<PathBuf> = inputs.iter().cloned().chain([missing.clone()]).collect();
+    let reported = Mutex::new(Vec::new());
+    let statuses = run_batch(&jobs, &config, 2, &CancelToken::new(), &|index, status| {
+        if status.is_finished() {
+            reported.lock().unwrap().push(index);
+        }
+    });
+    let mut reported = reported.into_inner().unwrap();
+    reported.sort();
+    assert_eq!(reported, vec![0, 1, 2]);
+    assert!(matches!(&statuses[0], JobStatus::Done { output_path } if output_path.exists()));
+    assert!(matches!(statuses[2], JobStatus::Failed { .. }));
+    assert_eq!(BatchSummary::of(&statuses), BatchSummary { succeeded: 2, failed: 1, cancelled: 0 });
+
+    // The images decode through their manifests; tiles and channel siblings are left out
+    let images: Vec<PathBuf> = collect_inputs(std::slice::from_ref(&dir)).unwrap()
+        .into_iter()
+        .filter(|input| !inputs.contains(input))
+        .collect();
+    assert_eq!(images.len(), 2);
+    assert!(images.iter().all(|image| image.to_string_lossy().ends_with(".tiles.toml")));
+
+    // Cancelling skips what is queued
+    let cancel = CancelToken::new();
+    cancel.cancel();
+    let statuses = run_batch(&images, &config, 1, &cancel, &|_, _| {});
+    assert_eq!(statuses, vec![JobStatus::Cancelled, JobStatus::Cancelled]);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,178 @@
+use spectrogram_converter::{
+    analyze, audio_to_spectrogram, decode_spectrogram, image_to_spectrogram, spectrograms_to_audio, write_wav, BitDepth,
+    Change, ChannelMode, Edit, FrequencyScale, ImageFormat, Layer, Layers, LevelMode, Operation, Progress, Region,
+    Spectrogram, SpectrogramConfig, SpectrogramMetadata,
+};
+
+const SAMPLE_RATE: u32 = 44100;
+
+fn tone(len: usize, freq: f32) -> Vec<f32> {
+    (0..len).map(|i| 0.5 * (2.0 * std::f32::consts::PI * freq * i as f32 / SAMPLE_RATE as f32).sin()).collect()
+}
+
+// Spectrogram with every cell at magnitude 1 and a phase that tells the cells apart
+fn flat_spectrogram(num_bins: usize, num_frames: usize) -> Spectrogram {
+    let mut spectrogram = analyze(&tone(4096, 440.0), SAMPLE_RATE, &SpectrogramConfig::default());
+    spectrogram.magnitude = vec![vec![1.0; num_frames]; num_bins];
+    spectrogram.phase = Some((0..num_bins).map(|bin| (0..num_frames).map(|frame| (bin * 1000 + frame) as f32).collect()).collect());
+    spectrogram
+}
+
+#[test]
+fn regions_rasterize_to_cell_centers() {
+    let rectangle = Region::Rectangle { from: [5.2, 3.0], to: [2.0, 6.9] };
+    let mask = rectangle.mask(100, 100);
+    assert_eq!((mask.frames.clone(), mask.bins.clone()), (2..6, 3..7));
+    assert_eq!(mask.weight(3, 2), 1.0);
+    assert_eq!(mask.weight(7, 2), 0.0);
+
+    // Clipped to the spectrogram
+    let mask = Region::Rectangle { from: [-10.0, -10.0], to: [200.0, 5.0] }.mask(50, 100);
+    assert_eq!((mask.frames, mask.bins), (0..100, 0..6));
+
+    // Full strength within radius * hardness, fading out to the radius
+    let stroke = Region::Stroke { points: vec![[10.0, 10.0], [20.0, 10.0]], radius: 4.0, hardness: 0.5 };
+    let mask = stroke.mask(100, 100);
+    assert_eq!(mask.weight(12, 15), 1.0);
+    assert_eq!(mask.weight(13, 15), 0.5);
+    assert_eq!(mask.weight(14, 15), 0.0);
+    assert_eq!(mask.weight(10, 24), 0.0);
+    assert_eq!(mask.weight(10, 23), 0.5);
+
+    // Triangle with its right angle at the origin
+    let lasso = Region::Lasso { points: vec![[0.0, 0.0], [10.0, 0.0], [0.0, 10.0]] };
+    let mask = lasso.mask(100, 100);
+    assert_eq!(mask.weight(2, 2), 1.0);
+    assert_eq!(mask.weight(8, 8), 0.0);
+    assert_eq!(mask.weight(1, 20), 0.0);
+}
+
+#[test]
+fn operations_change_only_their_region() {
+    let rectangle = Region::Rectangle { from: [10.0, 10.0], to: [19.0, 19.0] };
+    let edit = |operation| Edit { operation, region: rectangle.clone(), channel: None };
+    let inside = |spectrogram: &Spectrogram| spectrogram.magnitude[15][15];
+    let outside = |spectrogram: &Spectrogram| spectrogram.magnitude[25][15];
+
+    let mut spectrograms = vec![flat_spectrogram(40, 40)];
+    let frames = edit(Operation::Gain { db: -20.0 }).apply(&mut spectrograms, 1.0);
+    assert_eq!(frames, 10..20);
+    assert!((inside(&spectrograms[0]) - 0.1).abs() < 1e-6);
+    assert_eq!(outside(&spectrograms[0]), 1.0);
+
+    // Blurring a flat area changes nothing
+    let mut spectrograms = vec![flat_spectrogram(40, 40)];
+    edit(Operation::Blur { radius: 3 }).apply(&mut spectrograms, 1.0);
+    assert!(spectrograms[0].magnitude.iter().flatten().all(|&m| (m - 1.0).abs() < 1e-6));
+
+    let mut spectrograms = vec![flat_spectrogram(40, 40)];
+    edit(Operation::Erase { strength: 1.0 }).apply(&mut spectrograms, 1.0);
+    assert_eq!((inside(&spectrograms[0]), outside(&spectrograms[0])), (0.0, 1.0));
+
+    // Paint moves toward a level relative to the reference magnitude
+    edit(Operation::Paint { level_db: -6.0, strength: 1.0 }).apply(&mut spectrograms, 2.0);
+    assert!((inside(&spectrograms[0]) - 2.0 * 10f32.powf(-6.0 / 20.0)).abs() < 1e-5);
+    assert_eq!(outside(&spectrograms[0]), 1.0);
+}
+
+#[test]
+fn clone_copies_from_the_offset() {
+    let mut spectrograms = vec![flat_spectrogram(40, 40)];
+    spectrograms[0].magnitude[30][5] = 7.0;
+    let source_phase = spectrograms[0].phase.as_ref().unwrap()[30][5];
+
+    let edit = Edit {
+        operation: Operation::Clone { offset: [-5, 20], include_phase: true },
+        region: Region::Rectangle { from: [10.0, 10.0], to: [10.0, 10.0] },
+        channel: None,
+    };
+    edit.apply(&mut spectrograms, 1.0);
+    assert_eq!(spectrograms[0].magnitude[10][10], 7.0);
+    assert_eq!(spectrograms[0].phase.as_ref().unwrap()[10][10], source_phase);
+    assert_eq!(spectrograms[0].magnitude[30][5], 7.0);
+}
+
+#[test]
+fn layers_undo_redo_and_toggle_without_touching_the_base() {
+    let base = vec![flat_spectrogram(40, 40)];
+    let region = Region::Rectangle { from: [0.0, 0.0], to: [9.0, 9.0] };
+    let gain = Edit { operation: Operation::Gain { db: 20.0 }, region: region.clone(), channel: None };
+    let erase = Edit { operation: Operation::Erase { strength: 1.0 }, region, channel: None };
+    let cell = |layers: &Layers| layers.render(&base, 1.0)[0].magnitude[5][5];
+
+    let mut layers = Layers::new();
+    assert_eq!(layers.change(Change::AddEdit { layer: 0, edit: gain.clone() }).unwrap(), vec![gain.clone()]);
+    assert!((cell(&layers) - 10.0).abs() < 1e-4);
+
+    // A layer on top erases, hiding it brings the gain back
+    layers.change(Change::AddLayer { index: 1, layer: Layer::new("Erase") }).unwrap();
+    layers.change(Change::AddEdit { layer: 1, edit: erase.clone() }).unwrap();
+    assert_eq!(cell(&layers), 0.0);
+    layers.change(Change::SetVisible { layer: 1, visible: false }).unwrap();
+    assert!((cell(&layers) - 10.0).abs() < 1e-4);
+    layers.change(Change::SetVisible { layer: 1, visible: true }).unwrap();
+
+    // Moving the eraser below the gain leaves the gain nothing to raise
+    layers.change(Change::MoveLayer { from: 1, to: 0 }).unwrap();
+    assert_eq!(layers.layers()[0].name, "Erase");
+    assert_eq!(cell(&layers), 0.0);
+
+    // Undo everything back to the base, then redo it all
+    let before_undo = layers.clone();
+    let mut undone = 0;
+    while layers.undo().is_some() {
+        undone += 1;
+    }
+    assert_eq!(undone, 6);
+    assert!(!layers.has_edits() && !layers.can_undo() && layers.can_redo());
+    assert_eq!(layers.render(&base, 1.0)[0].magnitude, base[0].magnitude);
+    while layers.redo().is_some() {}
+    assert_eq!(layers.layers(), before_undo.layers());
+
+    // A new change drops what could be redone
+    layers.undo();
+    layers.change(Change::RemoveLayer { index: 0 }).unwrap();
+    assert!(!layers.can_redo());
+    assert!(layers.change(Change::RemoveLayer { index: 5 }).is_err());
+}
+
+#[test]
+fn resynthesized_bands_match_the_decoder() {
+    let dir = std::env::temp_dir().join(format!("spectrogram_editing_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("input.wav");
+    write_wav(&input, &[tone(22_050, 440.0), tone(22_050, 3000.0)], SAMPLE_RATE, BitDepth::Float32, false).unwrap();
+
+    let config = SpectrogramConfig {
+        frequency_scale: FrequencyScale::Linear,
+        num_bins: 0,
+        min_freq: 0.0,
+        max_freq: 0.0,
+        image_format: ImageFormat::Exr,
+        use_phase_encoding: true,
+        channel_mode: ChannelMode::Multichannel,
+        stack_channels: true,
+        level_mode: LevelMode::Original,
+        ..SpectrogramConfig::default()
+    };
+    let image_path = audio_to_spectrogram(&input, &dir.join("stereo"), &config, &Progress::none()).unwrap();
+    let decoded = decode_spectrogram(&image_path, &config, &Progress::none()).unwrap();
+
+    // The same bands the viewer edits
+    let metadata = SpectrogramMetadata::load(&image_path, &config).unwrap();
+    let img = image::open(&image_path).unwrap().to_rgb32f();
+    let band_height = img.height() / 2;
+    let mut spectrograms: Vec<Spectrogram> = (0..2)
+        .map(|band| image_to_spectrogram(&image::imageops::crop_imm(&img, 0, band * band_height, img.width(), band_height).to_image(), &metadata).unwrap())
+        .collect();
+    let resynthesized = spectrograms_to_audio(&spectrograms, &metadata, &Progress::none()).unwrap();
+    assert!(resynthesized.channels == decoded.channels);
+
+    // Erasing all of the second channel silences it and leaves the first alone
+    let everything = Region::Rectangle { from: [0.0, 0.0], to: [1e6, 1e6] };
+    Edit { operation: Operation::Erase { strength: 1.0 }, region: everything, channel: Some(1) }
+        .apply(&mut spectrograms, metadata.peak_magnitude.unwrap());
+    let edited = spectrograms_to_audio(&spectrograms, &metadata, &Progress::none()).unwrap();
+    assert!(edited.channels[0] == decoded.channels[0]);
+    assert!(edited.channels[1].iter().all(|&s| s == 0.0));
+}
@@ -0,0 +1,118 @@
+use spectrogram_converter::phase_retrieval::{griffin_lim, initial_phase, pghi, reconstruct_phase, rtisi_la};
+use spectrogram_converter::stft::{istft, stft, Window};
+use spectrogram_converter::{CancelToken, Cancelled, PhaseInit, PhaseRetrieval, Progress, SpectrogramConfig};
+
+const SAMPLE_RATE: u32 = 22050;
+
+// Chirp plus a steady tone, with some noise: harmonic content that plain Griffin-Lim smears
+fn test_signal(len: usize) -> Vec<f32> {
+    let mut state = 0x9e37_79b9u32;
+    (0..len)
+        .map(|i| {
+            state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            let noise = (state >> 8) as f32 / (1u32 << 24) as f32 - 0.5;
+            let t = i as f32 / SAMPLE_RATE as f32;
+            let chirp = 2.0 * std::f32::consts::PI * (200.0 * t + 800.0 * t * t);
+            0.4 * chirp.sin() + 0.3 * (2.0 * std::f32::consts::PI * 660.0 * t).sin() + 0.01 * noise
+        })
+        .collect()
+}
+
+// Spectral convergence in dB: how far the magnitude of the rebuilt signal is from the target
+fn spectral_convergence_db(target: &[Vec<f32>], phase: &[Vec<f32>], window: &Window) -> f32 {
+    let (rebuilt, _) = stft(&istft(target, phase, window), window);
+    let (error, energy) = target.iter().zip(&rebuilt).flat_map(|(a, b)| a.iter().zip(b)).fold(
+        (0.0f64, 0.0f64),
+        |(error, energy), (&a, &b)| (error + ((a - b) as f64).powi(2), energy + (a as f64).powi(2)),
+    );
+    (10.0 * (error / energy).log10()) as f32
+}
+
+// Magnitudes to rebuild and the window they were analyzed with
+fn target() -> (Vec<Vec<f32>>, Window) {
+    let config = SpectrogramConfig { fft_size: 1024, hop_size: 256, ..SpectrogramConfig::default() };
+    let window = Window::new(&config);
+    let (magnitude, _) = stft(&test_signal(SAMPLE_RATE as usize), &window);
+    (magnitude, window)
+}
+
+#[test]
+fn pghi_beats_plain_griffin_lim_without_iterating() {
+    let (magnitude, window) = target();
+    let zero = initial_phase(&magnitude, &window, PhaseInit::Zero, &Progress::none());
+    let plain = spectral_convergence_db(&magnitude, &griffin_lim(&magnitude, &window, zero, 30, 0.0, &Progress::none()).unwrap(), &window);
+    let integrated = spectral_convergence_db(&magnitude, &pghi(&magnitude, &window), &window);
+    assert!(integrated < plain - 3.0, "PGHI {:.1} dB, Griffin-Lim {:.1} dB", integrated, plain);
+}
+
+#[test]
+fn momentum_speeds_up_griffin_lim() {
+    let (magnitude, window) = target();
+    let zero = initial_phase(&magnitude, &window, PhaseInit::Zero, &Progress::none());
+    let plain = spectral_convergence_db(&magnitude, &griffin_lim(&magnitude, &window, zero.clone(), 30, 0.0, &Progress::none()).unwrap(), &window);
+    let fast = spectral_convergence_db(&magnitude, &griffin_lim(&magnitude, &window, zero, 30, 0.99, &Progress::none()).unwrap(), &window);
+    assert!(fast < plain - 3.0, "Fast Griffin-Lim {:.1} dB, Griffin-Lim {:.1} dB", fast, plain);
+}
+
+#[test]
+fn default_phase_retrieval_improves_on_plain_griffin_lim() {
+    let (magnitude, window) = target();
+    let config = SpectrogramConfig::default();
+    assert_eq!(config.phase_retrieval, PhaseRetrieval::FastGriffinLim);
+
+    let plain_config = SpectrogramConfig {
+        phase_retrieval: PhaseRetrieval::GriffinLim,
+        phase_init: PhaseInit::Zero,
+        ..SpectrogramConfig::default()
+    };
+    let plain = spectral_convergence_db(&magnitude, &reconstruct_phase(&magnitude, &window, &plain_config, &Progress::none()).unwrap(), &window);
+    let default = spectral_convergence_db(&magnitude, &reconstruct_phase(&magnitude, &window, &config, &Progress::none()).unwrap(), &window);
+    assert!(default < plain - 10.0, "default {:.1} dB, Griffin-Lim {:.1} dB", default, plain);
+}
+
+#[test]
+fn rtisi_la_converges_frame_by_frame() {
+    let (magnitude, window) = target();
+    let convergence = spectral_convergence_db(&magnitude, &rtisi_la(&magnitude, &window, 8, 3, &Progress::none()).unwrap(), &window);
+    assert!(convergence < -15.0, "RTISI-LA {:.1} dB", convergence);
+}
+
+#[test]
+fn random_phases_repeat_between_runs() {
+    let (magnitude, window) = target();
+    // The default phase retrieval (seeded from PGHI), random starting phases and RTISI-LA
+    let default = SpectrogramConfig::default();
+    let settings = [
+        (default.phase_retrieval, default.phase_init),
+        (PhaseRetrieval::GriffinLim, PhaseInit::Random),
+        (PhaseRetrieval::RtisiLa, PhaseInit::Zero),
+    ];
+    for (phase_retrieval, phase_init) in settings {
+        let config = SpectrogramConfig { phase_retrieval, phase_init, griffin_lim_iterations: 5, ..SpectrogramConfig::default() };
+        let first = reconstruct_phase(&magnitude, &window, &config, &Progress::none()).unwrap();
+        assert!(reconstruct_phase(&magnitude, &window, &config, &Progress::none()).unwrap() == first, "{} from {} differs between runs", phase_retrieval, phase_init);
+    }
+}
+
+#[test]
+fn griffin_lim_reports_progress_and_stops_when_cancelled() {
+    let (magnitude, window) = target();
+    let token = CancelToken::new();
+    let reported = std::sync::Mutex::new(Vec::new());
+    let report = |_: &str, fraction: Option<f32>| {
+        if let Some(fraction) = fraction {
+            reported.lock().unwrap().push(fraction);
+            if fraction >= 0.5 {
+                token.cancel();
+            }
+        }
+    };
+    let progress = Progress::new(&report, token.clone());
+
+    let zero = initial_phase(&magnitude, &window, PhaseInit::Zero, &progress);
+    assert_eq!(griffin_lim(&magnitude, &window, zero, 30, 0.99, &progress), Err(Cancelled));
+
+    let reported = reported.into_inner().unwrap();
+    assert!(reported.windows(2).all(|pair| pair[0] < pair[1]), "{:?}", reported);
+    assert_eq!(reported.last(), Some(&0.5));
+}
@@ -0,0 +1,35 @@
+use spectrogram_converter::preset::{builtin_preset, Presets, BUILTIN_PRESETS};
+use spectrogram_converter::{FrequencyScale, SpectrogramConfig};
+
+#[test]
+fn presets_save_load_and_replace_built_ins() {
+    let dir = std::env::temp_dir().join(format!("spectrogram_presets_{}", std::process::id()));
+    let presets = Presets::new(dir.clone());
+
+    // Built-in presets work before anything is saved
+    assert_eq!(presets.names(), BUILTIN_PRESETS);
+    for name in BUILTIN_PRESETS {
+        assert!(builtin_preset(name).unwrap().validate().is_ok());
+    }
+    assert_eq!(presets.load("speech").unwrap().frequency_scale, FrequencyScale::Mel);
+    assert!(presets.load("missing").is_err());
+
+    // Saved presets round-trip and are listed after the built-in ones
+    let config = SpectrogramConfig { fft_size: 1024, hop_size: 64, ..SpectrogramConfig::default() };
+    let path = presets.save("my_voice-2", &config).unwrap();
+    assert_eq!(path, dir.join("my_voice-2.toml"));
+    let loaded = presets.load("my_voice-2").unwrap();
+    assert_eq!((loaded.fft_size, loaded.hop_size), (1024, 64));
+    assert_eq!(presets.names().last().map(String::as_str), Some("my_voice-2"));
+
+    // A saved preset takes the place of a built-in one of the same name
+    presets.save("speech", &config).unwrap();
+    assert_eq!(presets.load("speech").unwrap().frequency_scale, config.frequency_scale);
+    assert_eq!(presets.names().len(), BUILTIN_PRESETS.len() + 1);
+
+    // Names must make plain file names
+    assert!(presets.save("../escape", &config).is_err());
+    assert!(presets.save("", &config).is_err());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
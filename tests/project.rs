@@ -0,0 +1,71 @@
+use spectrogram_converter::project::is_project;
+use spectrogram_converter::{
+    read_audio, spectrograms_to_audio, write_wav, BitDepth, ChannelMode, Edit, Operation, Progress, Project, Region,
+    SpectrogramConfig,
+};
+
+const SAMPLE_RATE: u32 = 44100;
+
+fn tone(len: usize, freq: f32) -> Vec<f32> {
+    (0..len).map(|i| 0.5 * (2.0 * std::f32::consts::PI * freq * i as f32 / SAMPLE_RATE as f32).sin()).collect()
+}
+
+#[test]
+fn projects_save_load_and_render() {
+    let dir = std::env::temp_dir().join(format!("spectrogram_project_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("input.wav");
+    write_wav(&input, &[tone(22_050, 440.0), tone(22_050, 3000.0)], SAMPLE_RATE, BitDepth::Float32, false).unwrap();
+
+    let config = SpectrogramConfig {
+        use_phase_encoding: true,
+        channel_mode: ChannelMode::Multichannel,
+        bit_depth: BitDepth::Float32,
+        ..SpectrogramConfig::default()
+    };
+    let mut project = Project::from_audio(&input, &config, &Progress::none()).unwrap();
+    assert_eq!(project.spectrograms.len(), 2);
+    let everything = Region::Rectangle { from: [0.0, 0.0], to: [1e6, 1e6] };
+    project.layers[0].edits.push(Edit { operation: Operation::Erase { strength: 1.0 }, region: everything, channel: Some(1) });
+
+    let project_dir = dir.join("song.specproj");
+    project.save(&project_dir).unwrap();
+    assert!(is_project(&project_dir) && is_project(&project_dir.join("project.toml")));
+    assert!(!is_project(&dir));
+
+    // The source is stored relative to the project folder
+    let toml = std::fs::read_to_string(project_dir.join("project.toml")).unwrap();
+    assert!(toml.contains("source = \"../input.wav\""));
+
+    let loaded = Project::load(&project_dir).unwrap();
+    assert_eq!(loaded.source.as_ref().map(|source| source.canonicalize().unwrap()), Some(input.canonicalize().unwrap()));
+    assert_eq!(loaded.layers, project.layers);
+    assert_eq!(loaded.export, project.export);
+    for (loaded, saved) in loaded.spectrograms.iter().zip(&project.spectrograms) {
+        assert!(loaded.magnitude == saved.magnitude && loaded.phase == saved.phase);
+    }
+
+    // Rendering writes the image and the edited audio into the folder
+    let written = loaded.render(&project_dir, &Progress::none()).unwrap();
+    assert_eq!(written, vec![project_dir.join("export.png"), project_dir.join("export.wav")]);
+    let rendered = read_audio(&written[1]).unwrap();
+    let expected = spectrograms_to_audio(&loaded.edited_spectrograms(), &loaded.metadata, &Progress::none()).unwrap();
+    assert_eq!(rendered.channels.len(), 2);
+    assert!(rendered.channels[0].iter().zip(&expected.channels[0]).all(|(a, b)| (a - b).abs() < 1e-6));
+    assert!(rendered.channels[1].iter().all(|&s| s == 0.0));
+
+    // Projects without channels are rejected rather than loaded empty
+    let broken = dir.join("broken.specproj");
+    loaded.save(&broken).unwrap();
+    let toml = std::fs::read_to_string(broken.join("project.toml")).unwrap().replace("channels = 2", "channels = 0");
+    std::fs::write(broken.join("project.toml"), toml).unwrap();
+    assert!(Project::load(&broken).is_err());
+
+    // Nothing enabled is an error
+    let mut nothing = loaded;
+    nothing.export.image = false;
+    nothing.export.audio = false;
+    assert!(nothing.render(&project_dir, &Progress::none()).is_err());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,567 @@
+use spectrogram_converter::level::{sample_peak, true_peak};
+use spectrogram_converter::stft::{istft, stft, Window};
+use spectrogram_converter::{
+    analyze, audio_to_spectrogram, decode_spectrogram, image_to_spectrogram, read_audio, read_wav, spectrogram_to_audio,
+    spectrogram_to_image, synthesize, write_wav, BitDepth, ChannelMode, FrequencyScale, ImageFormat, LevelMode, Padding,
+    PeakProtection, PhaseInit, PhaseRetrieval, Progress, Spectrogram, SpectrogramConfig, SpectrogramMetadata,
+    TileManifest, WindowFunction,
+};
+
+const SAMPLE_RATE: u32 = 44100;
+
+// Two tones plus deterministic white noise
+fn test_signal(len: usize) -> Vec<f32> {
+    let mut state = 0x1234_5678u32;
+    (0..len)
+        .map(|i| {
+            state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            let noise = (state >> 8) as f32 / (1u32 << 24) as f32 - 0.5;
+            let t = i as f32 / SAMPLE_RATE as f32;
+            0.5 * (2.0 * std::f32::consts::PI * 440.0 * t).sin()
+                + 0.2 * (2.0 * std::f32::consts::PI * 5000.0 * t).sin()
+                + 0.05 * noise
+        })
+        .collect()
+}
+
+// Float image with every FFT bin as one row, so nothing but the STFT itself is lossy
+fn lossless_config(fft_size: usize, hop_size: usize) -> SpectrogramConfig {
+    SpectrogramConfig {
+        fft_size,
+        hop_size,
+        frequency_scale: FrequencyScale::Linear,
+        min_freq: 0.0,
+        max_freq: 0.0,
+        num_bins: 0,
+        image_format: ImageFormat::Exr,
+        use_phase_encoding: true,
+        ..SpectrogramConfig::default()
+    }
+}
+
+// SNR in dB, skipping the first and last `margin` samples where fewer frames overlap
+fn snr_db(reference: &[f32], output: &[f32], margin: usize) -> f32 {
+    let end = reference.len().min(output.len()) - margin;
+    let (signal, error) = (margin..end).fold((0.0f64, 0.0f64), |(signal, error), i| {
+        let diff = (reference[i] - output[i]) as f64;
+        (signal + (reference[i] as f64).powi(2), error + diff * diff)
+    });
+    (10.0 * (signal / error.max(1e-30)).log10()) as f32
+}
+
+// Normalized correlation: 1 for signals that are equal up to their gain
+fn correlation(a: &[f32], b: &[f32]) -> f32 {
+    let dot = |x: &[f32], y: &[f32]| x.iter().zip(y).map(|(&x, &y)| x as f64 * y as f64).sum::<f64>();
+    (dot(a, b) / (dot(a, a) * dot(b, b)).sqrt()) as f32
+}
+
+#[test]
+fn embedded_metadata_wins_over_the_filename() {
+    let dir = std::env::temp_dir().join(format!("spectrogram_metadata_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("input.wav");
+    write_wav(&input, &[test_signal(5000)], 22050, BitDepth::Float32, false).unwrap();
+
+    // Encoder settings, sample rate, length and peak travel in the image, even once it is renamed
+    let config = SpectrogramConfig {
+        hop_size: 128,
+        db_min: -90.0,
+        frequency_scale: FrequencyScale::Mel,
+        num_bins: 200,
+        ..SpectrogramConfig::default()
+    };
+    let image = audio_to_spectrogram(&input, &dir.join("encoded"), &config, &Progress::none()).unwrap();
+    let renamed = dir.join("renamed_SR8000_LIN_MAG.png");
+    std::fs::rename(&image, &renamed).unwrap();
+    let metadata = SpectrogramMetadata::load(&renamed, &SpectrogramConfig::default()).unwrap();
+    assert_eq!((metadata.sample_rate, metadata.num_samples), (22050, 5000));
+    assert!(metadata.peak_magnitude.is_some_and(|peak| peak > 0.0));
+    assert_eq!((metadata.config.hop_size, metadata.config.db_min), (128, -90.0));
+    assert_eq!((metadata.config.frequency_scale, metadata.config.num_bins), (FrequencyScale::Mel, 200));
+    assert!(metadata.config.use_phase_encoding);
+
+    // Legacy images without metadata fall back to their names, and to the defaults past that
+    let legacy = dir.join("legacy_SR22050_LOG_MAG.png");
+    image::RgbImage::new(16, 513).save(&legacy).unwrap();
+    let metadata = SpectrogramMetadata::load(&legacy, &SpectrogramConfig::default()).unwrap();
+    assert_eq!((metadata.sample_rate, metadata.config.frequency_scale), (22050, FrequencyScale::Log));
+    assert!(!metadata.config.use_phase_encoding && metadata.peak_magnitude.is_none());
+    let unnamed = dir.join("unnamed.png");
+    std::fs::rename(&legacy, &unnamed).unwrap();
+    let metadata = SpectrogramMetadata::load(&unnamed, &SpectrogramConfig::default()).unwrap();
+    assert_eq!((metadata.sample_rate, metadata.config.frequency_scale), (44100, FrequencyScale::Linear));
+    assert!(metadata.config.use_phase_encoding);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn images_too_low_for_a_spectrum_are_rejected() {
+    let dir = std::env::temp_dir().join(format!("spectrogram_low_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    // A legacy image one row high would mean an FFT size of 0
+    let path = dir.join("flat_SR44100_LIN_MAG.png");
+    image::RgbImage::new(16, 1).save(&path).unwrap();
+    assert!(SpectrogramMetadata::load(&path, &SpectrogramConfig::default()).is_err());
+    image::RgbImage::new(16, 2).save(&path).unwrap();
+    let metadata = SpectrogramMetadata::load(&path, &SpectrogramConfig::default()).unwrap();
+
+    // Decoding checks too: two rows give a 2-sample FFT, shorter than the hop size
+    let image = image::open(&path).unwrap().to_rgb32f();
+    assert!(image_to_spectrogram(&image, &metadata).is_err());
+    let flat = image::imageops::crop_imm(&image, 0, 0, 16, 1).to_image();
+    assert!(image_to_spectrogram(&flat, &metadata).is_err());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn mid_side_round_trip_restores_left_and_right() {
+    let left = test_signal(SAMPLE_RATE as usize / 4);
+    let right: Vec<f32> = (0..left.len()).map(|i| 0.3 * (0.17 * i as f32).sin()).collect();
+    let signals = ChannelMode::MidSide.split(vec![left.clone(), right.clone()]).unwrap();
+    assert!(signals[0].iter().zip(&signals[1]).zip(&left).all(|((m, s), l)| (m + s - l).abs() < 1e-6));
+    let joined = ChannelMode::MidSide.join(signals);
+    for (channel, original) in joined.iter().zip([&left, &right]) {
+        assert!(channel.iter().zip(original).all(|(a, b)| (a - b).abs() < 1e-6));
+    }
+    assert!(ChannelMode::MidSide.split(vec![left.clone()]).is_err());
+
+    // Through an image and back, each channel comes out as itself rather than a mix of both
+    let dir = std::env::temp_dir().join(format!("spectrogram_mid_side_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("input.wav");
+    write_wav(&input, &[left.clone(), right.clone()], SAMPLE_RATE, BitDepth::Float32, false).unwrap();
+    let config = SpectrogramConfig {
+        channel_mode: ChannelMode::MidSide,
+        frequency_scale: FrequencyScale::Linear,
+        min_freq: 0.0,
+        ..SpectrogramConfig::default()
+    };
+    let image = audio_to_spectrogram(&input, &dir.join("mid_side"), &config, &Progress::none()).unwrap();
+    assert_eq!(SpectrogramMetadata::load(&image, &config).unwrap().channels, 2);
+    let output_path = dir.join("output.wav");
+    spectrogram_to_audio(&image, &output_path, &config, &Progress::none()).unwrap();
+    let output = read_wav(&output_path).unwrap().channels;
+    assert_eq!(output.len(), 2);
+    for (channel, [original, other]) in output.iter().zip([[&left, &right], [&right, &left]]) {
+        let (own, cross) = (correlation(original, channel), correlation(other, channel));
+        assert!(own > 0.8 && cross.abs() < 0.1, "Mid/Side: correlation {} with the channel, {} with the other", own, cross);
+    }
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn decoded_level_is_restored_or_kept_under_the_ceiling() {
+    let dir = std::env::temp_dir().join(format!("spectrogram_level_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let config = SpectrogramConfig { frequency_scale: FrequencyScale::Linear, min_freq: 0.0, ..SpectrogramConfig::default() };
+    let encode = |name: &str, samples: Vec<f32>| {
+        let input = dir.join(format!("{}.wav", name));
+        write_wav(&input, &[samples], SAMPLE_RATE, BitDepth::Float32, false).unwrap();
+        audio_to_spectrogram(&input, &dir.join(name), &config, &Progress::none()).unwrap()
+    };
+    let loud = test_signal(SAMPLE_RATE as usize / 4);
+    let loud_image = encode("loud", loud.clone());
+    let quiet_image = encode("quiet", loud.iter().map(|s| 0.1 * s).collect());
+    let decode = |image: &std::path::Path, level_mode, peak_protection, peak_ceiling_db| {
+        let config = SpectrogramConfig { level_mode, peak_protection, peak_ceiling_db, ..config.clone() };
+        let output = dir.join("output.wav");
+        spectrogram_to_audio(image, &output, &config, &Progress::none()).unwrap();
+        read_wav(&output).unwrap().channels
+    };
+
+    // Both images look the same; the stored reference brings the quiet file back 20 dB below
+    // the loud one rather than at full scale
+    let restored = decode(&quiet_image, LevelMode::Original, PeakProtection::None, 0.0);
+    let ratio = sample_peak(&restored) / sample_peak(&decode(&loud_image, LevelMode::Original, PeakProtection::None, 0.0));
+    assert!((ratio - 0.1).abs() < 1e-3, "quiet file restored at {} times the loud one", ratio);
+    assert!((sample_peak(&decode(&quiet_image, LevelMode::Normalize, PeakProtection::None, 0.0)) - 0.95).abs() < 1e-3);
+
+    // Peak protection keeps the true peak under the ceiling, whichever way it gets there
+    let ceiling_db = -30.0;
+    let ceiling = 10.0f32.powf(ceiling_db / 20.0);
+    assert!(true_peak(&restored) > ceiling);
+    for peak_protection in [PeakProtection::Scale, PeakProtection::Limit] {
+        let protected = decode(&quiet_image, LevelMode::Original, peak_protection, ceiling_db);
+        assert_eq!(protected[0].len(), loud.len());
+        assert!(true_peak(&protected) <= ceiling * 1.001, "{}: true peak {} over {}", peak_protection, true_peak(&protected), ceiling);
+    }
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn float_images_keep_levels_below_db_min() {
+    let dir = std::env::temp_dir().join(format!("spectrogram_quiet_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("input.wav");
+    // A loud half, then the same signal 80 dB down: well below db_min
+    let half = SAMPLE_RATE as usize / 4;
+    let samples: Vec<f32> = test_signal(2 * half).iter().enumerate()
+        .map(|(i, &s)| if i < half { s } else { s * 1e-4 })
+        .collect();
+    write_wav(&input, std::slice::from_ref(&samples), SAMPLE_RATE, BitDepth::Float32, false).unwrap();
+
+    let config = SpectrogramConfig {
+        db_min: -40.0,
+        level_mode: LevelMode::Original,
+        peak_protection: PeakProtection::None,
+        bit_depth: BitDepth::Float32,
+        ..lossless_config(1024, 256)
+    };
+    let image = audio_to_spectrogram(&input, &dir.join("quiet"), &config, &Progress::none()).unwrap();
+    let output_path = dir.join("output.wav");
+    spectrogram_to_audio(&image, &output_path, &config, &Progress::none()).unwrap();
+    let output = read_wav(&output_path).unwrap().channels.remove(0);
+
+    let snr = snr_db(&samples[half + 1024..], &output[half + 1024..], 0);
+    assert!(snr > 60.0, "quiet half: SNR {:.1} dB", snr);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn black_pixels_decode_at_db_min() {
+    // Black is the bottom of the dB range, as it always was, not silence
+    let spectrogram = analyze(&test_signal(4096), SAMPLE_RATE, &lossless_config(512, 128));
+    let metadata = SpectrogramMetadata::from_spectrogram(&spectrogram);
+    let black = image::Rgb32FImage::new(spectrogram.num_frames() as u32, spectrogram.num_bins() as u32);
+    let decoded = image_to_spectrogram(&black, &metadata).unwrap();
+    let reference = metadata.peak_magnitude.unwrap_or(1.0);
+    for (bin, row) in decoded.magnitude.iter().enumerate() {
+        let expected = 10.0f32.powf((decoded.config.db_min - decoded.boost_db(bin)) / 20.0) * reference;
+        assert!(row.iter().all(|&m| (m - expected).abs() <= expected * 1e-4), "bin {}", bin);
+    }
+}
+
+#[test]
+fn sixteen_bit_images_keep_their_precision() {
+    let dir = std::env::temp_dir().join(format!("spectrogram_precision_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("input.wav");
+    let samples = test_signal(SAMPLE_RATE as usize / 4);
+    write_wav(&input, std::slice::from_ref(&samples), SAMPLE_RATE, BitDepth::Float32, false).unwrap();
+
+    for image_format in ImageFormat::ALL {
+        // No boost and a wide range, so nothing clips at the top or drops out at the bottom
+        let config = SpectrogramConfig {
+            image_format,
+            frequency_scale: FrequencyScale::Linear,
+            min_freq: 0.0,
+            boost_db_per_octave: 0.0,
+            db_min: -150.0,
+            db_max: 0.0,
+            level_mode: LevelMode::Original,
+            peak_protection: PeakProtection::None,
+            bit_depth: BitDepth::Float32,
+            ..SpectrogramConfig::default()
+        };
+        let path = audio_to_spectrogram(&input, &dir.join(format!("{}", image_format).replace(' ', "_")), &config, &Progress::none()).unwrap();
+
+        // Pixels are within half a step of the rendered values
+        let saved = image::open(&path).unwrap();
+        let levels = match image_format {
+            ImageFormat::Png => 255.0,
+            ImageFormat::Png16 | ImageFormat::Tiff16 => {
+                assert_eq!(saved.color(), image::ColorType::Rgb16, "{}", image_format);
+                65535.0
+            }
+            ImageFormat::Exr => f32::INFINITY,
+        };
+        let rendered = spectrogram_to_image(&analyze(&samples, SAMPLE_RATE, &config));
+        let clamp = |value: f32| if image_format == ImageFormat::Exr { value } else { value.clamp(0.0, 1.0) };
+        let error = saved.to_rgb32f().as_raw().iter().zip(rendered.as_raw()).map(|(a, &b)| (a - clamp(b)).abs()).fold(0.0f32, f32::max);
+        assert!(error <= 0.5 / levels + 1e-6, "{}: pixel error {}", image_format, error);
+
+        // Each extra bit of the pixels is worth about 6 dB of the decoded audio
+        let output = dir.join("output.wav");
+        spectrogram_to_audio(&path, &output, &config, &Progress::none()).unwrap();
+        let output = read_wav(&output).unwrap().channels.remove(0);
+        let snr = snr_db(&samples, &output, 4096);
+        let expected = match image_format {
+            ImageFormat::Png => 35.0,
+            ImageFormat::Png16 | ImageFormat::Tiff16 => 80.0,
+            ImageFormat::Exr => 100.0,
+        };
+        assert!(snr > expected, "{}: SNR {:.1} dB", image_format, snr);
+    }
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn frequency_scales_place_tones_on_their_rows() {
+    let samples = test_signal(8192);
+    for frequency_scale in FrequencyScale::ALL {
+        for freq in [40.0f32, 440.0, 5000.0, 16000.0] {
+            let back = frequency_scale.unwarp(frequency_scale.warp(freq));
+            assert!((back - freq).abs() < 1e-3 * freq, "{}: {} Hz comes back as {} Hz", frequency_scale, freq, back);
+        }
+
+        // Rows rise from min_freq to max_freq, and the 440 Hz tone is loudest on its own row
+        let config = SpectrogramConfig { frequency_scale, min_freq: 40.0, max_freq: 16000.0, ..SpectrogramConfig::default() };
+        let spectrogram = analyze(&samples, SAMPLE_RATE, &config);
+        let last = spectrogram.num_bins() - 1;
+        assert!((spectrogram.bin_frequency(0) - 40.0).abs() < 0.1, "{}", frequency_scale);
+        assert!((spectrogram.bin_frequency(last) - 16000.0).abs() < 1.0, "{}", frequency_scale);
+        assert!((0..last).all(|bin| spectrogram.bin_frequency(bin) < spectrogram.bin_frequency(bin + 1)), "{}", frequency_scale);
+        let frame = spectrogram.num_frames() / 2;
+        let column = |bin: usize| spectrogram.magnitude[bin][frame];
+        let loudest = (0..=last).max_by(|&a, &b| column(a).total_cmp(&column(b))).unwrap();
+        let freq = spectrogram.bin_frequency(loudest);
+        assert!((freq - 440.0).abs() < 15.0, "{}: loudest row at {} Hz", frequency_scale, freq);
+    }
+}
+
+#[test]
+fn resized_images_resample_to_the_stored_fft_size() {
+    let config = SpectrogramConfig {
+        fft_size: 1024,
+        hop_size: 256,
+        frequency_scale: FrequencyScale::Linear,
+        min_freq: 0.0,
+        num_bins: 300,
+        image_format: ImageFormat::Exr,
+        ..SpectrogramConfig::default()
+    };
+    let samples = test_signal(8192);
+    let spectrogram = analyze(&samples, SAMPLE_RATE, &config);
+    assert_eq!(spectrogram.num_bins(), 300);
+
+    // Images resized in an editor keep the FFT size from their metadata, and the tones their rows
+    let image = spectrogram_to_image(&spectrogram);
+    let resized = image::imageops::resize(&image, image.width(), 600, image::imageops::FilterType::Triangle);
+    let decoded = image_to_spectrogram(&resized, &SpectrogramMetadata::from_spectrogram(&spectrogram)).unwrap();
+    assert_eq!((decoded.config.num_bins, decoded.config.fft_size), (600, 1024));
+    let loudest = |spectrogram: &Spectrogram| {
+        let frame = spectrogram.num_frames() / 2;
+        let column = |bin: usize| spectrogram.magnitude[bin][frame];
+        spectrogram.bin_frequency((0..spectrogram.num_bins()).max_by(|&a, &b| column(a).total_cmp(&column(b))).unwrap())
+    };
+    assert!((loudest(&decoded) - loudest(&spectrogram)).abs() < spectrogram.bin_frequency(1));
+    assert_eq!(synthesize(&decoded).len(), samples.len());
+}
+
+#[test]
+fn stft_istft_reconstructs_perfectly() {
+    let samples = test_signal(SAMPLE_RATE as usize / 2);
+    for window_function in WindowFunction::ALL {
+        for (fft_size, hop_size) in [(512, 128), (1024, 256), (2048, 512), (4096, 1024), (1024, 100)] {
+            let config = SpectrogramConfig { window: window_function, ..lossless_config(fft_size, hop_size) };
+            let window = Window::new(&config);
+            let (magnitude, phase) = stft(&samples, &window);
+            let output = istft(&magnitude, &phase, &window);
+
+            let snr = snr_db(&samples, &output, fft_size);
+            assert!(snr > 60.0, "{} fft {} hop {}: SNR {:.1} dB", window_function, fft_size, hop_size, snr);
+        }
+    }
+}
+
+#[test]
+fn float_image_round_trip_is_transparent() {
+    let samples = test_signal(SAMPLE_RATE as usize / 2);
+    for (fft_size, hop_size) in [(512, 128), (1024, 256), (2048, 256), (4096, 1024)] {
+        let config = lossless_config(fft_size, hop_size);
+        let spectrogram = analyze(&samples, SAMPLE_RATE, &config);
+        let image = spectrogram_to_image(&spectrogram);
+        let metadata = SpectrogramMetadata::from_spectrogram(&spectrogram);
+        let output = synthesize(&image_to_spectrogram(&image, &metadata).unwrap());
+
+        // Padding keeps the ends, so every sample counts
+        assert_eq!(output.len(), samples.len());
+        let snr = snr_db(&samples, &output, 0);
+        assert!(snr > 60.0, "fft {} hop {}: SNR {:.1} dB", fft_size, hop_size, snr);
+    }
+}
+
+#[test]
+fn padding_keeps_exact_length() {
+    for padding in [Padding::Zero, Padding::Reflect, Padding::Edge] {
+        // Shorter than one frame, not a multiple of the hop size, and longer than the padding
+        for len in [1, 300, 4095, 10_007] {
+            let samples = test_signal(len);
+            let config = SpectrogramConfig { padding, ..lossless_config(1024, 256) };
+            let output = synthesize(&analyze(&samples, SAMPLE_RATE, &config));
+
+            assert_eq!(output.len(), len, "{} padding", padding);
+            let snr = snr_db(&samples, &output, 0);
+            assert!(snr > 60.0, "{} padding, {} samples: SNR {:.1} dB", padding, len, snr);
+        }
+    }
+}
+
+#[test]
+fn thread_count_does_not_change_results() {
+    // Long enough for several blocks of frames; magnitude only so Griffin-Lim runs too
+    let samples = test_signal(SAMPLE_RATE as usize);
+    let run = |num_threads, phase_retrieval, phase_init| {
+        let config = SpectrogramConfig {
+            num_threads,
+            frequency_scale: FrequencyScale::Mel,
+            num_bins: 256,
+            phase_retrieval,
+            phase_init,
+            griffin_lim_iterations: 5,
+            ..lossless_config(1024, 64)
+        };
+        let mut spectrogram = analyze(&samples, SAMPLE_RATE, &config);
+        let phase = spectrogram.phase.take();
+        (spectrogram.magnitude.clone(), phase, synthesize(&spectrogram))
+    };
+
+    // The default phase retrieval (seeded from PGHI), random starting phases and RTISI-LA
+    // repeat exactly too
+    let default = SpectrogramConfig::default();
+    let settings = [
+        (PhaseRetrieval::FastGriffinLim, PhaseInit::Zero),
+        (default.phase_retrieval, default.phase_init),
+        (PhaseRetrieval::GriffinLim, PhaseInit::Random),
+        (PhaseRetrieval::RtisiLa, PhaseInit::Zero),
+    ];
+    for (phase_retrieval, phase_init) in settings {
+        let single = run(1, phase_retrieval, phase_init);
+        assert!(run(1, phase_retrieval, phase_init) == single, "{} from {} differs between runs", phase_retrieval, phase_init);
+        assert!(run(3, phase_retrieval, phase_init) == single, "{} from {}: 3 threads differ from one", phase_retrieval, phase_init);
+    }
+    for num_threads in [2, 8] {
+        assert!(run(num_threads, settings[0].0, settings[0].1) == run(1, settings[0].0, settings[0].1), "{} threads differ from one", num_threads);
+    }
+}
+
+#[test]
+fn tiled_files_decode_like_one_image() {
+    let dir = std::env::temp_dir().join(format!("spectrogram_tiles_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("input.wav");
+    let left = test_signal(20_011);
+    let right: Vec<f32> = left.iter().rev().map(|s| -0.5 * s).collect();
+    write_wav(&input, &[left, right], SAMPLE_RATE, BitDepth::Float32, false).unwrap();
+
+    let config = |tile_width, use_phase_encoding| {
+        SpectrogramConfig {
+            tile_width,
+            padding: Padding::Reflect,
+            channel_mode: ChannelMode::Multichannel,
+            stack_channels: false,
+            bit_depth: BitDepth::Float32,
+            use_phase_encoding,
+            ..lossless_config(1024, 256)
+        }
+    };
+    let encode = |name: &str, config: &SpectrogramConfig| {
+        audio_to_spectrogram(&input, &dir.join(name), config, &Progress::none()).unwrap()
+    };
+    let decode = |image: &std::path::Path, config: &SpectrogramConfig| {
+        let output = dir.join("output.wav");
+        spectrogram_to_audio(image, &output, config, &Progress::none()).unwrap();
+        read_audio(&output).unwrap().channels
+    };
+
+    let whole_image = encode("whole", &config(0, true));
+    assert!(whole_image.ends_with("whole_SR44100_LIN_PHASE_CH1.exr"));
+    let whole = decode(&whole_image, &config(0, true));
+
+    // The manifest, its folder or any tile decode the whole sequence
+    let manifest = encode("tiled", &config(8, true));
+    assert!(manifest.ends_with("tiled_SR44100_LIN_PHASE.tiles.toml"));
+    let tile = dir.join("tiled_SR44100_LIN_PHASE_part010_CH2.exr");
+    for image in [manifest.as_path(), dir.as_path(), tile.as_path()] {
+        assert!(decode(image, &config(8, true)) == whole, "decoding {} differs from the single image", image.display());
+    }
+
+    // Tiles are written as they are decoded, at the level the whole signal would get
+    assert!(decode_spectrogram(&manifest, &config(8, true), &Progress::none()).unwrap().channels == whole);
+    for (level_mode, peak_protection) in [(LevelMode::Original, PeakProtection::Limit), (LevelMode::Normalize, PeakProtection::Scale)] {
+        let config = SpectrogramConfig { level_mode, peak_protection, peak_ceiling_db: -12.0, ..config(8, true) };
+        let whole = decode(&whole_image, &config);
+        assert!(decode(&manifest, &config) == whole, "{} with {} differs between tiles and one image", level_mode, peak_protection);
+    }
+    let names: Vec<String> = std::fs::read_dir(&dir).unwrap().map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned()).collect();
+    assert!(names.iter().all(|name| !name.ends_with(".decoding")), "temporary files left behind: {:?}", names);
+
+    // Phase retrieval runs tile by tile
+    let magnitude_manifest = encode("magnitude", &config(8, false));
+    let magnitude_only = decode(&magnitude_manifest, &config(8, false));
+    assert_eq!(magnitude_only.len(), 2);
+    assert!(magnitude_only.iter().all(|channel| channel.len() == 20_011));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+// Rewrite a PNG without its text chunks, as some image tools do
+fn strip_png_text(path: &std::path::Path) {
+    let bytes = std::fs::read(path).unwrap();
+    let mut stripped = bytes[..8].to_vec();
+    let mut offset = 8;
+    while offset < bytes.len() {
+        let length = u32::from_be_bytes(bytes[offset..offset + 4].try_into().unwrap()) as usize;
+        let chunk = &bytes[offset..offset + 12 + length];
+        if !matches!(&chunk[4..8], b"tEXt" | b"iTXt" | b"zTXt") {
+            stripped.extend_from_slice(chunk);
+        }
+        offset += 12 + length;
+    }
+    std::fs::write(path, stripped).unwrap();
+}
+
+#[test]
+fn stripped_tiles_decode_through_their_manifest() {
+    let dir = std::env::temp_dir().join(format!("spectrogram_stripped_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("input.wav");
+    let samples = test_signal(SAMPLE_RATE as usize);
+    write_wav(&input, std::slice::from_ref(&samples), SAMPLE_RATE, BitDepth::Float32, false).unwrap();
+
+    let config = SpectrogramConfig { tile_width: 100, ..SpectrogramConfig::default() };
+    let manifest_path = audio_to_spectrogram(&input, &dir.join("stripped"), &config, &Progress::none()).unwrap();
+    let mut manifest = TileManifest::load(&manifest_path).unwrap();
+    assert!(manifest.tiles.len() > 1);
+    for tile in &manifest.tiles {
+        strip_png_text(&dir.join(&tile.images[0]));
+    }
+
+    // The manifest still knows the length, sample rate and channels
+    let output_path = dir.join("output.wav");
+    spectrogram_to_audio(&manifest_path, &output_path, &SpectrogramConfig::default(), &Progress::none()).unwrap();
+    let output = read_audio(&output_path).unwrap();
+    assert_eq!(output.sample_rate, SAMPLE_RATE);
+    assert_eq!(output.channels.len(), 1);
+    assert_eq!(output.channels[0].len(), samples.len());
+    assert!(output.channels[0].iter().any(|&s| s != 0.0));
+
+    // Tiles that do not match their manifest, or a manifest without audio, are errors
+    manifest.tiles[1].frames += 1;
+    manifest.save(&manifest_path).unwrap();
+    assert!(spectrogram_to_audio(&manifest_path, &output_path, &SpectrogramConfig::default(), &Progress::none()).is_err());
+    manifest.tiles[1].frames -= 1;
+    manifest.num_samples = 0;
+    manifest.save(&manifest_path).unwrap();
+    assert!(spectrogram_to_audio(&manifest_path, &output_path, &SpectrogramConfig::default(), &Progress::none()).is_err());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn axis_mapping_matches_the_encoder() {
+    for frequency_scale in FrequencyScale::ALL {
+        let config = SpectrogramConfig { frequency_scale, min_freq: 40.0, num_bins: 300, ..lossless_config(1024, 256) };
+        let spectrogram = analyze(&test_signal(4096), SAMPLE_RATE, &config);
+        for bin in [1, 150, 298] {
+            let freq = spectrogram.bin_frequency(bin);
+            let row = spectrogram.frequency_bin(freq).unwrap();
+            assert!((row - bin as f32).abs() < 1e-2, "{}: bin {} maps back to {}", frequency_scale, bin, row);
+        }
+        assert_eq!(spectrogram.frequency_bin(20.0), None);
+    }
+
+    // Padded frames are centered on multiples of the hop size, unpadded ones start there
+    let padded = analyze(&test_signal(4096), SAMPLE_RATE, &lossless_config(1024, 256));
+    assert_eq!(padded.frame_time(2.0), 512.0 / SAMPLE_RATE as f32);
+    let config = SpectrogramConfig { padding: Padding::None, ..lossless_config(1024, 256) };
+    let unpadded = analyze(&test_signal(4096), SAMPLE_RATE, &config);
+    assert_eq!(unpadded.frame_time(2.0), 1024.0 / SAMPLE_RATE as f32);
+}